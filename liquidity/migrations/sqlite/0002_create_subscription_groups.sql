@@ -0,0 +1,6 @@
+CREATE TABLE subscription_groups (
+    stream TEXT NOT NULL,
+    group_name TEXT NOT NULL,
+    position BIGINT NOT NULL,
+    PRIMARY KEY (stream, group_name)
+);
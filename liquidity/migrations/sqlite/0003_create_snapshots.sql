@@ -0,0 +1,4 @@
+CREATE TABLE snapshots (
+    stream TEXT PRIMARY KEY,
+    payload TEXT NOT NULL
+);
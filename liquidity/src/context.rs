@@ -1,13 +1,126 @@
-use crate::db::DbConnection;
+use crate::db::{DbConnection, EventMetadata};
+use crate::Uuid;
 use std::fmt;
 
 #[derive(Debug, Clone)]
 pub struct User {
     pub id: String,
-    pub permissions: Vec<String>
+    pub permissions: Vec<String>,
+    pub roles: Vec<String>
+}
+
+/// Information about the request a context was created for
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestInfo {
+    /// The unique id of the request, always generated by the server so clients can't reuse someone else's
+    pub request_id: String,
+    /// The id the client gave the request, if it sent one. Kept apart from `request_id` since it can't be trusted
+    pub client_request_id: Option<String>,
+    /// The id shared by every request in the same operation. Same as the request id if the client didn't send one
+    pub correlation_id: String,
+    /// The id of the request that caused this one, if the client sent one
+    pub causation_id: Option<String>,
+    /// The IP address of the client, if it's known
+    pub client_ip: Option<String>
+}
+
+impl RequestInfo {
+    /// # Arguments
+    ///
+    /// * `request_id` - The unique id of the request
+    /// * `correlation_id` - The correlation id sent by the client, defaults to the request id
+    /// * `client_ip` - The IP address of the client, if it's known
+    pub fn new<S: Into<String>>(request_id: S, correlation_id: Option<String>, client_ip: Option<String>) -> Self {
+        let request_id = request_id.into();
+        RequestInfo {
+            correlation_id: correlation_id.unwrap_or_else(|| request_id.clone()),
+            client_request_id: None,
+            causation_id: None,
+            request_id,
+            client_ip
+        }
+    }
+
+    /// A request with a new id, taking the ids the client sent in its headers only if they're UUIDs. Anything else is
+    /// dropped, so clients can't fill the event metadata with arbitrary strings.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - The request id the client sent
+    /// * `correlation_id` - The correlation id the client sent
+    /// * `causation_id` - The id of the request that caused this one, according to the client
+    /// * `client_ip` - The IP address of the client, if it's known
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use liquidity::context::RequestInfo;
+    ///
+    /// let correlation_id = "0b8e4d6c-5d6f-4b5e-9a4b-2f6c1e0c3a7d";
+    /// let request = RequestInfo::from_client(Some("<script>"), Some(correlation_id), None, None);
+    ///
+    /// assert_eq!(request.client_request_id, None);
+    /// assert_eq!(request.correlation_id, correlation_id);
+    /// assert_ne!(request.request_id, correlation_id);
+    /// ```
+    pub fn from_client(request_id: Option<&str>, correlation_id: Option<&str>, causation_id: Option<&str>, client_ip: Option<String>) -> Self {
+        RequestInfo {
+            client_request_id: request_id.and_then(parse_id),
+            causation_id: causation_id.and_then(parse_id),
+            ..RequestInfo::new(Uuid::new_v4().to_string(), correlation_id.and_then(parse_id), client_ip)
+        }
+    }
+}
+
+/// The canonical form of an id a client sent, None if it isn't a UUID
+fn parse_id(id: &str) -> Option<String> {
+    Uuid::parse_str(id).ok().map(|id| id.to_string())
+}
+
+impl Default for RequestInfo {
+    /// A request with a new random id
+    fn default() -> Self {
+        RequestInfo::new(Uuid::new_v4().to_string(), None, None)
+    }
 }
 
 pub trait Context<DB: DbConnection> : fmt::Debug {
     fn db(&self) -> DB;
     fn user(&self) -> &Option<User>;
-}
\ No newline at end of file
+    fn request(&self) -> &RequestInfo;
+
+    /// The metadata to attach to events written on behalf of this context
+    fn metadata(&self) -> EventMetadata {
+        EventMetadata::for_request(self.user().as_ref().map(|user| user.id.clone()), self.request())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RequestInfo;
+
+    const CLIENT_ID: &str = "0b8e4d6c-5d6f-4b5e-9a4b-2f6c1e0c3a7d";
+
+    #[test]
+    fn keeps_client_ids_apart() {
+        let request = RequestInfo::from_client(Some(CLIENT_ID), None, Some(CLIENT_ID), Some("127.0.0.1".to_string()));
+
+        assert_ne!(request.request_id, CLIENT_ID);
+        assert_eq!(request.client_request_id, Some(CLIENT_ID.to_string()));
+        assert_eq!(request.correlation_id, request.request_id);
+        assert_eq!(request.causation_id, Some(CLIENT_ID.to_string()));
+    }
+
+    #[test]
+    fn drops_ids_that_arent_uuids() {
+        let long = "a".repeat(10_000);
+        let request = RequestInfo::from_client(Some(&long), Some("not-a-uuid"), Some(""), None);
+
+        assert_eq!(request.client_request_id, None);
+        assert_eq!(request.correlation_id, request.request_id);
+        assert_eq!(request.causation_id, None);
+        // Ids are stored in one form, however the client wrote them
+        let upper = RequestInfo::from_client(None, Some(&CLIENT_ID.to_uppercase()), None, None);
+        assert_eq!(upper.correlation_id, CLIENT_ID);
+    }
+}
@@ -0,0 +1,123 @@
+use serde_json::Value;
+use crate::db::{DatabaseError, EventMetadata, NewEvent};
+
+/// What to do with events an aggregate doesn't handle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnknownEvents {
+    /// Fail the read with `DatabaseError::UnknownEventType`
+    Fail,
+    /// Log a warning and leave the state as it is
+    Skip
+}
+
+/// The events an aggregate is folded from, usually an enum with a variant per event type
+pub trait DomainEvent: Sized {
+    /// Parse a stored event
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The stored event type
+    /// * `payload` - The stored payload
+    /// * `metadata` - The metadata stored with the event, if any
+    ///
+    /// # Returns
+    ///
+    /// The event, None if it isn't one of these events
+    fn from_stored(event_type: &str, payload: Value, metadata: Option<&EventMetadata>) -> Result<Option<Self>, DatabaseError>;
+
+    /// Prepare the event for writing
+    fn to_new_event(&self, metadata: &EventMetadata) -> Result<NewEvent, DatabaseError>;
+}
+
+/// An object folded from the events in a stream
+pub trait Aggregate: Sized {
+    type Event: DomainEvent;
+
+    /// What to do with events that aren't one of `Event`, like ones written by a newer version of the service
+    const UNKNOWN_EVENTS: UnknownEvents = UnknownEvents::Fail;
+
+    /// Apply an event to the aggregate
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The aggregate folded from the events before this one, None if it doesn't exist
+    /// * `event` - The event to apply
+    ///
+    /// # Returns
+    ///
+    /// The aggregate after the event, None if it doesn't exist after it
+    fn apply(state: Option<Self>, event: Self::Event) -> Option<Self>;
+
+    /// Record the number of the last event in the stream that was folded into the aggregate, skipped events
+    /// included. Aggregates that don't track their version can ignore it.
+    fn set_version(&mut self, _version: u64) {}
+}
+
+/// Fold a stored event into an aggregate, handling events of unknown types by the aggregate's policy
+///
+/// # Arguments
+///
+/// * `state` - The aggregate folded from the events before this one, None if it doesn't exist
+/// * `event_type` - The stored event type
+/// * `payload` - The stored payload
+/// * `metadata` - The metadata stored with the event, if any
+/// * `number` - The number of the event in its stream
+/// * `stream` - The stream the event is in, for the warning logged when it's skipped
+///
+/// # Example
+///
+/// ```
+/// use liquidity::db::{Aggregate, DatabaseError, DomainEvent, EventMetadata, fold_event, NewEvent, UnknownEvents};
+/// use serde_json::{json, Value};
+///
+/// struct Counter(u64);
+/// struct Incremented;
+///
+/// impl DomainEvent for Incremented {
+///     fn from_stored(event_type: &str, _: Value, _: Option<&EventMetadata>) -> Result<Option<Self>, DatabaseError> {
+///         Ok(if event_type == "incremented" { Some(Incremented) } else { None })
+///     }
+///
+///     fn to_new_event(&self, metadata: &EventMetadata) -> Result<NewEvent, DatabaseError> {
+///         NewEvent::new("incremented", &json!({}), metadata)
+///     }
+/// }
+///
+/// impl Aggregate for Counter {
+///     type Event = Incremented;
+///     const UNKNOWN_EVENTS: UnknownEvents = UnknownEvents::Skip;
+///
+///     fn apply(state: Option<Self>, _: Incremented) -> Option<Self> {
+///         Some(Counter(state.map_or(1, |counter| counter.0 + 1)))
+///     }
+/// }
+///
+/// let counter = fold_event::<Counter>(None, "incremented", json!({}), None, 0, "counter-1").unwrap();
+/// let counter = fold_event(counter, "reset", json!({}), None, 1, "counter-1").unwrap();
+///
+/// assert_eq!(counter.map(|counter| counter.0), Some(1));
+/// ```
+pub fn fold_event<T: Aggregate>(
+    state: Option<T>,
+    event_type: &str,
+    payload: Value,
+    metadata: Option<&EventMetadata>,
+    number: u64,
+    stream: &str
+) -> Result<Option<T>, DatabaseError> {
+    let state = match T::Event::from_stored(event_type, payload, metadata)? {
+        Some(event) => T::apply(state, event),
+        None => match T::UNKNOWN_EVENTS {
+            UnknownEvents::Fail => return Err(DatabaseError::UnknownEventType(event_type.to_string())),
+            UnknownEvents::Skip => {
+                warn!("Skipping unknown event type {} in {}", event_type, stream);
+                state
+            }
+        }
+    };
+
+    Ok(state.map(|mut state| {
+        state.set_version(number);
+        state
+    }))
+}
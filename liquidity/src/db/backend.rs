@@ -0,0 +1,160 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use crate::Connection;
+use crate::db::{
+    Aggregate, DatabaseError, DbConnection, EventMetadata, EventStream, ExpectedVersion, FileConnection, MemoryConnection, NewEvent,
+    PersistentSubscription, SqlConnection, StoredEvent, Versioned
+};
+
+/// A database picked at runtime, so the backend can be chosen by configuration
+#[derive(Clone)]
+pub enum Backend {
+    EventStore(Arc<Connection>),
+    File(FileConnection),
+    Sql(SqlConnection),
+    Memory(MemoryConnection)
+}
+
+impl From<Arc<Connection>> for Backend {
+    fn from(conn: Arc<Connection>) -> Self {
+        Backend::EventStore(conn)
+    }
+}
+
+impl From<FileConnection> for Backend {
+    fn from(conn: FileConnection) -> Self {
+        Backend::File(conn)
+    }
+}
+
+impl From<SqlConnection> for Backend {
+    fn from(conn: SqlConnection) -> Self {
+        Backend::Sql(conn)
+    }
+}
+
+impl From<MemoryConnection> for Backend {
+    fn from(conn: MemoryConnection) -> Self {
+        Backend::Memory(conn)
+    }
+}
+
+/// Call the same method on whichever connection the backend holds
+macro_rules! dispatch {
+    ($backend:expr, $method:ident($($arg:expr),*)) => {
+        match $backend {
+            Backend::EventStore(conn) => DbConnection::$method(conn, $($arg),*).await,
+            Backend::File(conn) => DbConnection::$method(conn, $($arg),*).await,
+            Backend::Sql(conn) => DbConnection::$method(conn, $($arg),*).await,
+            Backend::Memory(conn) => DbConnection::$method(conn, $($arg),*).await
+        }
+    };
+}
+
+#[async_trait]
+impl DbConnection for Backend {
+    async fn write_event<S, E, P>(&self, stream: S, event_type: E, payload: P, metadata: &EventMetadata, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, E: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+
+        dispatch!(self, write_event(stream, event_type, payload, metadata, expected_version))
+    }
+
+    async fn write_events<S>(&self, stream: S, events: Vec<NewEvent>, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        dispatch!(self, write_events(stream, events, expected_version))
+    }
+
+    async fn create<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug {
+
+        dispatch!(self, create(stream, payload, metadata))
+    }
+
+    async fn read<S, T>(&self, stream: S) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        dispatch!(self, read(stream))
+    }
+
+    async fn read_from<S, T>(&self, stream: S, start: Option<(T, u64)>) -> Result<Option<(T, u64)>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        dispatch!(self, read_from(stream, start))
+    }
+
+    async fn read_snapshot<S>(&self, stream: S) -> Result<Option<Value>, DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        dispatch!(self, read_snapshot(stream))
+    }
+
+    async fn write_snapshot<S>(&self, stream: S, snapshot: Value) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        dispatch!(self, write_snapshot(stream, snapshot))
+    }
+
+    async fn read_at_version<S, T>(&self, stream: S, version: u64) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        dispatch!(self, read_at_version(stream, version))
+    }
+
+    async fn read_as_of<S, T>(&self, stream: S, as_of: DateTime<Utc>) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        dispatch!(self, read_as_of(stream, as_of))
+    }
+
+    async fn read_last<S, P>(&self, stream: S) -> Result<Option<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        dispatch!(self, read_last(stream))
+    }
+
+    async fn read_all<S, P>(&self, stream: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        dispatch!(self, read_all(stream))
+    }
+
+    async fn read_category<S, P>(&self, category: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        dispatch!(self, read_category(category))
+    }
+
+    async fn subscribe<S, P>(&self, stream: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        dispatch!(self, subscribe(stream, from))
+    }
+
+    async fn subscribe_category<S, P>(&self, category: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        dispatch!(self, subscribe_category(category, from))
+    }
+
+    async fn subscribe_persistent<S, G, P>(&self, stream: S, group: G) -> Result<PersistentSubscription<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, G: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        dispatch!(self, subscribe_persistent(stream, group))
+    }
+
+    async fn update<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug {
+
+        dispatch!(self, update(stream, payload, metadata))
+    }
+
+    async fn delete<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+
+        dispatch!(self, delete(stream, payload, metadata))
+    }
+}
@@ -1,11 +1,20 @@
-use crate::{Connection, Merge};
-use eventstore::{EventData, ResolvedEvent};
-use crate::db::DatabaseError;
+use crate::Connection;
+use eventstore::{EventData, RecordedEvent, ResolvedEvent, LinkTos, NakAction, PersistActionError, PersistActionResult, StreamMetadata, StreamMetadataResult, SubscriptionWrite};
+use eventstore::ExpectedVersion as ESExpectedVersion;
+use crate::db::{
+    fold_event, Acknowledge, Aggregate, DatabaseError, EventMetadata, EventStream, PersistentSubscription, SubscriptionEvent, Versioned,
+    Snapshot, SnapshotPolicy, snapshot_stream, SNAPSHOT_EVENT_TYPE
+};
 use tracing_futures::Instrument;
-use serde::{Serialize, de::DeserializeOwned};
-use std::sync::Arc;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, TimeZone, Utc};
 use std::fmt::Debug;
-use futures::TryStreamExt;
+use std::str::FromStr;
+use futures::{future, StreamExt, TryStreamExt};
+use futures::lock::Mutex as AsyncMutex;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum EventType {
@@ -24,110 +33,585 @@ impl AsRef<str> for EventType {
     }
 }
 
-impl From<String> for EventType {
-    fn from(s: String) -> Self {
-        match s.as_str() {
-            "create" => EventType::Create,
-            "update" => EventType::Update,
-            "delete" => EventType::Delete,
-            _ => EventType::Create
+impl FromStr for EventType {
+    type Err = DatabaseError;
+
+    /// # Example
+    ///
+    /// ```
+    /// use liquidity::db::EventType;
+    ///
+    /// assert_eq!("update".parse::<EventType>().unwrap(), EventType::Update);
+    /// assert!("election-vote".parse::<EventType>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(EventType::Create),
+            "update" => Ok(EventType::Update),
+            "delete" => Ok(EventType::Delete),
+            _ => Err(DatabaseError::UnknownEventType(s.to_string()))
+        }
+    }
+}
+
+/// The version a stream is expected to be at when writing to it, to detect concurrent writes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpectedVersion {
+    /// Write regardless of the version of the stream
+    Any,
+    /// The stream must not exist yet
+    NoStream,
+    /// The last event in the stream must have this number, starting at 0
+    Exact(u64)
+}
+
+impl From<ExpectedVersion> for ESExpectedVersion {
+    fn from(v: ExpectedVersion) -> Self {
+        match v {
+            ExpectedVersion::Any => ESExpectedVersion::Any,
+            ExpectedVersion::NoStream => ESExpectedVersion::NoStream,
+            ExpectedVersion::Exact(n) => ESExpectedVersion::Exact(n as i64)
         }
     }
 }
 
+/// An event to append to a stream, used to write several events at once
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewEvent {
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub metadata: EventMetadata
+}
+
+impl NewEvent {
+    /// Serialize a payload into a new event
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use liquidity::db::{NewEvent, EventType, EventMetadata};
+    ///
+    /// let event = NewEvent::new(EventType::Update, &serde_json::json!({ "name": "test" }), &EventMetadata::system()).unwrap();
+    ///
+    /// assert_eq!(event.event_type, "update".to_string());
+    /// ```
+    pub fn new<E: AsRef<str>, P: Serialize>(event_type: E, payload: &P, metadata: &EventMetadata) -> Result<Self, DatabaseError> {
+        Ok(NewEvent {
+            event_type: event_type.as_ref().to_string(),
+            payload: serde_json::to_value(payload)?,
+            metadata: metadata.clone()
+        })
+    }
+}
+
+/// A single event as it was stored in a stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredEvent<P> {
+    pub stream_id: String,
+    pub event_type: String,
+    pub payload: P,
+    /// The metadata of the event. None for events written before metadata was stored
+    pub metadata: Option<EventMetadata>
+}
+
 #[async_trait]
-pub trait DbConnection : Clone {
-    async fn write_event<S, P>(&self, stream: S, event_type: EventType, payload: P) -> Result<(), DatabaseError>
-        where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug;
+pub trait DbConnection : Clone + Send + Sync {
+    /// Append an event to a stream. Fails with `DatabaseError::WrongExpectedVersion` if the stream
+    /// isn't at the expected version.
+    async fn write_event<S, E, P>(&self, stream: S, event_type: E, payload: P, metadata: &EventMetadata, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, E: AsRef<str> + Send + Debug, P: Serialize + Send + Debug;
 
-    async fn create<S, P>(&self, stream: S, payload: P) -> Result<(), DatabaseError>
-        where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug;
+    /// Append several events to a stream in a single transaction, so either all of them are written or none are.
+    /// Fails with `DatabaseError::WrongExpectedVersion` if the stream isn't at the expected version.
+    async fn write_events<S>(&self, stream: S, events: Vec<NewEvent>, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug;
 
-    async fn read<S, T, C, U>(&self, stream: S) -> Result<Option<T>, DatabaseError>
-        where S: AsRef<str> + Send + Debug, T: From<C> + Merge<U> + Send + Clone, C: DeserializeOwned, U: DeserializeOwned;
+    /// Create a new stream with a create event, tagged with the current schema version of the payload.
+    /// Fails if the stream already exists.
+    async fn create<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug;
 
-    async fn update<S, P>(&self, stream: S, payload: P) -> Result<(), DatabaseError>
-        where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug;
+    /// Fold the events of a stream into an aggregate. None if the stream doesn't exist or the aggregate doesn't
+    /// exist after its last event.
+    async fn read<S, T>(&self, stream: S) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone;
+
+    /// Fold a stream like `read`, but continue from a state that already includes the events up to and
+    /// including the event with the number in `start`. Returns the state with the number of the last event
+    /// folded into it.
+    async fn read_from<S, T>(&self, stream: S, start: Option<(T, u64)>) -> Result<Option<(T, u64)>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone;
+
+    /// Fold a stream like `read`, but start from its latest snapshot and store a new one when
+    /// `policy.interval` events were folded since. Failing to store a snapshot doesn't fail the read.
+    async fn read_with_snapshots<S, T>(&self, stream: S, policy: &SnapshotPolicy) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Serialize + DeserializeOwned + Send + Sync + Clone + Debug {
+
+        // Snapshots that don't deserialize anymore are treated like snapshots of an older schema
+        let snapshot = self.read_snapshot(stream.as_ref()).await?
+            .and_then(|snapshot| serde_json::from_value::<Snapshot<T>>(snapshot).ok())
+            .filter(|snapshot| snapshot.schema_version == policy.schema_version);
+        let snapshot_version = snapshot.as_ref().map(|snapshot| snapshot.version);
+
+        let folded = self.read_from::<_, T>(stream.as_ref(), snapshot.map(|snapshot| (snapshot.state, snapshot.version))).await?;
+
+        if let Some((state, version)) = &folded {
+            if policy.is_due(snapshot_version, *version) {
+                let snapshot = Snapshot { version: *version, schema_version: policy.schema_version, state: state.clone() };
+                let result = match serde_json::to_value(snapshot) {
+                    Ok(snapshot) => self.write_snapshot(stream.as_ref(), snapshot).await,
+                    Err(e) => Err(e.into())
+                };
+                if let Err(e) = result { warn!("Failed to store snapshot: {:?}", e) }
+            }
+        }
+
+        Ok(folded.map(|(state, _)| state))
+    }
+
+    /// Read the latest snapshot stored for a stream, None if it has none
+    async fn read_snapshot<S>(&self, stream: S) -> Result<Option<Value>, DatabaseError>
+        where S: AsRef<str> + Send + Debug;
+
+    /// Store a snapshot of a stream. Only the latest snapshot of a stream is ever read, so it replaces the older ones
+    /// instead of piling up next to them.
+    async fn write_snapshot<S>(&self, stream: S, snapshot: Value) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug;
+
+    /// Fold a stream like `read`, but only up to and including the event with this number, starting at 0
+    async fn read_at_version<S, T>(&self, stream: S, version: u64) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone;
+
+    /// Fold a stream like `read`, but only the events written at or before `as_of`
+    async fn read_as_of<S, T>(&self, stream: S, as_of: DateTime<Utc>) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone;
+
+    /// Read the last event in a stream, None if the stream is empty or doesn't exist
+    async fn read_last<S, P>(&self, stream: S) -> Result<Option<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send;
+
+    /// Read every event in a stream without folding them, for append-only streams like votes.
+    /// A stream that doesn't exist is treated as empty.
+    async fn read_all<S, P>(&self, stream: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send;
+
+    /// Read every event in every stream of a category, where the category is the part of the stream id
+    /// before the first dash. The payload type has to fit every event in the category, like `serde_json::Value`.
+    async fn read_category<S, P>(&self, category: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send;
+
+    /// Subscribe to a stream. The events after `from` that were already written are delivered first, then new
+    /// events as they're written. Subscribing to a stream that doesn't exist yet waits for it to be created.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream to subscribe to
+    /// * `from` - The position of the last event that was already processed, None to start from the beginning
+    async fn subscribe<S, P>(&self, stream: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static;
+
+    /// Subscribe to every stream in a category, like `subscribe`. Positions increase with every event of the category,
+    /// but are otherwise specific to the backend: EventStore numbers the events of the category, the other backends use
+    /// the position among every event. Treat them as opaque checkpoints that are only valid for the same database.
+    async fn subscribe_category<S, P>(&self, category: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static;
+
+    /// Subscribe to a stream as a member of a consumer group, creating the group if it doesn't exist yet.
+    /// The subscription continues after the events the group acknowledged, even across restarts.
+    async fn subscribe_persistent<S, G, P>(&self, stream: S, group: G) -> Result<PersistentSubscription<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, G: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static;
 
-    async fn delete<S, P>(&self, stream: S, payload: P) -> Result<(), DatabaseError>
+    /// Append an update event, tagged with the current schema version of the payload
+    async fn update<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug;
+
+    async fn delete<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
         where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug;
 }
 
+/// Make a snapshot stream only keep its latest snapshot, unless it already does
+async fn limit_snapshots(conn: &Connection, snapshot_stream: &str) -> Result<(), DatabaseError> {
+    if let StreamMetadataResult::Success(current) = conn.read_stream_metadata(snapshot_stream).execute().await? {
+        if current.metadata.max_count == Some(1) { return Ok(()) }
+    }
+
+    let metadata = StreamMetadata::builder().max_count(1).build();
+    conn.write_stream_metadata(snapshot_stream, metadata).execute().await?;
+    Ok(())
+}
+
+/// Events written before metadata was stored have none, so they're read back as None
+fn read_metadata(event: &RecordedEvent) -> Option<EventMetadata> {
+    serde_json::from_slice(&event.metadata).ok()
+}
+
+/// The time an event was written at. Falls back to the time the database recorded for events
+/// without metadata.
+fn written_at(event: &RecordedEvent) -> Option<DateTime<Utc>> {
+    read_metadata(event)
+        .map(|metadata| metadata.timestamp)
+        .or_else(|| event.created_epoch.and_then(|millis| Utc.timestamp_millis_opt(millis).single()))
+}
+
+/// Turn an event received by a subscription into a stored event. Links are resolved, but positioned by the link.
+///
+/// # Returns
+///
+/// None if the event was a link to an event that doesn't exist anymore
+fn subscription_event<P: DeserializeOwned>(item: &ResolvedEvent) -> Result<Option<SubscriptionEvent<P>>, DatabaseError> {
+    let position = match item.get_original_event() {
+        Some(original) => original.event_number as u64,
+        None => return Ok(None)
+    };
+
+    match &item.event {
+        Some(event) => Ok(Some(SubscriptionEvent {
+            position,
+            event: StoredEvent {
+                stream_id: event.event_stream_id.to_owned(),
+                event_type: event.event_type.to_owned(),
+                payload: event.as_json::<P>()?,
+                metadata: read_metadata(event)
+            }
+        })),
+        None => Ok(None)
+    }
+}
+
+/// Acknowledges events of an EventStore persistent subscription. EventStore acknowledges events rather than
+/// positions, so the received events are kept until they're acknowledged. Events that are never delivered, because
+/// they don't deserialize or were deleted, are settled right away instead.
+struct PersistentAcks {
+    write: Arc<AsyncMutex<SubscriptionWrite>>,
+    received: Arc<Mutex<HashMap<u64, ResolvedEvent>>>
+}
+
+#[async_trait]
+impl Acknowledge for PersistentAcks {
+    async fn ack(&mut self, position: u64) -> Result<(), DatabaseError> {
+        let event = self.received.lock().unwrap().remove(&position);
+        if let Some(event) = event {
+            self.write.lock().await.ack_event(event).await;
+        }
+        Ok(())
+    }
+}
+
+/// Fold the events of a stream into a single object, skipping the events `include` rejects
+///
+/// # Arguments
+///
+/// * `start` - The state to continue from and the number of the last event folded into it
+///
+/// # Returns
+///
+/// The folded object with the number of the last event folded into it
+async fn fold_stream<S, T, F>(conn: &Connection, stream: S, start: Option<(T, u64)>, include: F) -> Result<Option<(T, u64)>, DatabaseError>
+    where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone,
+          F: Fn(&RecordedEvent) -> bool + Send {
+
+    let first_event = start.as_ref().map_or(0, |(_, version)| *version as i64 + 1);
+    let stream_id = stream.as_ref().to_string();
+    let stream = conn.read_stream(stream)
+        .forward()
+        .start_from(first_event)
+        .iterate_over()
+        .map_err(DatabaseError::from);
+
+    let include = &include;
+    let stream_id = &stream_id;
+    let res = stream.try_fold(start, move |acc: Option<(T, u64)>, item: ResolvedEvent| {
+        async move {
+            let event = match item.event.filter(|event| include(event)) {
+                Some(event) => event,
+                None => return Ok(acc)
+            };
+            let version = event.event_number as u64;
+            let metadata = read_metadata(&event);
+            let state = fold_event(acc.map(|(state, _)| state), &event.event_type, event.as_json()?, metadata.as_ref(), version, stream_id)?;
+            // Skipped events count towards the version too, so a fold resumed from here doesn't read them again
+            Ok(state.map(|state| (state, version)))
+        }
+    }).await;
+
+    match res.as_ref() {
+        Err(DatabaseError::NotFound) => Ok(None),
+        _ => res
+    }
+}
+
 #[async_trait]
 impl DbConnection for Arc<Connection> {
-    async fn write_event<S, P>(&self, stream: S, event_type: EventType, payload: P) -> Result<(), DatabaseError>
-        where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+    async fn write_event<S, E, P>(&self, stream: S, event_type: E, payload: P, metadata: &EventMetadata, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, E: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+
+        let event = NewEvent::new(event_type, &payload, metadata)?;
+        DbConnection::write_events(self, stream, vec![event], expected_version).await
+    }
 
-        let event_data = EventData::json(event_type, payload)?;
-        self
-            .write_events(stream)
-            .push_event(event_data)
+    async fn write_events<S>(&self, stream: S, events: Vec<NewEvent>, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        let event_data = events.into_iter()
+            .map(|NewEvent { event_type, payload, metadata }| {
+                EventData::json(event_type, payload).map(|data| data.metadata_as_json(metadata))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Connection::write_events(self, stream)
+            .append_events(event_data)
+            .expected_version(expected_version.into())
             .execute()
-            .instrument(trace_span!("store_event"))
+            .instrument(trace_span!("store_events"))
             .await?;
 
         Ok(())
     }
 
     #[instrument(skip(self))]
-    async fn create<S, P>(&self, stream: S, payload: P) -> Result<(), DatabaseError>
-        where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+    async fn create<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug {
+
+        self.write_event(stream, EventType::Create, payload, &metadata.versioned::<P>(), ExpectedVersion::NoStream).await
+    }
 
-        self.write_event(stream, EventType::Create, payload).await
+    #[instrument(skip(self))]
+    async fn read<S, T>(&self, stream: S) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        Ok(fold_stream(self, stream, None, |_| true).await?.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self, start))]
+    async fn read_from<S, T>(&self, stream: S, start: Option<(T, u64)>) -> Result<Option<(T, u64)>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        fold_stream(self, stream, start, |_| true).await
     }
 
     #[instrument(skip(self))]
-    async fn read<S, T, C, U>(&self, stream: S) -> Result<Option<T>, DatabaseError>
-        where S: AsRef<str> + Send + Debug, T: From<C> + Merge<U> + Send + Clone, C: DeserializeOwned, U: DeserializeOwned {
+    async fn read_snapshot<S>(&self, stream: S) -> Result<Option<Value>, DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        Ok(self.read_last::<_, Value>(snapshot_stream(stream.as_ref())).await?.map(|event| event.payload))
+    }
+
+    /// Snapshots are events in a stream of their own, which only keeps its last event
+    #[instrument(skip(self, snapshot))]
+    async fn write_snapshot<S>(&self, stream: S, snapshot: Value) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        let snapshot_stream = snapshot_stream(stream.as_ref());
+        let event = EventData::json(SNAPSHOT_EVENT_TYPE, snapshot)?.metadata_as_json(EventMetadata::system());
+        Connection::write_events(self, &snapshot_stream)
+            .push_event(event)
+            .execute()
+            .await?;
+
+        // Checked on every write, so a limit that failed to be set is retried. The snapshot is stored either way, so
+        // failing to only leaves older snapshots around for longer.
+        if let Err(e) = limit_snapshots(self, &snapshot_stream).await {
+            warn!("Failed to limit the snapshots of {}: {:?}", stream.as_ref(), e)
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn read_at_version<S, T>(&self, stream: S, version: u64) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        let folded = fold_stream(self, stream, None, move |event| event.event_number as u64 <= version).await?;
+        Ok(folded.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self))]
+    async fn read_as_of<S, T>(&self, stream: S, as_of: DateTime<Utc>) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        let folded = fold_stream(self, stream, None, move |event| match written_at(event) {
+            Some(written_at) => written_at <= as_of,
+            None => true
+        }).await?;
+        Ok(folded.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self))]
+    async fn read_last<S, P>(&self, stream: S) -> Result<Option<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        // Reading backwards from -1 starts at the end of the stream
+        let mut stream = self.read_stream(stream)
+            .backward()
+            .start_from(-1)
+            .max_count(1)
+            .iterate_over()
+            .map_err(DatabaseError::from);
+
+        let event = match stream.try_next().await {
+            Err(DatabaseError::NotFound) => None,
+            res => res?.and_then(|item| item.event)
+        };
+
+        match event {
+            Some(event) => Ok(Some(StoredEvent {
+                stream_id: event.event_stream_id.to_owned(),
+                event_type: event.event_type.to_owned(),
+                payload: event.as_json::<P>()?,
+                metadata: read_metadata(&event)
+            })),
+            None => Ok(None)
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn read_all<S, P>(&self, stream: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
 
         let stream = self.read_stream(stream)
             .forward()
             .iterate_over()
             .map_err(DatabaseError::from);
 
-        let res = stream.try_fold(None, move |acc: Option<T>, item: ResolvedEvent| {
-            async {
-                let event = item.event;
-                event.map(|event| {
-                    match event.event_type.to_owned().into() {
-                        EventType::Create => {
-                            let payload = event.as_json::<C>()?;
-                            Ok(Some(payload.into()))
-                        },
-                        EventType::Update => {
-                            match acc.clone() {
-                                Some(acc) => {
-                                    let payload = event.as_json::<U>()?;
-                                    Ok(Some(acc.merge_with(payload)))
-                                },
-                                None => Ok(None)
-                            }
-                        },
-                        EventType::Delete => {
-                            Err(DatabaseError::NotFound)
-                        }
-                    }
-                }).unwrap_or(Ok(acc))
+        let res = stream.try_fold(Vec::new(), |mut acc: Vec<StoredEvent<P>>, item: ResolvedEvent| {
+            async move {
+                if let Some(event) = item.event {
+                    let payload = event.as_json::<P>()?;
+                    acc.push(StoredEvent {
+                        stream_id: event.event_stream_id.to_owned(),
+                        event_type: event.event_type.to_owned(),
+                        payload,
+                        metadata: read_metadata(&event)
+                    });
+                }
+                Ok(acc)
             }
         }).await;
 
-        match res.as_ref() {
-            Err(DatabaseError::NotFound) => Ok(None),
+        match res {
+            Err(DatabaseError::NotFound) => Ok(Vec::new()),
             _ => res
         }
     }
 
     #[instrument(skip(self))]
-    async fn update<S, P>(&self, stream: S, payload: P) -> Result<(), DatabaseError>
-        where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+    async fn read_category<S, P>(&self, category: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        // The category projection links to the original events, so they need to be resolved
+        let stream = self.read_stream(format!("$ce-{}", category.as_ref()))
+            .forward()
+            .resolve_link_tos(LinkTos::ResolveLink)
+            .iterate_over()
+            .map_err(DatabaseError::from);
+
+        let res = stream.try_fold(Vec::new(), |mut acc: Vec<StoredEvent<P>>, item: ResolvedEvent| {
+            async move {
+                if let Some(event) = item.event {
+                    let payload = event.as_json::<P>()?;
+                    acc.push(StoredEvent {
+                        stream_id: event.event_stream_id.to_owned(),
+                        event_type: event.event_type.to_owned(),
+                        payload,
+                        metadata: read_metadata(&event)
+                    });
+                }
+                Ok(acc)
+            }
+        }).await;
+
+        match res {
+            Err(DatabaseError::NotFound) => Ok(Vec::new()),
+            _ => res
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn subscribe<S, P>(&self, stream: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        let subscription = self.subscribe_to_stream_from(stream.as_ref());
+        let subscription = match from {
+            Some(position) => subscription.start_position(position as i64),
+            None => subscription
+        };
+
+        let events = subscription
+            .resolve_link_tos(LinkTos::ResolveLink)
+            .execute()
+            .map_err(DatabaseError::from)
+            .try_filter_map(|item| future::ready(subscription_event(&item)))
+            .boxed();
+        Ok(events)
+    }
+
+    #[instrument(skip(self))]
+    async fn subscribe_category<S, P>(&self, category: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        DbConnection::subscribe(self, format!("$ce-{}", category.as_ref()), from).await
+    }
+
+    #[instrument(skip(self))]
+    async fn subscribe_persistent<S, G, P>(&self, stream: S, group: G) -> Result<PersistentSubscription<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, G: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        let created = self.create_persistent_subscription(stream.as_ref(), group.as_ref())
+            .resolve_link_tos(LinkTos::ResolveLink)
+            .start_from(0)
+            .execute()
+            .await?;
+        match created {
+            PersistActionResult::Success | PersistActionResult::Failure(PersistActionError::AlreadyExists) => {},
+            PersistActionResult::Failure(e) => {
+                return Err(DatabaseError::SubscriptionFailed(format!("{:?} creating group {} on {}", e, group.as_ref(), stream.as_ref())))
+            }
+        }
+
+        let (read, write) = self.connect_persistent_subscription(stream.as_ref(), group.as_ref()).execute();
+        let write = Arc::new(AsyncMutex::new(write));
+        let received = Arc::new(Mutex::new(HashMap::new()));
+
+        let events = futures::stream::unfold(read, |mut read| async move {
+            read.read_next().await.map(|item| (item, read))
+        });
+        let events = {
+            let write = write.clone();
+            let received = received.clone();
+            events
+                .then(move |item| {
+                    let write = write.clone();
+                    let received = received.clone();
+                    async move {
+                        let event = subscription_event(&item);
+                        match &event {
+                            // Nobody could ever acknowledge it, so it's parked instead of being delivered to the group again and again
+                            Err(e) => write.lock().await.nak_event(item, NakAction::Park, &e.to_string()).await,
+                            Ok(Some(event)) => {
+                                received.lock().unwrap().insert(event.position, item);
+                            },
+                            // A link to an event that doesn't exist anymore leaves nothing to process
+                            Ok(None) => write.lock().await.ack_event(item).await
+                        }
+                        event.transpose()
+                    }
+                })
+                .filter_map(future::ready)
+                .boxed()
+        };
+
+        Ok(PersistentSubscription::new(events, Box::new(PersistentAcks { write, received })))
+    }
+
+    #[instrument(skip(self))]
+    async fn update<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug {
 
-        self.write_event(stream, EventType::Update, payload).await
+        self.write_event(stream, EventType::Update, payload, &metadata.versioned::<P>(), ExpectedVersion::Any).await
     }
 
     #[instrument(skip(self))]
-    async fn delete<S, P>(&self, stream: S, payload: P) -> Result<(), DatabaseError>
+    async fn delete<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
         where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
 
-        self.write_event(stream, EventType::Delete, payload).await
+        self.write_event(stream, EventType::Delete, payload, metadata, ExpectedVersion::Any).await
     }
 }
\ No newline at end of file
@@ -0,0 +1,422 @@
+//! The append-only log the file backend stores events in
+//!
+//! Events are appended to numbered segment files. Every write is stored as a single record, so a write with several
+//! events is either stored completely or not at all. Records are framed by their length and a checksum of their body,
+//! which is how a record torn by a crash is found and cut off when the log is opened. Full segments are sealed with
+//! an index of their records, so opening the log only has to scan the segment that was being written to. A lock file
+//! keeps a second process from opening the same log.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use crate::db::{DatabaseError, NewEvent};
+use crate::db::local::{sync_directory, write_atomically, LocalEvent};
+
+/// The length of the frame in front of every record, the length of the body followed by its checksum
+const HEADER_LENGTH: u64 = 8;
+
+const LOCK_FILE: &str = "LOCK";
+
+/// When appended events are flushed to disk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncPolicy {
+    /// After every write, so a write that succeeded survives a crash of the machine
+    Always,
+    /// After this many writes. A crash of the machine can lose the writes since the last flush, but never corrupts the log
+    Every(u32),
+    /// Leave flushing to the operating system
+    Never
+}
+
+/// How the file backend stores its log
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileOptions {
+    /// The size in bytes after which a segment is sealed and writes continue in a new one
+    pub segment_size: u64,
+    pub sync: SyncPolicy
+}
+
+impl FileOptions {
+    pub fn new(segment_size: u64, sync: SyncPolicy) -> Self {
+        FileOptions { segment_size, sync }
+    }
+}
+
+impl Default for FileOptions {
+    /// 64 MiB segments, flushed after every write
+    fn default() -> Self {
+        FileOptions::new(64 * 1024 * 1024, SyncPolicy::Always)
+    }
+}
+
+/// A single write
+#[derive(Serialize, Deserialize)]
+struct Record {
+    stream_id: String,
+    /// The position of the first event of the record in the whole log
+    position: u64,
+    events: Vec<NewEvent>
+}
+
+/// A record in a segment, as listed in the index of a sealed segment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordLocation {
+    stream_id: String,
+    offset: u64,
+    length: u64,
+    count: usize
+}
+
+/// Where an event is stored
+#[derive(Debug, Clone)]
+struct Entry {
+    stream_id: Arc<str>,
+    number: u64,
+    segment: u64,
+    offset: u64,
+    length: u64,
+    /// The index of the event in its record
+    index: usize
+}
+
+pub struct Log {
+    directory: PathBuf,
+    options: FileOptions,
+    /// Holds the lock on the directory for as long as the log is open
+    _lock: File,
+    active: File,
+    active_segment: u64,
+    active_length: u64,
+    /// The records of the active segment, for its index once it's sealed
+    active_records: Vec<RecordLocation>,
+    /// Every event, by position
+    entries: Vec<Entry>,
+    /// The positions of the events of every stream, by number
+    streams: HashMap<String, Vec<u64>>,
+    /// The number of writes since the log was last flushed
+    unsynced: u32,
+    /// Set when a failed write couldn't be undone, after which the log has to be opened again
+    failed: bool
+}
+
+/// FNV-1a, to detect records that weren't written completely
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x0100_0193))
+}
+
+fn segment_path(directory: &Path, segment: u64) -> PathBuf {
+    directory.join(format!("{:020}.log", segment))
+}
+
+fn index_path(directory: &Path, segment: u64) -> PathBuf {
+    directory.join(format!("{:020}.idx", segment))
+}
+
+fn corrupted(segment: u64, offset: u64) -> DatabaseError {
+    let message = format!("segment {} is corrupted at offset {}", segment, offset);
+    DatabaseError::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// Lock a directory for this process, failing if another process already did
+fn lock(directory: &Path) -> Result<File, DatabaseError> {
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(directory.join(LOCK_FILE))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(std::fs::TryLockError::WouldBlock) => {
+            let message = format!("{} is already used by another process", directory.display());
+            Err(DatabaseError::IoError(io::Error::new(io::ErrorKind::WouldBlock, message)))
+        },
+        Err(std::fs::TryLockError::Error(e)) => Err(e.into())
+    }
+}
+
+/// Read the valid records of a segment
+///
+/// # Arguments
+///
+/// * `first_position` - The position the first record of the segment has to start at
+///
+/// # Returns
+///
+/// The records and the length of the segment up to the end of the last valid one
+fn scan(bytes: &[u8], first_position: u64) -> (Vec<RecordLocation>, u64) {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    let mut position = first_position;
+
+    while bytes.len() as u64 - offset >= HEADER_LENGTH {
+        let start = offset as usize;
+        let mut length = [0; 4];
+        let mut sum = [0; 4];
+        length.copy_from_slice(&bytes[start..start + 4]);
+        sum.copy_from_slice(&bytes[start + 4..start + 8]);
+        let length = u32::from_le_bytes(length) as u64;
+
+        let body_start = start + HEADER_LENGTH as usize;
+        if (bytes.len() - body_start) < length as usize { break }
+        let body = &bytes[body_start..body_start + length as usize];
+        if checksum(body) != u32::from_le_bytes(sum) { break }
+
+        let record = match serde_json::from_slice::<Record>(body) {
+            Ok(record) if record.position == position => record,
+            _ => break
+        };
+
+        position += record.events.len() as u64;
+        records.push(RecordLocation { stream_id: record.stream_id, offset, length, count: record.events.len() });
+        offset += HEADER_LENGTH + length;
+    }
+
+    (records, offset)
+}
+
+impl Log {
+    /// Open the log in a directory, creating it if it doesn't exist. A record at the end of the log that wasn't written
+    /// completely is cut off. Fails if the log is already open, in this process or another one.
+    pub fn open(directory: &Path, options: FileOptions) -> Result<Self, DatabaseError> {
+        fs::create_dir_all(directory)?;
+        let lock = lock(directory)?;
+
+        let mut segments = fs::read_dir(directory)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != "log" { return None }
+                path.file_stem()?.to_str()?.parse::<u64>().ok()
+            })
+            .collect::<Vec<_>>();
+        segments.sort();
+        let active_segment = segments.last().copied().unwrap_or(0);
+
+        let mut log = Log {
+            directory: directory.to_path_buf(),
+            options,
+            _lock: lock,
+            active: OpenOptions::new().create(true).append(true).open(segment_path(directory, active_segment))?,
+            active_segment,
+            active_length: 0,
+            active_records: Vec::new(),
+            entries: Vec::new(),
+            streams: HashMap::new(),
+            unsynced: 0,
+            failed: false
+        };
+
+        for segment in segments.iter().copied().filter(|segment| *segment != active_segment) {
+            let records = match log.read_index(segment) {
+                Some(records) => records,
+                None => log.rebuild_index(segment)?
+            };
+            for record in records {
+                log.register(segment, &record);
+            }
+        }
+
+        let bytes = fs::read(segment_path(directory, active_segment))?;
+        let (records, length) = scan(&bytes, log.entries.len() as u64);
+        if length < bytes.len() as u64 {
+            warn!("Cutting off {} bytes of an incomplete write at the end of segment {}", bytes.len() as u64 - length, active_segment);
+            log.active.set_len(length)?;
+            log.active.sync_all()?;
+        }
+        for record in &records {
+            log.register(active_segment, record);
+        }
+        log.active_length = length;
+        log.active_records = records;
+
+        Ok(log)
+    }
+
+    /// The records listed in the index of a sealed segment, None if the index is missing or unreadable
+    fn read_index(&self, segment: u64) -> Option<Vec<RecordLocation>> {
+        let bytes = fs::read(index_path(&self.directory, segment)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// Scan a sealed segment whose index is missing and write its index again. Sealed segments were flushed
+    /// completely, so an invalid record in one means the log was damaged.
+    fn rebuild_index(&self, segment: u64) -> Result<Vec<RecordLocation>, DatabaseError> {
+        let bytes = fs::read(segment_path(&self.directory, segment))?;
+        let (records, length) = scan(&bytes, self.entries.len() as u64);
+        if length < bytes.len() as u64 { return Err(corrupted(segment, length)) }
+
+        warn!("Rebuilt the missing index of segment {}", segment);
+        write_atomically(&index_path(&self.directory, segment), &serde_json::to_vec(&records)?)?;
+        Ok(records)
+    }
+
+    fn register(&mut self, segment: u64, record: &RecordLocation) {
+        let stream_id: Arc<str> = Arc::from(record.stream_id.as_str());
+        let positions = self.streams.entry(record.stream_id.clone()).or_default();
+
+        for index in 0..record.count {
+            let position = self.entries.len() as u64;
+            self.entries.push(Entry {
+                stream_id: stream_id.clone(),
+                number: positions.len() as u64,
+                segment,
+                offset: record.offset,
+                length: record.length,
+                index
+            });
+            positions.push(position);
+        }
+    }
+
+    /// The number of the last event in a stream, None if the stream doesn't exist
+    pub fn version(&self, stream_id: &str) -> Option<u64> {
+        self.streams.get(stream_id).map(|positions| positions.len() as u64 - 1)
+    }
+
+    /// Append events to a stream as a single record
+    ///
+    /// # Returns
+    ///
+    /// The appended events, with their positions
+    pub fn append(&mut self, stream_id: &str, events: Vec<NewEvent>) -> Result<Vec<LocalEvent>, DatabaseError> {
+        if self.failed {
+            let message = "a failed write couldn't be undone, the log has to be opened again";
+            return Err(DatabaseError::IoError(io::Error::other(message)));
+        }
+        if events.is_empty() { return Ok(Vec::new()) }
+
+        let position = self.entries.len() as u64;
+        let record = Record { stream_id: stream_id.to_string(), position, events };
+        let body = serde_json::to_vec(&record)?;
+        let length = body.len() as u64;
+
+        if self.active_length > 0 && self.active_length + HEADER_LENGTH + length > self.options.segment_size {
+            self.seal()?;
+        }
+
+        let mut frame = Vec::with_capacity((HEADER_LENGTH + length) as usize);
+        frame.extend_from_slice(&(length as u32).to_le_bytes());
+        frame.extend_from_slice(&checksum(&body).to_le_bytes());
+        frame.extend_from_slice(&body);
+        if let Err(e) = self.write_frame(&frame) {
+            // Don't leave the record behind for the next write to append to, or for the next open to find. A write
+            // that failed to flush may still reach the disk later, so it's cut off as well.
+            if self.active.set_len(self.active_length).and_then(|_| self.active.sync_all()).is_err() {
+                error!("Failed to undo a failed write to segment {}", self.active_segment);
+                self.failed = true;
+            }
+            return Err(e);
+        }
+
+        let location = RecordLocation { stream_id: stream_id.to_string(), offset: self.active_length, length, count: record.events.len() };
+        self.register(self.active_segment, &location);
+        self.active_records.push(location);
+        self.active_length += HEADER_LENGTH + length;
+
+        let Record { stream_id, events, .. } = record;
+        let first_number = self.version(&stream_id).unwrap_or(0) + 1 - events.len() as u64;
+        Ok(events.into_iter().enumerate().map(|(index, event)| LocalEvent {
+            position: position + index as u64,
+            stream_id: stream_id.clone(),
+            number: first_number + index as u64,
+            event
+        }).collect())
+    }
+
+    /// Write a framed record to the active segment and flush it if the sync policy says so
+    fn write_frame(&mut self, frame: &[u8]) -> Result<(), DatabaseError> {
+        self.active.write_all(frame)?;
+
+        self.unsynced += 1;
+        let due = match self.options.sync {
+            SyncPolicy::Always => true,
+            SyncPolicy::Every(writes) => self.unsynced >= writes,
+            SyncPolicy::Never => false
+        };
+        if due {
+            if let Err(e) = self.sync() {
+                // The earlier writes since the last flush may be lost as well, and they can't be taken back anymore
+                if self.unsynced > 1 { self.failed = true; }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush every write to disk
+    pub fn sync(&mut self) -> Result<(), DatabaseError> {
+        self.active.sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Write the index of the active segment and continue in a new one
+    fn seal(&mut self) -> Result<(), DatabaseError> {
+        self.sync()?;
+        write_atomically(&index_path(&self.directory, self.active_segment), &serde_json::to_vec(&self.active_records)?)?;
+
+        let segment = self.active_segment + 1;
+        self.active = OpenOptions::new().create(true).append(true).open(segment_path(&self.directory, segment))?;
+        sync_directory(&self.directory)?;
+        self.active_segment = segment;
+        self.active_length = 0;
+        self.active_records.clear();
+        Ok(())
+    }
+
+    /// Read the events at some positions, in the order of the positions
+    fn read_positions<I: IntoIterator<Item = u64>>(&self, positions: I) -> Result<Vec<LocalEvent>, DatabaseError> {
+        let mut events = Vec::new();
+        // Consecutive events are usually in the same record, so the last one is kept around
+        let mut file: Option<(u64, File)> = None;
+        let mut record: Option<(u64, u64, Record)> = None;
+
+        for position in positions {
+            let entry = &self.entries[position as usize];
+
+            let cached = matches!(&record, Some((segment, offset, _)) if *segment == entry.segment && *offset == entry.offset);
+            if !cached {
+                if !matches!(&file, Some((segment, _)) if *segment == entry.segment) {
+                    file = Some((entry.segment, File::open(segment_path(&self.directory, entry.segment))?));
+                }
+                let (_, segment_file) = file.as_mut().unwrap();
+                let mut body = vec![0; entry.length as usize];
+                segment_file.seek(SeekFrom::Start(entry.offset + HEADER_LENGTH))?;
+                segment_file.read_exact(&mut body)?;
+                record = Some((entry.segment, entry.offset, serde_json::from_slice(&body)?));
+            }
+
+            let (_, _, Record { events: stored, .. }) = record.as_ref().unwrap();
+            events.push(LocalEvent {
+                position,
+                stream_id: entry.stream_id.to_string(),
+                number: entry.number,
+                event: stored[entry.index].clone()
+            });
+        }
+
+        Ok(events)
+    }
+
+    /// Read the events of a stream, starting at the event with the number `from`
+    pub fn read_stream(&self, stream_id: &str, from: u64) -> Result<Vec<LocalEvent>, DatabaseError> {
+        let positions = self.streams.get(stream_id)
+            .map(|positions| positions.iter().skip(from as usize).copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+        self.read_positions(positions)
+    }
+
+    /// Read the events of every stream whose id starts with a prefix, in the order they were written
+    ///
+    /// # Arguments
+    ///
+    /// * `after` - Only read events after this position
+    pub fn read_prefix(&self, prefix: &str, after: Option<u64>) -> Result<Vec<LocalEvent>, DatabaseError> {
+        let first = after.map_or(0, |position| position as usize + 1);
+        let positions = self.entries.iter().enumerate()
+            .skip(first)
+            .filter(|(_, entry)| entry.stream_id.starts_with(prefix))
+            .map(|(position, _)| position as u64)
+            .collect::<Vec<_>>();
+        self.read_positions(positions)
+    }
+}
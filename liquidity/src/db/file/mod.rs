@@ -0,0 +1,503 @@
+//! A `DbConnection` storing events in files, so a single node can run without EventStore
+
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use crate::db::{
+    Acknowledge, Aggregate, DatabaseError, DbConnection, EventMetadata, EventStream, EventType, ExpectedVersion, NewEvent,
+    PersistentSubscription, StoredEvent, Versioned
+};
+use crate::db::local::{self, write_atomically, Groups, LocalEvent, Subscribers};
+use crate::db::worker::Workers;
+
+mod log;
+
+use self::log::Log;
+pub use self::log::{FileOptions, SyncPolicy};
+
+const GROUPS_FILE: &str = "groups.json";
+const SNAPSHOT_DIRECTORY: &str = "snapshots";
+
+/// The file the snapshot of a stream is kept in. Characters that aren't safe in file names are percent-encoded, so
+/// every stream gets a file of its own.
+fn snapshot_path(directory: &Path, stream: &str) -> PathBuf {
+    let name = stream.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte)
+        })
+        .collect::<String>();
+    directory.join(SNAPSHOT_DIRECTORY).join(format!("{}.json", name))
+}
+
+/// Everything the thread the log is written on owns
+struct State {
+    directory: PathBuf,
+    log: Log,
+    subscribers: Subscribers,
+    groups: Groups
+}
+
+/// Stores events in an append-only log in a directory. Only one process may open a directory at a time.
+///
+/// The log is read and written on a thread of its own, so its I/O never blocks the async executor.
+///
+/// # Example
+///
+/// ```
+/// use liquidity::db::{DbConnection, EventMetadata, ExpectedVersion, FileConnection, FileOptions, SyncPolicy};
+/// use serde_json::{json, Value};
+/// # futures::executor::block_on(async {
+/// # let directory = std::env::temp_dir().join(liquidity::Uuid::new_v4().to_string());
+///
+/// let conn = FileConnection::open(&directory, FileOptions::new(1024, SyncPolicy::Always)).unwrap();
+/// conn.write_event("test-1", "test", json!({ "name": "test" }), &EventMetadata::system(), ExpectedVersion::Any).await.unwrap();
+/// drop(conn);
+///
+/// let reopened = FileConnection::open(&directory, FileOptions::default()).unwrap();
+/// let events = reopened.read_all::<_, Value>("test-1").await.unwrap();
+///
+/// assert_eq!(events[0].payload, json!({ "name": "test" }));
+/// # std::fs::remove_dir_all(directory).unwrap();
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct FileConnection {
+    workers: Arc<Workers<State>>
+}
+
+impl FileConnection {
+    /// Open the event log in a directory, creating it if it doesn't exist. The directory stays locked until the last
+    /// clone of the connection is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory to store the log in
+    /// * `options` - The size of the segment files and when writes are flushed to disk
+    pub fn open<P: AsRef<Path>>(directory: P, options: FileOptions) -> Result<Self, DatabaseError> {
+        let directory = directory.as_ref().to_path_buf();
+        let log = Log::open(&directory, options)?;
+        let groups = match fs::read(directory.join(GROUPS_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Groups::new(),
+            Err(e) => return Err(e.into())
+        };
+
+        let state = State { directory, log, subscribers: Subscribers::default(), groups };
+        Ok(FileConnection { workers: Arc::new(Workers::new("file-log", vec![state])?) })
+    }
+
+    /// Flush every write to disk, regardless of the sync policy. Call it before shutting down.
+    pub async fn sync(&self) -> Result<(), DatabaseError> {
+        self.workers.run(|state| state.log.sync()).await
+    }
+
+    /// Read the events of a stream, starting at the event with the number `from`
+    async fn read_stream(&self, stream: &str, from: u64) -> Result<Vec<LocalEvent>, DatabaseError> {
+        let stream = stream.to_string();
+        self.workers.run(move |state| state.log.read_stream(&stream, from)).await
+    }
+
+    async fn fold<T, F>(&self, stream: &str, start: Option<(T, u64)>, include: F) -> Result<Option<(T, u64)>, DatabaseError>
+        where T: Aggregate + Send, F: Fn(&LocalEvent) -> bool + Send {
+
+        let events = self.read_stream(stream, local::first_event(&start)).await?;
+        local::fold(stream, events, start, include)
+    }
+}
+
+/// Stores the acknowledged position of a consumer group with the log, so the group continues after a restart
+struct FileAcks {
+    workers: Arc<Workers<State>>,
+    stream: String,
+    group: String
+}
+
+#[async_trait]
+impl Acknowledge for FileAcks {
+    async fn ack(&mut self, position: u64) -> Result<(), DatabaseError> {
+        let stream = self.stream.clone();
+        let group = self.group.clone();
+
+        self.workers.run(move |state| {
+            local::acknowledge(&mut state.groups, &stream, &group, position);
+            write_atomically(&state.directory.join(GROUPS_FILE), &serde_json::to_vec(&state.groups)?)
+        }).await
+    }
+}
+
+#[async_trait]
+impl DbConnection for FileConnection {
+    async fn write_event<S, E, P>(&self, stream: S, event_type: E, payload: P, metadata: &EventMetadata, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, E: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+
+        let event = NewEvent::new(event_type, &payload, metadata)?;
+        self.write_events(stream, vec![event], expected_version).await
+    }
+
+    #[instrument(skip(self, events))]
+    async fn write_events<S>(&self, stream: S, events: Vec<NewEvent>, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        let stream = stream.as_ref().to_string();
+        self.workers.run(move |state| {
+            if !local::version_matches(state.log.version(&stream), expected_version) {
+                return Err(DatabaseError::WrongExpectedVersion(stream))
+            }
+
+            let written = state.log.append(&stream, events)?;
+            state.subscribers.broadcast(&written);
+            Ok(())
+        }).await
+    }
+
+    #[instrument(skip(self))]
+    async fn create<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug {
+
+        self.write_event(stream, EventType::Create, payload, &metadata.versioned::<P>(), ExpectedVersion::NoStream).await
+    }
+
+    #[instrument(skip(self))]
+    async fn read<S, T>(&self, stream: S) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        Ok(self.fold(stream.as_ref(), None, |_| true).await?.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self, start))]
+    async fn read_from<S, T>(&self, stream: S, start: Option<(T, u64)>) -> Result<Option<(T, u64)>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        self.fold(stream.as_ref(), start, |_| true).await
+    }
+
+    #[instrument(skip(self))]
+    async fn read_snapshot<S>(&self, stream: S) -> Result<Option<Value>, DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        let stream = stream.as_ref().to_string();
+        self.workers.run(move |state| {
+            match fs::read(snapshot_path(&state.directory, &stream)) {
+                Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into())
+            }
+        }).await
+    }
+
+    /// Snapshots are kept next to the log, in a file per stream that each new snapshot replaces
+    #[instrument(skip(self, snapshot))]
+    async fn write_snapshot<S>(&self, stream: S, snapshot: Value) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        let stream = stream.as_ref().to_string();
+        self.workers.run(move |state| {
+            fs::create_dir_all(state.directory.join(SNAPSHOT_DIRECTORY))?;
+            write_atomically(&snapshot_path(&state.directory, &stream), &serde_json::to_vec(&snapshot)?)
+        }).await
+    }
+
+    #[instrument(skip(self))]
+    async fn read_at_version<S, T>(&self, stream: S, version: u64) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        Ok(self.fold(stream.as_ref(), None, move |event| event.number <= version).await?.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self))]
+    async fn read_as_of<S, T>(&self, stream: S, as_of: DateTime<Utc>) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        Ok(self.fold(stream.as_ref(), None, move |event| event.event.metadata.timestamp <= as_of).await?.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self))]
+    async fn read_last<S, P>(&self, stream: S) -> Result<Option<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        let stream = stream.as_ref().to_string();
+        let event = self.workers.run(move |state| match state.log.version(&stream) {
+            Some(version) => Ok(state.log.read_stream(&stream, version)?.pop()),
+            None => Ok(None)
+        }).await?;
+        event.map(LocalEvent::into_stored).transpose()
+    }
+
+    #[instrument(skip(self))]
+    async fn read_all<S, P>(&self, stream: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        let events = self.read_stream(stream.as_ref(), 0).await?;
+        events.into_iter().map(LocalEvent::into_stored).collect()
+    }
+
+    #[instrument(skip(self))]
+    async fn read_category<S, P>(&self, category: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        let prefix = format!("{}-", category.as_ref());
+        let events = self.workers.run(move |state| state.log.read_prefix(&prefix, None)).await?;
+        events.into_iter().map(LocalEvent::into_stored).collect()
+    }
+
+    #[instrument(skip(self))]
+    async fn subscribe<S, P>(&self, stream: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        let stream_id = stream.as_ref().to_string();
+        let (existing, receiver) = self.workers.run({
+            let stream_id = stream_id.clone();
+            move |state| {
+                let existing = state.log.read_stream(&stream_id, from.map_or(0, |from| from + 1))?;
+                // Subscribe in the same job, so no event is written in between
+                Ok((existing, state.subscribers.subscribe()))
+            }
+        }).await?;
+
+        Ok(local::stream_subscription(stream_id, existing, receiver, from))
+    }
+
+    /// Positions in categories are positions in the whole log, so they stay valid when other streams are written to
+    #[instrument(skip(self))]
+    async fn subscribe_category<S, P>(&self, category: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        let prefix = format!("{}-", category.as_ref());
+        let (existing, receiver) = self.workers.run({
+            let prefix = prefix.clone();
+            move |state| Ok((state.log.read_prefix(&prefix, from)?, state.subscribers.subscribe()))
+        }).await?;
+
+        Ok(local::prefix_subscription(prefix, existing, receiver, from))
+    }
+
+    #[instrument(skip(self))]
+    async fn subscribe_persistent<S, G, P>(&self, stream: S, group: G) -> Result<PersistentSubscription<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, G: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        // Members of a group don't share the load here, every subscription gets every event
+        let acks = FileAcks {
+            workers: self.workers.clone(),
+            stream: stream.as_ref().to_string(),
+            group: group.as_ref().to_string()
+        };
+        let (stream_id, group_id) = (acks.stream.clone(), acks.group.clone());
+        let from = self.workers.run(move |state| {
+            Ok(state.groups.get(&stream_id).and_then(|groups| groups.get(&group_id)).copied())
+        }).await?;
+        let events = self.subscribe(stream, from).await?;
+
+        Ok(PersistentSubscription::new(events, Box::new(acks)))
+    }
+
+    #[instrument(skip(self))]
+    async fn update<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug {
+
+        self.write_event(stream, EventType::Update, payload, &metadata.versioned::<P>(), ExpectedVersion::Any).await
+    }
+
+    #[instrument(skip(self))]
+    async fn delete<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+
+        self.write_event(stream, EventType::Delete, payload, metadata, ExpectedVersion::Any).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+    use crate::Uuid;
+    use crate::db::{DatabaseError, DbConnection, EventMetadata, EventStream, ExpectedVersion};
+    use super::{FileConnection, FileOptions, SyncPolicy};
+
+    fn directory() -> PathBuf {
+        std::env::temp_dir().join(format!("liquidity-{}", Uuid::new_v4()))
+    }
+
+    async fn write(conn: &FileConnection, stream: &str, payload: i64) {
+        conn.write_event(stream, "test", json!(payload), &EventMetadata::system(), ExpectedVersion::Any).await.unwrap();
+    }
+
+    async fn read(conn: &FileConnection, stream: &str) -> Vec<Value> {
+        conn.read_all::<_, Value>(stream).await.unwrap().into_iter().map(|event| event.payload).collect()
+    }
+
+    async fn next(events: &mut EventStream<Value>) -> (u64, String, Value) {
+        let event = events.next().await.unwrap().unwrap();
+        (event.position, event.event.stream_id, event.event.payload)
+    }
+
+    #[test]
+    fn cuts_off_incomplete_writes() {
+        block_on(async {
+            let directory = directory();
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            write(&conn, "test-a", 0).await;
+            write(&conn, "test-a", 1).await;
+            drop(conn);
+
+            // A crash in the middle of a write leaves part of a record behind
+            let mut segment = OpenOptions::new().append(true).open(directory.join(format!("{:020}.log", 0))).unwrap();
+            segment.write_all(&[200, 0, 0, 0, 1, 2, 3, 4, b'{']).unwrap();
+            drop(segment);
+
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            assert_eq!(read(&conn, "test-a").await, vec![json!(0), json!(1)]);
+            write(&conn, "test-a", 2).await;
+            drop(conn);
+
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            assert_eq!(read(&conn, "test-a").await, vec![json!(0), json!(1), json!(2)]);
+            fs::remove_dir_all(directory).unwrap();
+        })
+    }
+
+    #[test]
+    fn locks_the_directory() {
+        block_on(async {
+            let directory = directory();
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            assert!(matches!(FileConnection::open(&directory, FileOptions::default()), Err(DatabaseError::IoError(_))));
+
+            // Clones share the lock, it's released with the last one
+            let clone = conn.clone();
+            drop(conn);
+            write(&clone, "test-a", 0).await;
+            drop(clone);
+
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            assert_eq!(read(&conn, "test-a").await, vec![json!(0)]);
+            fs::remove_dir_all(directory).unwrap();
+        })
+    }
+
+    #[test]
+    fn rolls_over_segments() {
+        block_on(async {
+            let directory = directory();
+            let options = FileOptions::new(256, SyncPolicy::Every(2));
+            let conn = FileConnection::open(&directory, options).unwrap();
+            for payload in 0..6 {
+                write(&conn, if payload % 2 == 0 { "test-a" } else { "test-b" }, payload).await;
+            }
+            conn.sync().await.unwrap();
+            drop(conn);
+
+            let segments = fs::read_dir(&directory).unwrap()
+                .filter(|entry| matches!(entry.as_ref().unwrap().path().extension(), Some(extension) if extension == "log"))
+                .count();
+            assert!(segments > 1);
+
+            // Indexes are only a shortcut, a missing one is rebuilt from its segment
+            fs::remove_file(directory.join(format!("{:020}.idx", 0))).unwrap();
+
+            let conn = FileConnection::open(&directory, options).unwrap();
+            assert_eq!(read(&conn, "test-a").await, vec![json!(0), json!(2), json!(4)]);
+            let category = conn.read_category::<_, Value>("test").await.unwrap();
+            assert_eq!(category.into_iter().map(|event| event.payload).collect::<Vec<_>>(), (0..6).map(|payload| json!(payload)).collect::<Vec<_>>());
+            assert!(directory.join(format!("{:020}.idx", 0)).exists());
+            fs::remove_dir_all(directory).unwrap();
+        })
+    }
+
+    #[test]
+    fn checks_expected_versions() {
+        block_on(async {
+            let directory = directory();
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            let metadata = EventMetadata::system();
+
+            conn.write_event("test-a", "test", json!(0), &metadata, ExpectedVersion::NoStream).await.unwrap();
+            let exists = conn.write_event("test-a", "test", json!(1), &metadata, ExpectedVersion::NoStream).await;
+            assert!(matches!(exists, Err(DatabaseError::WrongExpectedVersion(_))));
+
+            conn.write_event("test-a", "test", json!(1), &metadata, ExpectedVersion::Exact(0)).await.unwrap();
+            let stale = conn.write_event("test-a", "test", json!(2), &metadata, ExpectedVersion::Exact(0)).await;
+            assert!(matches!(stale, Err(DatabaseError::WrongExpectedVersion(_))));
+
+            assert_eq!(read(&conn, "test-a").await, vec![json!(0), json!(1)]);
+            fs::remove_dir_all(directory).unwrap();
+        })
+    }
+
+    #[test]
+    fn category_positions_survive_restarts() {
+        block_on(async {
+            let directory = directory();
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            write(&conn, "test-b", 0).await;
+            write(&conn, "other-a", 0).await;
+            write(&conn, "test-a", 0).await;
+
+            let mut events = conn.subscribe_category::<_, Value>("test", None).await.unwrap();
+            assert_eq!(next(&mut events).await, (0, "test-b".to_string(), json!(0)));
+            assert_eq!(next(&mut events).await, (2, "test-a".to_string(), json!(0)));
+            write(&conn, "test-c", 0).await;
+            assert_eq!(next(&mut events).await, (3, "test-c".to_string(), json!(0)));
+            drop(events);
+            drop(conn);
+
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            write(&conn, "test-0", 0).await;
+            let mut events = conn.subscribe_category::<_, Value>("test", Some(2)).await.unwrap();
+            assert_eq!(next(&mut events).await, (3, "test-c".to_string(), json!(0)));
+            assert_eq!(next(&mut events).await, (4, "test-0".to_string(), json!(0)));
+            fs::remove_dir_all(directory).unwrap();
+        })
+    }
+
+    #[test]
+    fn persistent_subscriptions_survive_restarts() {
+        block_on(async {
+            let directory = directory();
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            for payload in 0..3 {
+                write(&conn, "test-a", payload).await;
+            }
+
+            let mut subscription = conn.subscribe_persistent::<_, _, Value>("test-a", "group").await.unwrap();
+            let first = subscription.next().await.unwrap().unwrap();
+            subscription.ack(&first).await.unwrap();
+            drop(subscription);
+            drop(conn);
+
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            let mut subscription = conn.subscribe_persistent::<_, _, Value>("test-a", "group").await.unwrap();
+            let next = subscription.next().await.unwrap().unwrap();
+            assert_eq!((next.position, next.event.payload), (1, json!(1)));
+            fs::remove_dir_all(directory).unwrap();
+        })
+    }
+
+    #[test]
+    fn keeps_the_latest_snapshot() {
+        block_on(async {
+            let directory = directory();
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            assert_eq!(conn.read_snapshot("test/a").await.unwrap(), None);
+            conn.write_snapshot("test/a", json!(1)).await.unwrap();
+            conn.write_snapshot("test/a", json!(2)).await.unwrap();
+            conn.write_snapshot("test_a", json!(3)).await.unwrap();
+            drop(conn);
+
+            let conn = FileConnection::open(&directory, FileOptions::default()).unwrap();
+            assert_eq!(conn.read_snapshot("test/a").await.unwrap(), Some(json!(2)));
+            assert_eq!(conn.read_snapshot("test_a").await.unwrap(), Some(json!(3)));
+            assert_eq!(fs::read_dir(directory.join("snapshots")).unwrap().count(), 2);
+            assert!(conn.read_category::<_, Value>("test").await.unwrap().is_empty());
+            fs::remove_dir_all(directory).unwrap();
+        })
+    }
+}
@@ -0,0 +1,165 @@
+//! What the backends that store events themselves (memory, file and SQL) share: the events they store, how they fold
+//! and subscribe to them, how new events reach subscriptions and how files are replaced without tearing them
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use futures::{future, stream, StreamExt};
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use crate::db::{fold_event, Aggregate, DatabaseError, EventStream, ExpectedVersion, NewEvent, StoredEvent, SubscriptionEvent};
+
+/// Flush the entries of a directory, so files created in it survive a crash. Not every platform can open directories,
+/// so failing to is ignored.
+pub(crate) fn sync_directory(directory: &Path) -> Result<(), DatabaseError> {
+    match File::open(directory) {
+        Ok(directory) => Ok(directory.sync_all()?),
+        Err(_) => Ok(())
+    }
+}
+
+/// Write a file so it's either replaced completely or not at all, and flush it to disk
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), DatabaseError> {
+    let temporary = path.with_extension("tmp");
+    let mut file = File::create(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(temporary, path)?;
+    match path.parent() {
+        Some(directory) => sync_directory(directory),
+        None => Ok(())
+    }
+}
+
+/// The position of the last event each consumer group acknowledged, by stream and group
+pub(crate) type Groups = HashMap<String, HashMap<String, u64>>;
+
+/// Record that a consumer group processed the events up to a position. Positions only ever move forward.
+pub(crate) fn acknowledge(groups: &mut Groups, stream: &str, group: &str, position: u64) {
+    let acknowledged = groups.entry(stream.to_string()).or_default().entry(group.to_string()).or_insert(position);
+    *acknowledged = (*acknowledged).max(position);
+}
+
+/// A stored event with its position among every event and its number in its stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LocalEvent {
+    /// The position of the event among the events of every stream
+    pub position: u64,
+    pub stream_id: String,
+    /// The number of the event in its stream, starting at 0
+    pub number: u64,
+    pub event: NewEvent
+}
+
+impl LocalEvent {
+    pub fn into_stored<P: DeserializeOwned>(self) -> Result<StoredEvent<P>, DatabaseError> {
+        Ok(StoredEvent {
+            stream_id: self.stream_id,
+            event_type: self.event.event_type,
+            payload: serde_json::from_value(self.event.payload)?,
+            metadata: Some(self.event.metadata)
+        })
+    }
+}
+
+/// Whether a write to a stream whose last event has the number `version` may go ahead
+pub(crate) fn version_matches(version: Option<u64>, expected_version: ExpectedVersion) -> bool {
+    match expected_version {
+        ExpectedVersion::Any => true,
+        ExpectedVersion::NoStream => version.is_none(),
+        ExpectedVersion::Exact(expected) => version == Some(expected)
+    }
+}
+
+/// The number of the first event to read to continue folding from `start`
+pub(crate) fn first_event<T>(start: &Option<(T, u64)>) -> u64 {
+    start.as_ref().map_or(0, |(_, number)| *number + 1)
+}
+
+/// Fold the events of a stream into a single object, skipping the events `include` rejects
+///
+/// # Arguments
+///
+/// * `events` - The events of the stream, starting at the one `first_event` returned
+/// * `start` - The state to continue from and the number of the last event folded into it
+pub(crate) fn fold<T, F>(stream: &str, events: Vec<LocalEvent>, start: Option<(T, u64)>, include: F) -> Result<Option<(T, u64)>, DatabaseError>
+    where T: Aggregate, F: Fn(&LocalEvent) -> bool {
+
+    events.into_iter()
+        .filter(|event| include(event))
+        .try_fold(start, |acc, LocalEvent { number, event: NewEvent { event_type, payload, metadata }, .. }| {
+            let state = fold_event(acc.map(|(state, _)| state), &event_type, payload, Some(&metadata), number, stream)?;
+            Ok(state.map(|state| (state, number)))
+        })
+}
+
+/// Receive every event written after they subscribed
+#[derive(Default)]
+pub(crate) struct Subscribers(Vec<UnboundedSender<LocalEvent>>);
+
+impl Subscribers {
+    pub fn subscribe(&mut self) -> UnboundedReceiver<LocalEvent> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.0.push(sender);
+        receiver
+    }
+
+    /// Send written events to every subscription that's still open. Broadcast before the next write, so subscriptions
+    /// see every event exactly once and in order.
+    pub fn broadcast(&mut self, events: &[LocalEvent]) {
+        for event in events {
+            self.0.retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+        }
+    }
+}
+
+/// Subscribe to a stream, with the numbers of its events as positions
+///
+/// # Arguments
+///
+/// * `existing` - The events of the stream after `from`, read while subscribing
+/// * `receiver` - Receives the events written after subscribing
+pub(crate) fn stream_subscription<P>(stream_id: String, existing: Vec<LocalEvent>, receiver: UnboundedReceiver<LocalEvent>, from: Option<u64>) -> EventStream<P>
+    where P: DeserializeOwned + Send + 'static {
+
+    subscription(existing, receiver, move |event| event.stream_id == stream_id, |event| event.number, from)
+}
+
+/// Subscribe to the streams whose ids start with a prefix. Positions are positions among every event, so they stay
+/// valid when other streams are written to.
+///
+/// # Arguments
+///
+/// * `existing` - The events of the streams after `from`, read while subscribing
+/// * `receiver` - Receives the events written after subscribing
+pub(crate) fn prefix_subscription<P>(prefix: String, existing: Vec<LocalEvent>, receiver: UnboundedReceiver<LocalEvent>, from: Option<u64>) -> EventStream<P>
+    where P: DeserializeOwned + Send + 'static {
+
+    subscription(existing, receiver, move |event| event.stream_id.starts_with(&prefix), |event| event.position, from)
+}
+
+/// Subscribe to the events of the streams `matches` accepts
+///
+/// # Arguments
+///
+/// * `position` - The position of an event in what was subscribed to
+fn subscription<P, M, N>(existing: Vec<LocalEvent>, receiver: UnboundedReceiver<LocalEvent>, matches: M, position: N, from: Option<u64>) -> EventStream<P>
+    where P: DeserializeOwned + Send + 'static,
+          M: Fn(&LocalEvent) -> bool + Send + 'static,
+          N: Fn(&LocalEvent) -> u64 + Send + Sync + Copy + 'static {
+
+    stream::iter(existing)
+        .chain(receiver.filter(move |event| future::ready(matches(event))))
+        .filter(move |event| future::ready(is_after(position(event), from)))
+        .map(move |event| Ok(SubscriptionEvent { position: position(&event), event: event.into_stored()? }))
+        .boxed()
+}
+
+/// Whether the event at `position` comes after the position a subscription started from
+fn is_after(position: u64, from: Option<u64>) -> bool {
+    match from {
+        Some(from) => position > from,
+        None => true
+    }
+}
@@ -0,0 +1,493 @@
+//! A `DbConnection` keeping every event in memory, for demos and ephemeral environments
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use crate::db::{
+    Acknowledge, Aggregate, DatabaseError, DbConnection, EventMetadata, EventStream, EventType, ExpectedVersion, NewEvent,
+    PersistentSubscription, StoredEvent, Versioned
+};
+use crate::db::local::{self, Groups, LocalEvent, Subscribers};
+
+#[derive(Default, Serialize, Deserialize)]
+struct Store {
+    /// Every event in the order it was written, so the index of an event is its position
+    events: Vec<LocalEvent>,
+    /// The positions of the events in each stream. Not dumped, it's rebuilt from the events.
+    #[serde(skip)]
+    streams: HashMap<String, Vec<usize>>,
+    groups: Groups,
+    /// The latest snapshot of each stream
+    #[serde(default)]
+    snapshots: HashMap<String, Value>,
+    #[serde(skip)]
+    subscribers: Subscribers
+}
+
+impl Store {
+    fn index(&mut self) {
+        self.streams.clear();
+        for (index, event) in self.events.iter().enumerate() {
+            self.streams.entry(event.stream_id.clone()).or_default().push(index);
+        }
+    }
+
+    /// The number of the last event in a stream, None if the stream doesn't exist
+    fn version(&self, stream: &str) -> Option<u64> {
+        self.streams.get(stream)
+            .and_then(|positions| positions.len().checked_sub(1))
+            .map(|version| version as u64)
+    }
+
+    fn append(&mut self, stream: &str, events: Vec<NewEvent>) -> Vec<LocalEvent> {
+        let first_number = self.version(stream).map_or(0, |version| version + 1);
+        let written = events.into_iter()
+            .enumerate()
+            .map(|(index, event)| LocalEvent {
+                position: (self.events.len() + index) as u64,
+                stream_id: stream.to_string(),
+                number: first_number + index as u64,
+                event
+            })
+            .collect::<Vec<_>>();
+
+        let positions = self.streams.entry(stream.to_string()).or_default();
+        positions.extend(written.iter().map(|event| event.position as usize));
+        self.events.extend(written.iter().cloned());
+        written
+    }
+
+    /// The events of a stream, starting at the event numbered `first_event`
+    fn read_stream(&self, stream: &str, first_event: u64) -> Vec<LocalEvent> {
+        self.streams.get(stream)
+            .map(|positions| positions.iter().skip(first_event as usize).map(|position| self.events[*position].clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// The events of every stream starting with `prefix`, after the position `from`
+    fn read_prefix(&self, prefix: &str, from: Option<u64>) -> Vec<LocalEvent> {
+        let first_position = from.map_or(0, |from| from as usize + 1);
+        self.events.iter()
+            .skip(first_position)
+            .filter(|event| event.stream_id.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Keeps every event in memory, ordered by a position shared by all streams. Everything is lost when the process
+/// exits unless the connection was loaded from a file and dumped back to it.
+///
+/// # Example
+///
+/// ```
+/// use liquidity::db::{DbConnection, EventMetadata, ExpectedVersion, MemoryConnection};
+/// use serde_json::{json, Value};
+/// # futures::executor::block_on(async {
+/// # let path = std::env::temp_dir().join(format!("{}.json", liquidity::Uuid::new_v4()));
+///
+/// let conn = MemoryConnection::load(&path).unwrap();
+/// conn.write_event("test-1", "test", json!({ "name": "test" }), &EventMetadata::system(), ExpectedVersion::Any).await.unwrap();
+/// conn.dump().unwrap();
+///
+/// let reloaded = MemoryConnection::load(&path).unwrap();
+/// let events = reloaded.read_all::<_, Value>("test-1").await.unwrap();
+///
+/// assert_eq!(events[0].payload, json!({ "name": "test" }));
+/// # std::fs::remove_file(path).unwrap();
+/// # });
+/// ```
+#[derive(Clone, Default)]
+pub struct MemoryConnection {
+    store: Arc<Mutex<Store>>,
+    /// The file `dump` writes to
+    path: Option<PathBuf>
+}
+
+impl MemoryConnection {
+    /// An empty store that is never written to disk
+    pub fn new() -> Self {
+        MemoryConnection::default()
+    }
+
+    /// Restore the events dumped to a file, starting empty if it doesn't exist yet
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to restore from, `dump` writes back to it
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, DatabaseError> {
+        let path = path.as_ref().to_path_buf();
+        let mut store = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice::<Store>(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Store::default(),
+            Err(e) => return Err(e.into())
+        };
+        store.index();
+
+        Ok(MemoryConnection {
+            store: Arc::new(Mutex::new(store)),
+            path: Some(path)
+        })
+    }
+
+    /// Write every event and acknowledged position to the file the connection was loaded from. Call it before shutting
+    /// down. Does nothing if the connection wasn't loaded from a file.
+    pub fn dump(&self) -> Result<(), DatabaseError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(())
+        };
+
+        let bytes = serde_json::to_vec(&*self.store.lock().unwrap())?;
+        local::write_atomically(path, &bytes)
+    }
+
+    /// The events of a stream as they were written, for tests
+    pub fn events<S: AsRef<str>>(&self, stream: S) -> Vec<NewEvent> {
+        self.store.lock().unwrap().read_stream(stream.as_ref(), 0).into_iter().map(|event| event.event).collect()
+    }
+
+    fn fold<T, F>(&self, stream: &str, start: Option<(T, u64)>, include: F) -> Result<Option<(T, u64)>, DatabaseError>
+        where T: Aggregate, F: Fn(&LocalEvent) -> bool {
+
+        let events = self.store.lock().unwrap().read_stream(stream, local::first_event(&start));
+        local::fold(stream, events, start, include)
+    }
+}
+
+/// Stores the acknowledged position of a consumer group with the events, so it's dumped along with them
+struct MemoryAcks {
+    store: Arc<Mutex<Store>>,
+    stream: String,
+    group: String
+}
+
+#[async_trait]
+impl Acknowledge for MemoryAcks {
+    async fn ack(&mut self, position: u64) -> Result<(), DatabaseError> {
+        local::acknowledge(&mut self.store.lock().unwrap().groups, &self.stream, &self.group, position);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DbConnection for MemoryConnection {
+    async fn write_event<S, E, P>(&self, stream: S, event_type: E, payload: P, metadata: &EventMetadata, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, E: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+
+        let event = NewEvent::new(event_type, &payload, metadata)?;
+        self.write_events(stream, vec![event], expected_version).await
+    }
+
+    #[instrument(skip(self, events))]
+    async fn write_events<S>(&self, stream: S, events: Vec<NewEvent>, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        let mut store = self.store.lock().unwrap();
+        if !local::version_matches(store.version(stream.as_ref()), expected_version) {
+            return Err(DatabaseError::WrongExpectedVersion(stream.as_ref().to_string()))
+        }
+
+        let written = store.append(stream.as_ref(), events);
+        store.subscribers.broadcast(&written);
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn create<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug {
+
+        self.write_event(stream, EventType::Create, payload, &metadata.versioned::<P>(), ExpectedVersion::NoStream).await
+    }
+
+    #[instrument(skip(self))]
+    async fn read<S, T>(&self, stream: S) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        Ok(self.fold(stream.as_ref(), None, |_| true)?.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self, start))]
+    async fn read_from<S, T>(&self, stream: S, start: Option<(T, u64)>) -> Result<Option<(T, u64)>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        self.fold(stream.as_ref(), start, |_| true)
+    }
+
+    #[instrument(skip(self))]
+    async fn read_snapshot<S>(&self, stream: S) -> Result<Option<Value>, DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        Ok(self.store.lock().unwrap().snapshots.get(stream.as_ref()).cloned())
+    }
+
+    #[instrument(skip(self, snapshot))]
+    async fn write_snapshot<S>(&self, stream: S, snapshot: Value) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        self.store.lock().unwrap().snapshots.insert(stream.as_ref().to_string(), snapshot);
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn read_at_version<S, T>(&self, stream: S, version: u64) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        Ok(self.fold(stream.as_ref(), None, |event| event.number <= version)?.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self))]
+    async fn read_as_of<S, T>(&self, stream: S, as_of: DateTime<Utc>) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        Ok(self.fold(stream.as_ref(), None, |event| event.event.metadata.timestamp <= as_of)?.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self))]
+    async fn read_last<S, P>(&self, stream: S) -> Result<Option<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        let event = {
+            let store = self.store.lock().unwrap();
+            store.version(stream.as_ref()).and_then(|version| store.read_stream(stream.as_ref(), version).pop())
+        };
+        event.map(LocalEvent::into_stored).transpose()
+    }
+
+    #[instrument(skip(self))]
+    async fn read_all<S, P>(&self, stream: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        let events = self.store.lock().unwrap().read_stream(stream.as_ref(), 0);
+        events.into_iter().map(LocalEvent::into_stored).collect()
+    }
+
+    #[instrument(skip(self))]
+    async fn read_category<S, P>(&self, category: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        let events = self.store.lock().unwrap().read_prefix(&format!("{}-", category.as_ref()), None);
+        events.into_iter().map(LocalEvent::into_stored).collect()
+    }
+
+    #[instrument(skip(self))]
+    async fn subscribe<S, P>(&self, stream: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        let stream_id = stream.as_ref().to_string();
+        // Subscribe before the store is unlocked, so no event is missed in between
+        let (existing, receiver) = {
+            let mut store = self.store.lock().unwrap();
+            (store.read_stream(&stream_id, from.map_or(0, |from| from + 1)), store.subscribers.subscribe())
+        };
+
+        Ok(local::stream_subscription(stream_id, existing, receiver, from))
+    }
+
+    /// Positions in categories are positions in the whole store, so they stay valid when other streams are written to
+    #[instrument(skip(self))]
+    async fn subscribe_category<S, P>(&self, category: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        let prefix = format!("{}-", category.as_ref());
+        let (existing, receiver) = {
+            let mut store = self.store.lock().unwrap();
+            (store.read_prefix(&prefix, from), store.subscribers.subscribe())
+        };
+
+        Ok(local::prefix_subscription(prefix, existing, receiver, from))
+    }
+
+    #[instrument(skip(self))]
+    async fn subscribe_persistent<S, G, P>(&self, stream: S, group: G) -> Result<PersistentSubscription<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, G: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        // Members of a group don't share the load here, every subscription gets every event
+        let from = self.store.lock().unwrap().groups.get(stream.as_ref()).and_then(|groups| groups.get(group.as_ref())).copied();
+        let acks = MemoryAcks {
+            store: self.store.clone(),
+            stream: stream.as_ref().to_string(),
+            group: group.as_ref().to_string()
+        };
+        let events = self.subscribe(stream, from).await?;
+
+        Ok(PersistentSubscription::new(events, Box::new(acks)))
+    }
+
+    #[instrument(skip(self))]
+    async fn update<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug {
+
+        self.write_event(stream, EventType::Update, payload, &metadata.versioned::<P>(), ExpectedVersion::Any).await
+    }
+
+    #[instrument(skip(self))]
+    async fn delete<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+
+        self.write_event(stream, EventType::Delete, payload, metadata, ExpectedVersion::Any).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+    use crate::Uuid;
+    use crate::db::{DatabaseError, DbConnection, EventMetadata, EventStream, ExpectedVersion, NewEvent};
+    use super::MemoryConnection;
+
+    fn path() -> PathBuf {
+        std::env::temp_dir().join(format!("liquidity-{}.json", Uuid::new_v4()))
+    }
+
+    async fn write(conn: &MemoryConnection, stream: &str, payload: i64) {
+        conn.write_event(stream, "test", json!(payload), &EventMetadata::system(), ExpectedVersion::Any).await.unwrap();
+    }
+
+    async fn read(conn: &MemoryConnection, stream: &str) -> Vec<Value> {
+        conn.read_all::<_, Value>(stream).await.unwrap().into_iter().map(|event| event.payload).collect()
+    }
+
+    async fn next(events: &mut EventStream<Value>) -> (u64, String, Value) {
+        let event = events.next().await.unwrap().unwrap();
+        (event.position, event.event.stream_id, event.event.payload)
+    }
+
+    #[test]
+    fn checks_expected_versions() {
+        block_on(async {
+            let conn = MemoryConnection::new();
+            let metadata = EventMetadata::system();
+
+            conn.write_event("test-a", "test", json!(0), &metadata, ExpectedVersion::NoStream).await.unwrap();
+            let exists = conn.write_event("test-a", "test", json!(1), &metadata, ExpectedVersion::NoStream).await;
+            assert!(matches!(exists, Err(DatabaseError::WrongExpectedVersion(_))));
+
+            let events = vec![NewEvent::new("test", &json!(1), &metadata).unwrap(), NewEvent::new("test", &json!(2), &metadata).unwrap()];
+            conn.write_events("test-a", events, ExpectedVersion::Exact(0)).await.unwrap();
+            let stale = conn.write_event("test-a", "test", json!(3), &metadata, ExpectedVersion::Exact(1)).await;
+            assert!(matches!(stale, Err(DatabaseError::WrongExpectedVersion(_))));
+
+            assert_eq!(read(&conn, "test-a").await, vec![json!(0), json!(1), json!(2)]);
+            assert_eq!(conn.read_last::<_, Value>("test-a").await.unwrap().unwrap().payload, json!(2));
+        })
+    }
+
+    #[test]
+    fn categories_are_ordered_by_position() {
+        block_on(async {
+            let conn = MemoryConnection::new();
+            write(&conn, "test-b", 0).await;
+            write(&conn, "other-a", 0).await;
+            write(&conn, "test-a", 0).await;
+            write(&conn, "test-b", 1).await;
+
+            let category = conn.read_category::<_, Value>("test").await.unwrap();
+            let streams = category.into_iter().map(|event| event.stream_id).collect::<Vec<_>>();
+            assert_eq!(streams, vec!["test-b", "test-a", "test-b"]);
+
+            let mut events = conn.subscribe_category::<_, Value>("test", Some(0)).await.unwrap();
+            assert_eq!(next(&mut events).await, (2, "test-a".to_string(), json!(0)));
+            assert_eq!(next(&mut events).await, (3, "test-b".to_string(), json!(1)));
+            write(&conn, "other-a", 1).await;
+            write(&conn, "test-c", 0).await;
+            assert_eq!(next(&mut events).await, (5, "test-c".to_string(), json!(0)));
+        })
+    }
+
+    #[test]
+    fn subscribe_catches_up_then_follows() {
+        block_on(async {
+            let conn = MemoryConnection::new();
+            write(&conn, "test-a", 0).await;
+            write(&conn, "test-a", 1).await;
+
+            let mut events = conn.subscribe::<_, Value>("test-a", Some(0)).await.unwrap();
+            assert_eq!(next(&mut events).await, (1, "test-a".to_string(), json!(1)));
+
+            write(&conn, "test-b", 0).await;
+            write(&conn, "test-a", 2).await;
+            assert_eq!(next(&mut events).await, (2, "test-a".to_string(), json!(2)));
+        })
+    }
+
+    #[test]
+    fn persistent_subscriptions_continue_after_acks() {
+        block_on(async {
+            let conn = MemoryConnection::new();
+            for payload in 0..3 {
+                write(&conn, "test-a", payload).await;
+            }
+
+            let mut subscription = conn.subscribe_persistent::<_, _, Value>("test-a", "group").await.unwrap();
+            let first = subscription.next().await.unwrap().unwrap();
+            subscription.ack(&first).await.unwrap();
+            // Received, but never acknowledged
+            subscription.next().await.unwrap().unwrap();
+            drop(subscription);
+
+            let mut subscription = conn.subscribe_persistent::<_, _, Value>("test-a", "group").await.unwrap();
+            assert_eq!(subscription.next().await.unwrap().unwrap().position, 1);
+
+            let mut other = conn.subscribe_persistent::<_, _, Value>("test-a", "other_group").await.unwrap();
+            assert_eq!(other.next().await.unwrap().unwrap().position, 0);
+        })
+    }
+
+    #[test]
+    fn dumps_and_loads_events_and_groups() {
+        block_on(async {
+            let path = path();
+            let conn = MemoryConnection::load(&path).unwrap();
+            for payload in 0..3 {
+                write(&conn, "test-a", payload).await;
+            }
+            write(&conn, "test-b", 0).await;
+
+            let mut subscription = conn.subscribe_persistent::<_, _, Value>("test-a", "group").await.unwrap();
+            let first = subscription.next().await.unwrap().unwrap();
+            subscription.ack(&first).await.unwrap();
+            drop(subscription);
+            conn.dump().unwrap();
+            drop(conn);
+
+            let conn = MemoryConnection::load(&path).unwrap();
+            assert_eq!(read(&conn, "test-a").await, vec![json!(0), json!(1), json!(2)]);
+            write(&conn, "test-a", 3).await;
+            let category = conn.read_category::<_, Value>("test").await.unwrap();
+            assert_eq!(category.last().map(|event| event.payload.clone()), Some(json!(3)));
+
+            let mut subscription = conn.subscribe_persistent::<_, _, Value>("test-a", "group").await.unwrap();
+            let next = subscription.next().await.unwrap().unwrap();
+            assert_eq!((next.position, next.event.payload), (1, json!(1)));
+            fs::remove_file(path).unwrap();
+        })
+    }
+
+    #[test]
+    fn keeps_the_latest_snapshot() {
+        block_on(async {
+            let path = path();
+            let conn = MemoryConnection::load(&path).unwrap();
+            assert_eq!(conn.read_snapshot("test-a").await.unwrap(), None);
+            conn.write_snapshot("test-a", json!(1)).await.unwrap();
+            conn.write_snapshot("test-a", json!(2)).await.unwrap();
+            conn.dump().unwrap();
+            drop(conn);
+
+            let conn = MemoryConnection::load(&path).unwrap();
+            assert_eq!(conn.read_snapshot("test-a").await.unwrap(), Some(json!(2)));
+            assert!(conn.read_category::<_, Value>("test").await.unwrap().is_empty());
+            fs::remove_file(path).unwrap();
+        })
+    }
+}
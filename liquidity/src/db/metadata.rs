@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use crate::context::RequestInfo;
+use crate::db::Versioned;
+
+/// Information about who wrote an event and why, stored alongside every event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventMetadata {
+    /// The id of the user who caused the event, or None if the system caused it
+    pub actor_id: Option<String>,
+    /// The time the event was written at
+    pub timestamp: DateTime<Utc>,
+    /// The id of the request that caused the event
+    pub request_id: Option<String>,
+    /// The id the client gave that request, which unlike `request_id` isn't guaranteed to be unique
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_request_id: Option<String>,
+    /// The IP address of the client that made the request
+    pub client_ip: Option<String>,
+    /// The id shared by every event caused by the same operation, even across requests
+    pub correlation_id: Option<String>,
+    /// The id of the message that directly caused the request the event was written for, if there was one
+    pub causation_id: Option<String>,
+    /// The schema version of the payload. None for events written before schema versions were stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>
+}
+
+impl EventMetadata {
+    /// Metadata for events written on behalf of a request
+    ///
+    /// # Arguments
+    ///
+    /// * `actor_id` - The id of the user who made the request, if they're logged in
+    /// * `request` - The request the events are written for
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use liquidity::context::RequestInfo;
+    /// use liquidity::db::EventMetadata;
+    ///
+    /// let request = RequestInfo::new("request-1", None, Some("127.0.0.1".to_string()));
+    /// let metadata = EventMetadata::for_request(Some("alice".to_string()), &request);
+    ///
+    /// assert_eq!(metadata.actor_id, Some("alice".to_string()));
+    /// assert_eq!(metadata.correlation_id, Some("request-1".to_string()));
+    /// assert_eq!(metadata.causation_id, None);
+    /// ```
+    pub fn for_request(actor_id: Option<String>, request: &RequestInfo) -> Self {
+        EventMetadata {
+            actor_id,
+            timestamp: Utc::now(),
+            request_id: Some(request.request_id.clone()),
+            client_request_id: request.client_request_id.clone(),
+            client_ip: request.client_ip.clone(),
+            correlation_id: Some(request.correlation_id.clone()),
+            causation_id: request.causation_id.clone(),
+            schema_version: None
+        }
+    }
+
+    /// Metadata for events the system writes on its own, like migrations
+    pub fn system() -> Self {
+        EventMetadata {
+            actor_id: None,
+            timestamp: Utc::now(),
+            request_id: None,
+            client_request_id: None,
+            client_ip: None,
+            correlation_id: None,
+            causation_id: None,
+            schema_version: None
+        }
+    }
+
+    /// The same metadata, tagged with the current schema version of a payload type
+    pub fn versioned<P: Versioned>(&self) -> Self {
+        EventMetadata { schema_version: Some(P::SCHEMA_VERSION), ..self.clone() }
+    }
+}
@@ -2,9 +2,35 @@ pub use eventstore::OperationError;
 use std::fmt;
 use std::error::Error;
 
+mod aggregate;
+mod backend;
 mod connection;
+mod file;
+mod local;
+mod memory;
+mod metadata;
+mod snapshot;
+mod sql;
+mod subscription;
+mod upcast;
+mod worker;
 
-pub use connection::{DbConnection, EventType};
+pub use aggregate::{Aggregate, DomainEvent, fold_event, UnknownEvents};
+pub use backend::Backend;
+pub use connection::{DbConnection, EventType, ExpectedVersion, NewEvent, StoredEvent};
+pub use file::{FileConnection, FileOptions, SyncPolicy};
+pub use memory::MemoryConnection;
+pub use metadata::EventMetadata;
+pub use sql::{migrate, Dialect, Migration, MIGRATIONS, SqlClient, SqlConnection, SqlValue};
+#[cfg(feature = "postgres")]
+pub use sql::PostgresClient;
+#[cfg(feature = "sqlite")]
+pub use sql::SqliteClient;
+pub use snapshot::{Snapshot, SnapshotPolicy, snapshot_stream, SNAPSHOT_EVENT_TYPE};
+pub use subscription::{Acknowledge, EventStream, PersistentSubscription, SubscriptionEvent};
+pub use upcast::{decode, Upcaster, Upcasters, Versioned, INITIAL_SCHEMA_VERSION};
+pub(crate) use local::write_atomically;
+pub(crate) use worker::Workers;
 
 pub trait ESResultExt<T> {
     fn map_not_found(self) -> Result<Option<T>, OperationError>;
@@ -49,7 +75,19 @@ pub enum DatabaseError {
     DatabaseError(OperationError),
     AccessDenied(String),
     SerializationError(serde_json::Error),
-    NotFound
+    NotFound,
+    /// The stream with this id was written to since it was read
+    WrongExpectedVersion(String),
+    /// A stored payload has a schema version that can't be migrated to the current one
+    UnsupportedSchemaVersion(u32),
+    /// A stored event has a type the code reading it doesn't know
+    UnknownEventType(String),
+    /// A persistent subscription couldn't be created or connected to
+    SubscriptionFailed(String),
+    /// Reading or writing a file failed
+    IoError(std::io::Error),
+    /// A SQL database returned an error
+    SqlError(String)
 }
 
 impl fmt::Display for DatabaseError {
@@ -59,7 +97,13 @@ impl fmt::Display for DatabaseError {
             DatabaseError::DatabaseError(e) => write!(f, "{:?}", e),
             DatabaseError::AccessDenied(s) => write!(f, "Access Denied: {}", s),
             DatabaseError::SerializationError(e) => write!(f, "{:?}", e),
-            DatabaseError::NotFound => write!(f, "object doesn't exist")
+            DatabaseError::NotFound => write!(f, "object doesn't exist"),
+            DatabaseError::WrongExpectedVersion(s) => write!(f, "{} was changed concurrently, please try again", s),
+            DatabaseError::UnsupportedSchemaVersion(v) => write!(f, "unsupported event schema version {}", v),
+            DatabaseError::UnknownEventType(s) => write!(f, "unknown event type {}", s),
+            DatabaseError::SubscriptionFailed(s) => write!(f, "subscription failed: {}", s),
+            DatabaseError::IoError(e) => write!(f, "{}", e),
+            DatabaseError::SqlError(s) => write!(f, "sql error: {}", s)
         }
     }
 }
@@ -73,6 +117,7 @@ impl From<OperationError> for DatabaseError {
             OperationError::AuthenticationRequired => DatabaseError::AccessDenied("Not authenticated".to_string()),
             OperationError::StreamDeleted(_) | OperationError::StreamNotFound(_) => DatabaseError::NotFound,
             OperationError::Aborted | OperationError::ConnectionHasDropped => DatabaseError::ConnectionFailed,
+            OperationError::WrongExpectedVersion(s, _) => DatabaseError::WrongExpectedVersion(s),
             _ => DatabaseError::DatabaseError(e)
         }
     }
@@ -83,3 +128,9 @@ impl From<serde_json::Error> for DatabaseError {
         DatabaseError::SerializationError(e)
     }
 }
+
+impl From<std::io::Error> for DatabaseError {
+    fn from(e: std::io::Error) -> Self {
+        DatabaseError::IoError(e)
+    }
+}
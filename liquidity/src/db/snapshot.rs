@@ -0,0 +1,72 @@
+use serde::{Serialize, Deserialize};
+
+/// The event type snapshots are stored with
+pub const SNAPSHOT_EVENT_TYPE: &str = "snapshot";
+
+/// How often to snapshot a stream, and which snapshots are still valid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapshotPolicy {
+    /// The number of events to fold since the last snapshot before a new one is stored. 0 disables snapshots
+    pub interval: u64,
+    /// The version of the shape of the snapshotted object. Snapshots with a different schema version are
+    /// ignored, so bumping it invalidates every existing snapshot after a schema change.
+    pub schema_version: u32
+}
+
+impl SnapshotPolicy {
+    pub fn new(interval: u64, schema_version: u32) -> Self {
+        SnapshotPolicy { interval, schema_version }
+    }
+
+    /// Whether a new snapshot should be stored
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot_version` - The version of the snapshot the fold started from, if there was one
+    /// * `version` - The number of the last event that was folded
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use liquidity::db::SnapshotPolicy;
+    ///
+    /// let policy = SnapshotPolicy::new(10, 1);
+    ///
+    /// assert!(!policy.is_due(None, 8));
+    /// assert!(policy.is_due(None, 9));
+    /// assert!(!policy.is_due(Some(9), 18));
+    /// assert!(policy.is_due(Some(9), 19));
+    /// ```
+    pub fn is_due(&self, snapshot_version: Option<u64>, version: u64) -> bool {
+        if self.interval == 0 { return false }
+
+        let folded = match snapshot_version {
+            Some(snapshot_version) => version.saturating_sub(snapshot_version),
+            None => version + 1
+        };
+        folded >= self.interval
+    }
+}
+
+/// The folded state of a stream up to and including an event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot<T> {
+    /// The number of the last event folded into the state
+    pub version: u64,
+    /// The schema version of the policy the snapshot was taken with
+    pub schema_version: u32,
+    pub state: T
+}
+
+/// The id of the stream the snapshots of a stream are stored in
+///
+/// # Example
+///
+/// ```
+/// use liquidity::db::snapshot_stream;
+///
+/// assert_eq!(snapshot_stream("election-1"), "snapshot-election-1".to_string());
+/// ```
+pub fn snapshot_stream(stream: &str) -> String {
+    format!("snapshot-{}", stream)
+}
@@ -0,0 +1,103 @@
+use crate::db::DatabaseError;
+
+/// The SQL dialects the SQL backend can talk to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Sqlite,
+    Postgres
+}
+
+impl Dialect {
+    /// The statement starting a write transaction. SQLite takes the write lock right away, so two writers can't both
+    /// read the version of a stream before either of them writes.
+    pub(crate) fn begin(self) -> &'static str {
+        match self {
+            Dialect::Sqlite => "BEGIN IMMEDIATE",
+            Dialect::Postgres => "BEGIN"
+        }
+    }
+
+    /// The statement that makes the rest of a transaction wait for every other transaction taking the same lock, in
+    /// any process. SQLite doesn't need one, `begin` already locks the whole database.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Which lock to take
+    pub(crate) fn lock(self, key: i64) -> Option<String> {
+        match self {
+            Dialect::Sqlite => None,
+            Dialect::Postgres => Some(format!("SELECT pg_advisory_xact_lock({})", key))
+        }
+    }
+
+    /// A text parameter stored in the `created_at` column
+    pub(crate) fn timestamp(self, parameter: &str) -> String {
+        match self {
+            Dialect::Sqlite => parameter.to_string(),
+            Dialect::Postgres => format!("{}::text::timestamptz", parameter)
+        }
+    }
+}
+
+/// A parameter or column value
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Null,
+    Int(i64),
+    Text(String)
+}
+
+impl SqlValue {
+    pub fn as_int(&self) -> Result<Option<i64>, DatabaseError> {
+        match self {
+            SqlValue::Null => Ok(None),
+            SqlValue::Int(value) => Ok(Some(*value)),
+            SqlValue::Text(_) => Err(DatabaseError::SqlError(format!("expected an integer, got {:?}", self)))
+        }
+    }
+
+    pub fn into_text(self) -> Result<String, DatabaseError> {
+        match self {
+            SqlValue::Text(value) => Ok(value),
+            _ => Err(DatabaseError::SqlError(format!("expected text, got {:?}", self)))
+        }
+    }
+}
+
+impl From<i64> for SqlValue {
+    fn from(value: i64) -> Self {
+        SqlValue::Int(value)
+    }
+}
+
+impl From<&str> for SqlValue {
+    fn from(value: &str) -> Self {
+        SqlValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for SqlValue {
+    fn from(value: String) -> Self {
+        SqlValue::Text(value)
+    }
+}
+
+/// A connection to a SQL database. Statements use numbered parameters like `$1`, which both SQLite and Postgres
+/// understand, and only need integer and text values.
+pub trait SqlClient: Send {
+    fn dialect(&self) -> Dialect;
+
+    /// Run statements without parameters, like a migration
+    fn batch(&mut self, sql: &str) -> Result<(), DatabaseError>;
+
+    /// Run a statement that doesn't return rows
+    ///
+    /// # Returns
+    ///
+    /// The number of rows the statement changed. Fails with `DatabaseError::WrongExpectedVersion` if it violated a
+    /// unique constraint.
+    fn execute(&mut self, sql: &str, params: &[SqlValue]) -> Result<u64, DatabaseError>;
+
+    /// Run a statement that returns rows, like `execute`
+    fn query(&mut self, sql: &str, params: &[SqlValue]) -> Result<Vec<Vec<SqlValue>>, DatabaseError>;
+}
@@ -0,0 +1,103 @@
+use chrono::Utc;
+use crate::db::DatabaseError;
+use crate::db::sql::{rollback, Dialect, SqlClient};
+
+/// A change to the schema of the SQL backend. Migrations are applied in order, and each one only once.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    sqlite: &'static str,
+    postgres: &'static str
+}
+
+impl Migration {
+    pub fn sql(&self, dialect: Dialect) -> &'static str {
+        match dialect {
+            Dialect::Sqlite => self.sqlite,
+            Dialect::Postgres => self.postgres
+        }
+    }
+}
+
+/// Every migration, oldest first. Never change one that was released, add a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_events",
+        sqlite: include_str!("../../../migrations/sqlite/0001_create_events.sql"),
+        postgres: include_str!("../../../migrations/postgres/0001_create_events.sql")
+    },
+    Migration {
+        version: 2,
+        name: "create_subscription_groups",
+        sqlite: include_str!("../../../migrations/sqlite/0002_create_subscription_groups.sql"),
+        postgres: include_str!("../../../migrations/postgres/0002_create_subscription_groups.sql")
+    },
+    Migration {
+        version: 3,
+        name: "create_snapshots",
+        sqlite: include_str!("../../../migrations/sqlite/0003_create_snapshots.sql"),
+        postgres: include_str!("../../../migrations/postgres/0003_create_snapshots.sql")
+    }
+];
+
+/// The advisory lock migrations hold, so processes starting at the same time don't apply the same migration twice
+const MIGRATION_LOCK: i64 = 0x6c69_7175_6964_0001;
+
+/// Apply the migrations that weren't applied to a database yet. Every migration is applied in a transaction that
+/// locks out other processes migrating the same database.
+///
+/// # Returns
+///
+/// The number of migrations that were applied
+pub fn migrate(client: &mut dyn SqlClient) -> Result<usize, DatabaseError> {
+    let mut applied = 0;
+    while apply_next(client)? {
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+/// Apply the oldest migration that wasn't applied yet
+///
+/// # Returns
+///
+/// Whether there was one
+fn apply_next(client: &mut dyn SqlClient) -> Result<bool, DatabaseError> {
+    let dialect = client.dialect();
+    client.batch(dialect.begin())?;
+
+    let applied = next_migration(client)
+        .and_then(|migration| match migration {
+            None => Ok(false),
+            Some(migration) => {
+                info!("Applying migration {} {}", migration.version, migration.name);
+                client.batch(migration.sql(dialect)).and_then(|_| client.execute(
+                    "INSERT INTO schema_migrations (version, name, applied_at) VALUES ($1, $2, $3)",
+                    &[migration.version.into(), migration.name.into(), Utc::now().to_rfc3339().into()]
+                )).map(|_| true)
+            }
+        })
+        .and_then(|applied| client.batch("COMMIT").map(|_| applied));
+    if applied.is_err() {
+        rollback(client);
+    }
+    applied
+}
+
+/// Lock the migrations and find the next one. The applied migrations are only read once the lock is held, another
+/// process may have applied some while this one waited.
+fn next_migration(client: &mut dyn SqlClient) -> Result<Option<&'static Migration>, DatabaseError> {
+    if let Some(lock) = client.dialect().lock(MIGRATION_LOCK) {
+        client.batch(&lock)?;
+    }
+    client.batch("CREATE TABLE IF NOT EXISTS schema_migrations (version BIGINT PRIMARY KEY, name TEXT NOT NULL, applied_at TEXT NOT NULL)")?;
+
+    let rows = client.query("SELECT MAX(version) FROM schema_migrations", &[])?;
+    let current = match rows.first().and_then(|row| row.first()) {
+        Some(version) => version.as_int()?.unwrap_or(0),
+        None => 0
+    };
+
+    Ok(MIGRATIONS.iter().find(|migration| migration.version > current))
+}
@@ -0,0 +1,582 @@
+//! A `DbConnection` storing events in a single `events` table of a SQL database
+//!
+//! Every event is a row with its stream and its version in the stream. A unique constraint on the two makes
+//! concurrent writes to the same stream fail instead of both being stored, even across processes.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use crate::db::{
+    Acknowledge, Aggregate, DatabaseError, DbConnection, EventMetadata, EventStream, EventType, ExpectedVersion, NewEvent,
+    PersistentSubscription, StoredEvent, Versioned
+};
+use crate::db::local::{self, LocalEvent, Subscribers};
+use crate::db::worker::Workers;
+
+mod client;
+mod migrations;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use self::client::{Dialect, SqlClient, SqlValue};
+pub use self::migrations::{migrate, Migration, MIGRATIONS};
+#[cfg(feature = "postgres")]
+pub use self::postgres::PostgresClient;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteClient;
+
+const EVENT_COLUMNS: &str = "position, stream, version, type, payload, metadata";
+
+/// The advisory lock every write holds until it commits, so positions are handed out in the order writes commit
+const WRITE_LOCK: i64 = 0x6c69_7175_6964_0002;
+
+/// Roll back the open transaction after a failed statement or commit. A transaction left open would make every later
+/// `BEGIN` on the connection fail, while a failed rollback is only logged so the error that caused it isn't lost.
+fn rollback(client: &mut dyn SqlClient) {
+    if let Err(e) = client.batch("ROLLBACK") {
+        warn!("Failed to roll back: {:?}", e)
+    }
+}
+
+/// Read an event from the columns in `EVENT_COLUMNS`
+fn parse_event(columns: Vec<SqlValue>) -> Result<LocalEvent, DatabaseError> {
+    let mut columns = columns.into_iter();
+    let mut next = || columns.next().ok_or_else(|| DatabaseError::SqlError("missing column".to_string()));
+
+    let position = next()?.as_int()?.unwrap_or(0) as u64;
+    let stream_id = next()?.into_text()?;
+    let number = next()?.as_int()?.unwrap_or(0) as u64;
+    let event_type = next()?.into_text()?;
+    let payload = serde_json::from_str(&next()?.into_text()?)?;
+    let metadata = serde_json::from_str(&next()?.into_text()?)?;
+
+    Ok(LocalEvent { position, stream_id, number, event: NewEvent { event_type, payload, metadata } })
+}
+
+/// The connection every write goes through, and the subscriptions notified of them
+struct Writer {
+    client: Box<dyn SqlClient>,
+    subscribers: Subscribers
+}
+
+/// Stores events in a SQL database. Every connection runs on a thread of its own, so queries never block the async
+/// executor.
+///
+/// Writes hold a lock until they commit, so positions in the events table grow in the order events become visible
+/// and a subscription catching up from a position never skips an event, whichever process wrote it. New events are
+/// only pushed to the subscriptions of the `SqlConnection` that wrote them though. Running several processes that
+/// subscribe against one database isn't supported, a process only sees the events the others wrote when it
+/// subscribes again.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "sqlite")]
+/// use liquidity::db::{DbConnection, EventMetadata, ExpectedVersion, SqlConnection, SqliteClient};
+/// # #[cfg(feature = "sqlite")]
+/// use serde_json::{json, Value};
+/// # #[cfg(feature = "sqlite")]
+/// # futures::executor::block_on(async {
+///
+/// let conn = SqlConnection::new(SqliteClient::in_memory().unwrap()).unwrap();
+/// conn.write_event("test-1", "test", json!({ "name": "test" }), &EventMetadata::system(), ExpectedVersion::NoStream).await.unwrap();
+///
+/// let events = conn.read_all::<_, Value>("test-1").await.unwrap();
+/// assert_eq!(events[0].payload, json!({ "name": "test" }));
+///
+/// let conflict = conn.write_event("test-1", "test", json!({}), &EventMetadata::system(), ExpectedVersion::NoStream).await;
+/// assert!(conflict.is_err());
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct SqlConnection {
+    writer: Arc<Workers<Writer>>,
+    /// The connections reads go through, None if they go through the writer
+    readers: Option<Arc<Workers<Box<dyn SqlClient>>>>
+}
+
+impl SqlConnection {
+    /// Apply pending migrations to a database and connect to it with a single connection
+    pub fn new<C: SqlClient + 'static>(client: C) -> Result<Self, DatabaseError> {
+        SqlConnection::pool(vec![client])
+    }
+
+    /// Apply pending migrations to a database and connect to it with several connections. The first one writes, the
+    /// others read in parallel. Don't pool connections to an in-memory SQLite database, each of them has a database of
+    /// its own.
+    pub fn pool<C: SqlClient + 'static>(clients: Vec<C>) -> Result<Self, DatabaseError> {
+        let mut clients = clients.into_iter().map(|client| Box::new(client) as Box<dyn SqlClient>);
+        let mut client = clients.next().ok_or(DatabaseError::ConnectionFailed)?;
+        migrate(client.as_mut())?;
+
+        let readers = clients.collect::<Vec<_>>();
+        let readers = match readers.is_empty() {
+            true => None,
+            false => Some(Arc::new(Workers::new("sql-reader", readers)?))
+        };
+        let writer = Writer { client, subscribers: Subscribers::default() };
+
+        Ok(SqlConnection { writer: Arc::new(Workers::new("sql-writer", vec![writer])?), readers })
+    }
+
+    /// Run a job on the next free connection for reads
+    async fn read<R, F>(&self, job: F) -> Result<R, DatabaseError>
+        where R: Send + 'static, F: FnOnce(&mut dyn SqlClient) -> Result<R, DatabaseError> + Send + 'static {
+
+        match &self.readers {
+            Some(readers) => readers.run(move |client| job(client.as_mut())).await,
+            None => self.writer.run(move |writer| job(writer.client.as_mut())).await
+        }
+    }
+
+    fn query(client: &mut dyn SqlClient, sql: &str, params: &[SqlValue]) -> Result<Vec<LocalEvent>, DatabaseError> {
+        client.query(sql, params)?.into_iter().map(parse_event).collect()
+    }
+
+    /// Read the events of a stream, starting at the event with the version `from`
+    fn read_stream(client: &mut dyn SqlClient, stream: &str, from: u64) -> Result<Vec<LocalEvent>, DatabaseError> {
+        let sql = format!("SELECT {} FROM events WHERE stream = $1 AND version >= $2 ORDER BY version", EVENT_COLUMNS);
+        SqlConnection::query(client, &sql, &[stream.into(), (from as i64).into()])
+    }
+
+    /// Read the events of every stream whose id starts with a prefix after a position, in the order they were written
+    fn read_prefix(client: &mut dyn SqlClient, prefix: &str, after: Option<u64>) -> Result<Vec<LocalEvent>, DatabaseError> {
+        // LIKE treats some characters in the prefix as wildcards, and isn't case sensitive in SQLite
+        let sql = format!(
+            "SELECT {} FROM events WHERE substr(stream, 1, length(CAST($1 AS TEXT))) = CAST($1 AS TEXT) AND position > $2 ORDER BY position",
+            EVENT_COLUMNS
+        );
+        SqlConnection::query(client, &sql, &[prefix.into(), (after.unwrap_or(0) as i64).into()])
+    }
+
+    /// Append events to a stream in a transaction
+    ///
+    /// # Returns
+    ///
+    /// The stored events
+    fn append(client: &mut dyn SqlClient, stream: &str, events: Vec<NewEvent>, expected_version: ExpectedVersion) -> Result<Vec<LocalEvent>, DatabaseError> {
+        // Writes that don't wait for each other could commit a later position before an earlier one
+        if let Some(lock) = client.dialect().lock(WRITE_LOCK) {
+            client.batch(&lock)?;
+        }
+
+        let rows = client.query("SELECT MAX(version) FROM events WHERE stream = $1", &[stream.into()])?;
+        let version = match rows.first().and_then(|row| row.first()) {
+            Some(version) => version.as_int()?,
+            None => None
+        };
+
+        if !local::version_matches(version.map(|version| version as u64), expected_version) {
+            return Err(DatabaseError::WrongExpectedVersion(stream.to_string()))
+        }
+
+        let sql = format!(
+            "INSERT INTO events (stream, version, type, payload, metadata, created_at) VALUES ($1, $2, $3, $4, $5, {}) RETURNING position",
+            client.dialect().timestamp("$6")
+        );
+        let first_version = version.map_or(0, |version| version + 1);
+        let mut stored = Vec::new();
+        for (index, event) in events.into_iter().enumerate() {
+            let version = first_version + index as i64;
+            let params = [
+                stream.into(),
+                version.into(),
+                event.event_type.as_str().into(),
+                serde_json::to_string(&event.payload)?.into(),
+                serde_json::to_string(&event.metadata)?.into(),
+                event.metadata.timestamp.to_rfc3339().into()
+            ];
+            let rows = client.query(&sql, &params)?;
+            let position = match rows.first().and_then(|row| row.first()) {
+                Some(position) => position.as_int()?.unwrap_or(0) as u64,
+                None => return Err(DatabaseError::SqlError("no position returned".to_string()))
+            };
+            stored.push(LocalEvent { position, stream_id: stream.to_string(), number: version as u64, event });
+        }
+
+        Ok(stored)
+    }
+
+    async fn fold<T, F>(&self, stream: &str, start: Option<(T, u64)>, include: F) -> Result<Option<(T, u64)>, DatabaseError>
+        where T: Aggregate + Send, F: Fn(&LocalEvent) -> bool + Send {
+
+        let first_event = local::first_event(&start);
+        let stream_id = stream.to_string();
+        let events = self.read(move |client| SqlConnection::read_stream(client, &stream_id, first_event)).await?;
+        local::fold(stream, events, start, include)
+    }
+}
+
+/// Stores the acknowledged position of a consumer group in the `subscription_groups` table
+struct SqlAcks {
+    writer: Arc<Workers<Writer>>,
+    stream: String,
+    group: String
+}
+
+#[async_trait]
+impl Acknowledge for SqlAcks {
+    async fn ack(&mut self, position: u64) -> Result<(), DatabaseError> {
+        let params = [self.stream.as_str().into(), self.group.as_str().into(), (position as i64).into()];
+
+        // Positions only ever move forward, even when acks from different processes race
+        self.writer.run(move |writer| {
+            writer.client.execute(
+                "INSERT INTO subscription_groups (stream, group_name, position) VALUES ($1, $2, $3) \
+                    ON CONFLICT (stream, group_name) DO UPDATE SET position = excluded.position \
+                    WHERE subscription_groups.position < excluded.position",
+                &params
+            )?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl DbConnection for SqlConnection {
+    async fn write_event<S, E, P>(&self, stream: S, event_type: E, payload: P, metadata: &EventMetadata, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, E: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+
+        let event = NewEvent::new(event_type, &payload, metadata)?;
+        self.write_events(stream, vec![event], expected_version).await
+    }
+
+    #[instrument(skip(self, events))]
+    async fn write_events<S>(&self, stream: S, events: Vec<NewEvent>, expected_version: ExpectedVersion) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        let stream = stream.as_ref().to_string();
+        self.writer.run(move |writer| {
+            let client = writer.client.as_mut();
+            let dialect = client.dialect();
+            client.batch(dialect.begin())?;
+
+            let written = SqlConnection::append(client, &stream, events, expected_version)
+                .and_then(|written| client.batch("COMMIT").map(|_| written));
+            let written = match written {
+                Ok(written) => written,
+                Err(e) => {
+                    rollback(client);
+                    // Another process wrote the same version of the stream first
+                    return match e {
+                        DatabaseError::WrongExpectedVersion(_) => Err(DatabaseError::WrongExpectedVersion(stream)),
+                        e => Err(e)
+                    }
+                }
+            };
+
+            writer.subscribers.broadcast(&written);
+            Ok(())
+        }).await
+    }
+
+    #[instrument(skip(self))]
+    async fn create<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug {
+
+        self.write_event(stream, EventType::Create, payload, &metadata.versioned::<P>(), ExpectedVersion::NoStream).await
+    }
+
+    #[instrument(skip(self))]
+    async fn read<S, T>(&self, stream: S) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        Ok(self.fold(stream.as_ref(), None, |_| true).await?.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self, start))]
+    async fn read_from<S, T>(&self, stream: S, start: Option<(T, u64)>) -> Result<Option<(T, u64)>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        self.fold(stream.as_ref(), start, |_| true).await
+    }
+
+    #[instrument(skip(self))]
+    async fn read_snapshot<S>(&self, stream: S) -> Result<Option<Value>, DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        let params = [stream.as_ref().into()];
+        self.read(move |client| {
+            let rows = client.query("SELECT payload FROM snapshots WHERE stream = $1", &params)?;
+            match rows.into_iter().next().and_then(|row| row.into_iter().next()) {
+                Some(payload) => Ok(Some(serde_json::from_str(&payload.into_text()?)?)),
+                None => Ok(None)
+            }
+        }).await
+    }
+
+    /// Snapshots are kept in the `snapshots` table, with a row per stream that each new snapshot replaces
+    #[instrument(skip(self, snapshot))]
+    async fn write_snapshot<S>(&self, stream: S, snapshot: Value) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug {
+
+        let params = [stream.as_ref().into(), serde_json::to_string(&snapshot)?.into()];
+        self.writer.run(move |writer| {
+            writer.client.execute(
+                "INSERT INTO snapshots (stream, payload) VALUES ($1, $2) ON CONFLICT (stream) DO UPDATE SET payload = excluded.payload",
+                &params
+            )?;
+            Ok(())
+        }).await
+    }
+
+    #[instrument(skip(self))]
+    async fn read_at_version<S, T>(&self, stream: S, version: u64) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        Ok(self.fold(stream.as_ref(), None, move |event| event.number <= version).await?.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self))]
+    async fn read_as_of<S, T>(&self, stream: S, as_of: DateTime<Utc>) -> Result<Option<T>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, T: Aggregate + Send + Clone {
+
+        Ok(self.fold(stream.as_ref(), None, move |event| event.event.metadata.timestamp <= as_of).await?.map(|(state, _)| state))
+    }
+
+    #[instrument(skip(self))]
+    async fn read_last<S, P>(&self, stream: S) -> Result<Option<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        let sql = format!("SELECT {} FROM events WHERE stream = $1 ORDER BY version DESC LIMIT 1", EVENT_COLUMNS);
+        let params = [stream.as_ref().into()];
+        let event = self.read(move |client| SqlConnection::query(client, &sql, &params)).await?.pop();
+        event.map(LocalEvent::into_stored).transpose()
+    }
+
+    #[instrument(skip(self))]
+    async fn read_all<S, P>(&self, stream: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        let stream = stream.as_ref().to_string();
+        let events = self.read(move |client| SqlConnection::read_stream(client, &stream, 0)).await?;
+        events.into_iter().map(LocalEvent::into_stored).collect()
+    }
+
+    #[instrument(skip(self))]
+    async fn read_category<S, P>(&self, category: S) -> Result<Vec<StoredEvent<P>>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send {
+
+        let prefix = format!("{}-", category.as_ref());
+        let events = self.read(move |client| SqlConnection::read_prefix(client, &prefix, None)).await?;
+        events.into_iter().map(LocalEvent::into_stored).collect()
+    }
+
+    #[instrument(skip(self))]
+    async fn subscribe<S, P>(&self, stream: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        let stream_id = stream.as_ref().to_string();
+        let (existing, receiver) = self.writer.run({
+            let stream_id = stream_id.clone();
+            move |writer| {
+                let existing = SqlConnection::read_stream(writer.client.as_mut(), &stream_id, from.map_or(0, |from| from + 1))?;
+                // Subscribe on the writer, so no event is written in between
+                Ok((existing, writer.subscribers.subscribe()))
+            }
+        }).await?;
+
+        Ok(local::stream_subscription(stream_id, existing, receiver, from))
+    }
+
+    /// Positions in categories are positions in the events table, so they stay valid when other streams are written to
+    #[instrument(skip(self))]
+    async fn subscribe_category<S, P>(&self, category: S, from: Option<u64>) -> Result<EventStream<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        let prefix = format!("{}-", category.as_ref());
+        let (existing, receiver) = self.writer.run({
+            let prefix = prefix.clone();
+            move |writer| Ok((SqlConnection::read_prefix(writer.client.as_mut(), &prefix, from)?, writer.subscribers.subscribe()))
+        }).await?;
+
+        Ok(local::prefix_subscription(prefix, existing, receiver, from))
+    }
+
+    #[instrument(skip(self))]
+    async fn subscribe_persistent<S, G, P>(&self, stream: S, group: G) -> Result<PersistentSubscription<P>, DatabaseError>
+        where S: AsRef<str> + Send + Debug, G: AsRef<str> + Send + Debug, P: DeserializeOwned + Send + 'static {
+
+        // Members of a group don't share the load here, every subscription gets every event
+        let params = [stream.as_ref().into(), group.as_ref().into()];
+        let from = self.read(move |client| {
+            let rows = client.query("SELECT position FROM subscription_groups WHERE stream = $1 AND group_name = $2", &params)?;
+            match rows.first().and_then(|row| row.first()) {
+                Some(position) => Ok(position.as_int()?.map(|position| position as u64)),
+                None => Ok(None)
+            }
+        }).await?;
+        let acks = SqlAcks { writer: self.writer.clone(), stream: stream.as_ref().to_string(), group: group.as_ref().to_string() };
+        let events = self.subscribe(stream, from).await?;
+
+        Ok(PersistentSubscription::new(events, Box::new(acks)))
+    }
+
+    #[instrument(skip(self))]
+    async fn update<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Versioned + Send + Debug {
+
+        self.write_event(stream, EventType::Update, payload, &metadata.versioned::<P>(), ExpectedVersion::Any).await
+    }
+
+    #[instrument(skip(self))]
+    async fn delete<S, P>(&self, stream: S, payload: P, metadata: &EventMetadata) -> Result<(), DatabaseError>
+        where S: AsRef<str> + Send + Debug, P: Serialize + Send + Debug {
+
+        self.write_event(stream, EventType::Delete, payload, metadata, ExpectedVersion::Any).await
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+    use crate::Uuid;
+    use crate::db::{DatabaseError, DbConnection, EventMetadata, ExpectedVersion, NewEvent};
+    use super::{migrate, SqlClient, SqlConnection, SqlValue, SqliteClient, MIGRATIONS};
+
+    fn database() -> PathBuf {
+        std::env::temp_dir().join(format!("liquidity-{}.db", Uuid::new_v4()))
+    }
+
+    async fn write(conn: &SqlConnection, stream: &str, payload: i64) {
+        conn.write_event(stream, "test", json!(payload), &EventMetadata::system(), ExpectedVersion::Any).await.unwrap();
+    }
+
+    #[test]
+    fn migrates_once() {
+        let path = database();
+        let mut client = SqliteClient::open(&path).unwrap();
+        assert_eq!(migrate(&mut client).unwrap(), MIGRATIONS.len());
+        drop(client);
+
+        let mut client = SqliteClient::open(&path).unwrap();
+        assert_eq!(migrate(&mut client).unwrap(), 0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn checks_expected_versions() {
+        block_on(async {
+            let conn = SqlConnection::new(SqliteClient::in_memory().unwrap()).unwrap();
+            let metadata = EventMetadata::system();
+            let events = vec![
+                NewEvent::new("test", &json!(0), &metadata).unwrap(),
+                NewEvent::new("test", &json!(1), &metadata).unwrap()
+            ];
+
+            conn.write_events("test-a", events.clone(), ExpectedVersion::NoStream).await.unwrap();
+            let stale = conn.write_events("test-a", events, ExpectedVersion::Exact(0)).await;
+            assert!(matches!(stale, Err(DatabaseError::WrongExpectedVersion(_))));
+            conn.write_event("test-a", "test", json!(2), &metadata, ExpectedVersion::Exact(1)).await.unwrap();
+
+            let events = conn.read_all::<_, Value>("test-a").await.unwrap();
+            assert_eq!(events.into_iter().map(|event| event.payload).collect::<Vec<_>>(), vec![json!(0), json!(1), json!(2)]);
+        })
+    }
+
+    #[test]
+    fn only_duplicate_stream_versions_are_version_conflicts() {
+        let mut client = SqliteClient::in_memory().unwrap();
+        migrate(&mut client).unwrap();
+        let insert = "INSERT INTO events (stream, version, type, payload, metadata, created_at) VALUES ($1, $2, 'test', '0', '{}', '')";
+
+        client.execute(insert, &["test-a".into(), 0i64.into()]).unwrap();
+        assert!(matches!(client.execute(insert, &["test-a".into(), 0i64.into()]), Err(DatabaseError::WrongExpectedVersion(_))));
+        assert!(matches!(client.execute(insert, &[SqlValue::Null, 1i64.into()]), Err(DatabaseError::SqlError(_))));
+
+        let group = "INSERT INTO subscription_groups (stream, group_name, position) VALUES ('test-a', 'group', 0)";
+        client.execute(group, &[]).unwrap();
+        assert!(matches!(client.execute(group, &[]), Err(DatabaseError::SqlError(_))));
+    }
+
+    #[test]
+    fn pools_connections() {
+        block_on(async {
+            let path = database();
+            let clients = (0..3).map(|_| SqliteClient::open(&path).unwrap()).collect();
+            let conn = SqlConnection::pool(clients).unwrap();
+            for payload in 0..3 {
+                write(&conn, "test-a", payload).await;
+            }
+
+            // Reads go through the other connections, and see every committed write
+            let events = conn.read_all::<_, Value>("test-a").await.unwrap();
+            assert_eq!(events.into_iter().map(|event| event.payload).collect::<Vec<_>>(), vec![json!(0), json!(1), json!(2)]);
+            drop(conn);
+            fs::remove_file(path).unwrap();
+        })
+    }
+
+    #[test]
+    fn rejects_duplicate_versions() {
+        block_on(async {
+            let path = database();
+            let conn = SqlConnection::new(SqliteClient::open(&path).unwrap()).unwrap();
+            write(&conn, "test-a", 0).await;
+
+            // Another process that read the stream before the write above tries to write the same version
+            let mut other = SqliteClient::open(&path).unwrap();
+            let duplicate = other.execute(
+                "INSERT INTO events (stream, version, type, payload, metadata, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+                &["test-a".into(), 0.into(), "test".into(), "1".into(), "{}".into(), "".into()]
+            );
+            assert!(matches!(duplicate, Err(DatabaseError::WrongExpectedVersion(_))));
+            fs::remove_file(path).unwrap();
+        })
+    }
+
+    #[test]
+    fn subscriptions_use_table_positions() {
+        block_on(async {
+            let path = database();
+            let conn = SqlConnection::new(SqliteClient::open(&path).unwrap()).unwrap();
+            write(&conn, "test-b", 0).await;
+            write(&conn, "other-a", 0).await;
+            write(&conn, "test-a", 0).await;
+
+            let mut events = conn.subscribe_category::<_, Value>("test", Some(1)).await.unwrap();
+            let event = events.next().await.unwrap().unwrap();
+            assert_eq!((event.position, event.event.stream_id), (3, "test-a".to_string()));
+            write(&conn, "test-c", 0).await;
+            let event = events.next().await.unwrap().unwrap();
+            assert_eq!((event.position, event.event.stream_id), (4, "test-c".to_string()));
+
+            let mut subscription = conn.subscribe_persistent::<_, _, Value>("test-b", "group").await.unwrap();
+            let first = subscription.next().await.unwrap().unwrap();
+            subscription.ack(&first).await.unwrap();
+            write(&conn, "test-b", 1).await;
+            drop(subscription);
+            drop(events);
+            drop(conn);
+
+            let conn = SqlConnection::new(SqliteClient::open(&path).unwrap()).unwrap();
+            let mut subscription = conn.subscribe_persistent::<_, _, Value>("test-b", "group").await.unwrap();
+            let next = subscription.next().await.unwrap().unwrap();
+            assert_eq!((next.position, next.event.payload), (1, json!(1)));
+            fs::remove_file(path).unwrap();
+        })
+    }
+
+    #[test]
+    fn keeps_the_latest_snapshot() {
+        block_on(async {
+            let conn = SqlConnection::new(SqliteClient::in_memory().unwrap()).unwrap();
+            assert_eq!(conn.read_snapshot("test-a").await.unwrap(), None);
+            conn.write_snapshot("test-a", json!(1)).await.unwrap();
+            conn.write_snapshot("test-a", json!(2)).await.unwrap();
+            conn.write_snapshot("test-b", json!(3)).await.unwrap();
+
+            assert_eq!(conn.read_snapshot("test-a").await.unwrap(), Some(json!(2)));
+            assert_eq!(conn.read_snapshot("test-b").await.unwrap(), Some(json!(3)));
+            assert!(conn.read_category::<_, Value>("test").await.unwrap().is_empty());
+        })
+    }
+}
@@ -0,0 +1,81 @@
+use std::path::Path;
+use rusqlite::{ffi, params_from_iter, Connection, ToSql};
+use rusqlite::types::{ToSqlOutput, ValueRef};
+use crate::db::DatabaseError;
+use crate::db::sql::{Dialect, SqlClient, SqlValue};
+
+/// A SQLite database, stored in a single file
+pub struct SqliteClient {
+    conn: Connection
+}
+
+impl SqliteClient {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, DatabaseError> {
+        Ok(SqliteClient { conn: Connection::open(path).map_err(to_database_error)? })
+    }
+
+    /// A database that only lives as long as the client, for tests
+    pub fn in_memory() -> Result<Self, DatabaseError> {
+        Ok(SqliteClient { conn: Connection::open_in_memory().map_err(to_database_error)? })
+    }
+}
+
+/// How SQLite reports a second event with the same stream and version, which means the stream was written to concurrently
+const STREAM_VERSION_VIOLATION: &str = "UNIQUE constraint failed: events.stream, events.version";
+
+fn to_database_error(e: rusqlite::Error) -> DatabaseError {
+    match &e {
+        rusqlite::Error::SqliteFailure(error, Some(message))
+            if error.extended_code == ffi::SQLITE_CONSTRAINT_UNIQUE && message == STREAM_VERSION_VIOLATION => {
+            DatabaseError::WrongExpectedVersion(String::new())
+        },
+        _ => DatabaseError::SqlError(e.to_string())
+    }
+}
+
+impl ToSql for SqlValue {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            SqlValue::Null => ToSqlOutput::from(rusqlite::types::Null),
+            SqlValue::Int(value) => ToSqlOutput::from(*value),
+            SqlValue::Text(value) => ToSqlOutput::from(value.as_str())
+        })
+    }
+}
+
+impl SqlClient for SqliteClient {
+    fn dialect(&self) -> Dialect {
+        Dialect::Sqlite
+    }
+
+    fn batch(&mut self, sql: &str) -> Result<(), DatabaseError> {
+        self.conn.execute_batch(sql).map_err(to_database_error)
+    }
+
+    fn execute(&mut self, sql: &str, params: &[SqlValue]) -> Result<u64, DatabaseError> {
+        let changed = self.conn.execute(sql, params_from_iter(params)).map_err(to_database_error)?;
+        Ok(changed as u64)
+    }
+
+    fn query(&mut self, sql: &str, params: &[SqlValue]) -> Result<Vec<Vec<SqlValue>>, DatabaseError> {
+        let mut statement = self.conn.prepare(sql).map_err(to_database_error)?;
+        let columns = statement.column_count();
+        let mut rows = statement.query(params_from_iter(params)).map_err(to_database_error)?;
+
+        let mut result = Vec::new();
+        while let Some(row) = rows.next().map_err(to_database_error)? {
+            let values = (0..columns)
+                .map(|column| match row.get_ref(column)? {
+                    ValueRef::Null => Ok(SqlValue::Null),
+                    ValueRef::Integer(value) => Ok(SqlValue::Int(value)),
+                    ValueRef::Text(value) => Ok(SqlValue::Text(String::from_utf8_lossy(value).into_owned())),
+                    other => Err(rusqlite::Error::InvalidColumnType(column, format!("{:?}", other), other.data_type()))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(to_database_error)?;
+            result.push(values);
+        }
+
+        Ok(result)
+    }
+}
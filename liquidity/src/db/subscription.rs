@@ -0,0 +1,47 @@
+use futures::stream::{BoxStream, Stream};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use crate::db::{DatabaseError, StoredEvent};
+
+/// An event delivered by a subscription
+#[derive(Debug, Clone)]
+pub struct SubscriptionEvent<P> {
+    /// The position of the event in what was subscribed to. Subscribing from it continues after this event.
+    pub position: u64,
+    pub event: StoredEvent<P>
+}
+
+/// The events of a subscription. The events that were already written come first, then new ones as they're written.
+pub type EventStream<P> = BoxStream<'static, Result<SubscriptionEvent<P>, DatabaseError>>;
+
+/// Records which events a consumer group has processed
+#[async_trait]
+pub trait Acknowledge: Send {
+    async fn ack(&mut self, position: u64) -> Result<(), DatabaseError>;
+}
+
+/// A subscription shared by a consumer group. The database remembers the events the group acknowledged,
+/// so a new subscription of the group continues after them.
+pub struct PersistentSubscription<P> {
+    events: EventStream<P>,
+    acks: Box<dyn Acknowledge>
+}
+
+impl <P> PersistentSubscription<P> {
+    pub fn new(events: EventStream<P>, acks: Box<dyn Acknowledge>) -> Self {
+        PersistentSubscription { events, acks }
+    }
+
+    /// Mark an event as processed by the group
+    pub async fn ack(&mut self, event: &SubscriptionEvent<P>) -> Result<(), DatabaseError> {
+        self.acks.ack(event.position).await
+    }
+}
+
+impl <P> Stream for PersistentSubscription<P> {
+    type Item = Result<SubscriptionEvent<P>, DatabaseError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.as_mut().poll_next(cx)
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use crate::db::{DatabaseError, EventMetadata};
+
+/// The schema version of events stored before schema versions were recorded
+pub const INITIAL_SCHEMA_VERSION: u32 = 1;
+
+/// Migrates a payload from one schema version to the next
+pub type Upcaster = fn(Value) -> Value;
+
+/// An event payload whose stored shape can change over time
+pub trait Versioned {
+    /// The schema version new events of this type are written with
+    const SCHEMA_VERSION: u32;
+
+    /// The upcasters migrating stored payloads of older versions to `SCHEMA_VERSION`
+    fn upcasters() -> Upcasters {
+        Upcasters::default()
+    }
+}
+
+/// A registry of upcasters for a payload type, keyed by the version they migrate from
+#[derive(Default)]
+pub struct Upcasters {
+    steps: HashMap<u32, Upcaster>
+}
+
+impl Upcasters {
+    pub fn new() -> Self {
+        Upcasters::default()
+    }
+
+    /// Register the upcaster migrating payloads from `version` to `version + 1`
+    pub fn register(mut self, version: u32, upcaster: Upcaster) -> Self {
+        self.steps.insert(version, upcaster);
+        self
+    }
+
+    /// Migrate a stored payload to the target version, one version at a time
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The stored payload
+    /// * `version` - The schema version the payload was stored with
+    /// * `target` - The schema version to migrate to
+    ///
+    /// # Returns
+    ///
+    /// The migrated payload, or `DatabaseError::UnsupportedSchemaVersion` if the payload is newer than the target or
+    /// an upcaster is missing
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use liquidity::db::Upcasters;
+    /// use serde_json::{json, Value};
+    ///
+    /// let upcasters = Upcasters::new()
+    ///     .register(1, |mut payload: Value| {
+    ///         payload["title"] = payload["name"].take();
+    ///         payload
+    ///     });
+    ///
+    /// let upcasted = upcasters.upcast(json!({ "name": "test" }), 1, 2).unwrap();
+    ///
+    /// assert_eq!(upcasted, json!({ "name": null, "title": "test" }));
+    /// assert!(upcasters.upcast(json!({}), 2, 3).is_err());
+    /// assert!(upcasters.upcast(json!({}), 3, 2).is_err());
+    /// ```
+    pub fn upcast(&self, payload: Value, version: u32, target: u32) -> Result<Value, DatabaseError> {
+        if version > target { return Err(DatabaseError::UnsupportedSchemaVersion(version)) }
+
+        (version..target).try_fold(payload, |payload, version| {
+            let upcaster = self.steps.get(&version).ok_or(DatabaseError::UnsupportedSchemaVersion(version))?;
+            Ok(upcaster(payload))
+        })
+    }
+}
+
+/// Deserialize a stored payload, upcasting it to the current schema version first
+///
+/// # Arguments
+///
+/// * `payload` - The stored payload
+/// * `metadata` - The metadata stored with the payload, if any. Events without a schema version are treated as
+///   `INITIAL_SCHEMA_VERSION`
+pub fn decode<P: Versioned + DeserializeOwned>(payload: Value, metadata: Option<&EventMetadata>) -> Result<P, DatabaseError> {
+    let version = metadata.and_then(|metadata| metadata.schema_version).unwrap_or(INITIAL_SCHEMA_VERSION);
+    let payload = P::upcasters().upcast(payload, version, P::SCHEMA_VERSION)?;
+    Ok(serde_json::from_value(payload)?)
+}
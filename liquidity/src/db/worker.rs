@@ -0,0 +1,72 @@
+//! Threads the blocking backends do their I/O on, so a slow disk or database never stalls the async executor
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use futures::channel::oneshot;
+use crate::db::DatabaseError;
+
+type Job<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+/// Threads that each own a resource, like an open log or a database connection, and run jobs on it one at a time.
+/// A job goes to whichever thread is free. Dropping the workers finishes the queued jobs and waits for the threads to
+/// exit, so the resources are released once the drop returns.
+pub(crate) struct Workers<T> {
+    sender: Option<mpsc::Sender<Job<T>>>,
+    threads: Vec<JoinHandle<()>>
+}
+
+impl<T: Send + 'static> Workers<T> {
+    /// Start a thread for every resource
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the threads, for debugging
+    pub fn new(name: &str, resources: Vec<T>) -> Result<Self, DatabaseError> {
+        let (sender, receiver) = mpsc::channel::<Job<T>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let threads = resources.into_iter().enumerate()
+            .map(|(index, mut resource)| {
+                let receiver = receiver.clone();
+                thread::Builder::new().name(format!("{}-{}", name, index)).spawn(move || loop {
+                    // The lock is only held while waiting for a job, never while running one
+                    let job = match receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => break
+                    };
+                    job(&mut resource);
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Workers { sender: Some(sender), threads })
+    }
+
+    /// Run a job on the next free thread
+    pub async fn run<R, F>(&self, job: F) -> Result<R, DatabaseError>
+        where R: Send + 'static, F: FnOnce(&mut T) -> Result<R, DatabaseError> + Send + 'static {
+
+        let (result, receiver) = oneshot::channel();
+        let job: Job<T> = Box::new(move |resource| {
+            // The caller may have stopped waiting, which leaves nobody to tell
+            let _ = result.send(job(resource));
+        });
+        self.sender.as_ref()
+            .ok_or(DatabaseError::ConnectionFailed)?
+            .send(job)
+            .map_err(|_| DatabaseError::ConnectionFailed)?;
+
+        // A job that panicked takes its thread down with it
+        receiver.await.map_err(|_| DatabaseError::ConnectionFailed)?
+    }
+}
+
+impl<T> Drop for Workers<T> {
+    fn drop(&mut self) {
+        // Closing the queue ends every thread once it's empty
+        self.sender.take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
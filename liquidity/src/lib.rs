@@ -9,6 +9,7 @@ pub use uuid::Uuid;
 pub mod db;
 pub mod context;
 pub mod permissions;
+pub mod projection;
 
 pub use context::Context;
 
@@ -2,6 +2,9 @@ use super::context::User;
 use std::fmt;
 use std::error::Error;
 
+/// The role every logged in user has
+pub const ALL_ROLE: &str = "@all";
+
 #[derive(Debug, PartialEq)]
 pub enum PermissionError {
     NotLoggedIn,
@@ -42,7 +45,8 @@ impl Error for PermissionError {
 /// // Make a mock user
 /// let user = Some(User {
 ///     id: "".to_string(),
-///     permissions: vec!["view:election".to_string()]
+///     permissions: vec!["view:election".to_string()],
+///     roles: Vec::new()
 /// });
 ///
 /// let valid = permissions::check("view:election", &user);
@@ -61,3 +65,44 @@ pub fn check(key: &str, user: &Option<User>) -> Result<(), PermissionError> {
         Some(true) => Ok(())
     }
 }
+
+/// Check the user's roles against a list of allowed roles
+/// # Arguments
+///
+/// * `allowed_roles` - The roles allowed access. `@all` allows access to every logged in user
+/// * `user` - The user object that holds the roles
+///
+/// # Returns
+///
+/// Ok if the user has one of the roles, PermissionError if the user doesn't have any of them or isn't logged in
+///
+/// # Example
+///
+/// ```
+/// # use liquidity::context::User;
+/// use liquidity::{permissions::{self, PermissionError}};
+///
+/// // Make a mock user
+/// let user = Some(User {
+///     id: "".to_string(),
+///     permissions: Vec::new(),
+///     roles: vec!["moderator".to_string()]
+/// });
+///
+/// let valid = permissions::check_roles(&["moderator".to_string()], &user);
+/// let everyone = permissions::check_roles(&["@all".to_string()], &user);
+/// let invalid = permissions::check_roles(&["admin".to_string()], &user);
+/// let not_logged_in = permissions::check_roles(&["@all".to_string()], &None);
+///
+/// assert_eq!(Ok(()), valid);
+/// assert_eq!(Ok(()), everyone);
+/// assert_eq!(Err(PermissionError::NotAllowed), invalid);
+/// assert_eq!(Err(PermissionError::NotLoggedIn), not_logged_in);
+/// ```
+pub fn check_roles(allowed_roles: &[String], user: &Option<User>) -> Result<(), PermissionError> {
+    let user = user.as_ref().ok_or(PermissionError::NotLoggedIn)?;
+    let allowed = allowed_roles.iter()
+        .any(|role| role == ALL_ROLE || user.roles.contains(role));
+
+    if allowed { Ok(()) } else { Err(PermissionError::NotAllowed) }
+}
@@ -0,0 +1,263 @@
+//! Read models built from event streams
+//!
+//! A `Projection` folds the events of a stream or category into some state. A `Projector` keeps that
+//! state up to date from a subscription, and stores it with the position of the last event it saw in a
+//! `CheckpointStore`, so a restart continues where it left off instead of replaying everything.
+
+use futures::{Stream, StreamExt};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use crate::db::{write_atomically, DatabaseError, DbConnection, EventStream, StoredEvent, SubscriptionEvent, Workers};
+
+/// The events a projection is built from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Stream(String),
+    /// Every stream in a category, like `election` for `election-<id>` streams
+    Category(String)
+}
+
+/// A read model built from events
+pub trait Projection: Send + Sync {
+    type State: Default + Serialize + DeserializeOwned + Send + Sync;
+
+    /// The name the checkpoints of the projection are stored under. Changing it rebuilds the projection from scratch.
+    fn name(&self) -> &str;
+
+    /// The events the projection is built from
+    fn source(&self) -> Source;
+
+    /// Apply an event to the state of the projection
+    fn apply(&self, state: &mut Self::State, event: &StoredEvent<Value>) -> Result<(), DatabaseError>;
+}
+
+/// Where projections store their checkpoints. Checkpoints are stored as JSON, so stores don't need to know the state types.
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    /// Load the last checkpoint of a projection, None if it doesn't have one
+    async fn load(&self, name: &str) -> Result<Option<Value>, DatabaseError>;
+
+    async fn save(&self, name: &str, checkpoint: Value) -> Result<(), DatabaseError>;
+
+    async fn clear(&self, name: &str) -> Result<(), DatabaseError>;
+}
+
+/// Keeps checkpoints in memory, so projections are rebuilt from scratch whenever the process restarts
+#[derive(Default)]
+pub struct MemoryCheckpoints {
+    checkpoints: Mutex<HashMap<String, Value>>
+}
+
+impl MemoryCheckpoints {
+    pub fn new() -> Self {
+        MemoryCheckpoints::default()
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for MemoryCheckpoints {
+    async fn load(&self, name: &str) -> Result<Option<Value>, DatabaseError> {
+        Ok(self.checkpoints.lock().unwrap().get(name).cloned())
+    }
+
+    async fn save(&self, name: &str, checkpoint: Value) -> Result<(), DatabaseError> {
+        self.checkpoints.lock().unwrap().insert(name.to_string(), checkpoint);
+        Ok(())
+    }
+
+    async fn clear(&self, name: &str) -> Result<(), DatabaseError> {
+        self.checkpoints.lock().unwrap().remove(name);
+        Ok(())
+    }
+}
+
+/// Keeps checkpoints in a directory, one JSON file per projection. The files are read and written on a thread of
+/// their own, so a slow disk never stalls the executor.
+pub struct FileCheckpoints {
+    workers: Workers<PathBuf>
+}
+
+impl FileCheckpoints {
+    /// # Arguments
+    ///
+    /// * `directory` - The directory to store the checkpoints in. It's created if it doesn't exist.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Result<Self, DatabaseError> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(FileCheckpoints { workers: Workers::new("checkpoints", vec![directory])? })
+    }
+}
+
+fn checkpoint_path(directory: &Path, name: &str) -> PathBuf {
+    directory.join(format!("{}.json", name))
+}
+
+#[async_trait]
+impl CheckpointStore for FileCheckpoints {
+    async fn load(&self, name: &str) -> Result<Option<Value>, DatabaseError> {
+        let name = name.to_string();
+        self.workers.run(move |directory| match fs::read(checkpoint_path(directory, &name)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into())
+        }).await
+    }
+
+    async fn save(&self, name: &str, checkpoint: Value) -> Result<(), DatabaseError> {
+        let name = name.to_string();
+        let contents = serde_json::to_vec(&checkpoint)?;
+        // A crash never leaves a half written checkpoint behind
+        self.workers.run(move |directory| write_atomically(&checkpoint_path(directory, &name), &contents)).await
+    }
+
+    async fn clear(&self, name: &str) -> Result<(), DatabaseError> {
+        let name = name.to_string();
+        self.workers.run(move |directory| match fs::remove_file(checkpoint_path(directory, &name)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(())
+        }).await
+    }
+}
+
+/// The state of a projection with the position of the last event applied to it
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<S> {
+    position: Option<u64>,
+    state: S
+}
+
+/// Keeps a projection up to date
+pub struct Projector<P: Projection> {
+    projection: P,
+    store: Arc<dyn CheckpointStore>,
+    checkpoint_interval: u64,
+    current: RwLock<Checkpoint<P::State>>,
+    /// The number of events applied since the last checkpoint was saved
+    unsaved: Mutex<u64>
+}
+
+impl <P: Projection> Projector<P> {
+    /// Create a projector with an empty state. Call `load` to continue from the last checkpoint.
+    pub fn new(projection: P, store: Arc<dyn CheckpointStore>) -> Self {
+        Projector {
+            projection,
+            store,
+            checkpoint_interval: 1,
+            current: RwLock::new(Checkpoint { position: None, state: P::State::default() }),
+            unsaved: Mutex::new(0)
+        }
+    }
+
+    /// Save a checkpoint only every `interval` events instead of after every event. Events after the last
+    /// checkpoint are applied again after a restart.
+    pub fn with_checkpoint_interval(mut self, interval: u64) -> Self {
+        self.checkpoint_interval = interval.max(1);
+        self
+    }
+
+    /// Continue from the last checkpoint. A checkpoint that doesn't deserialize anymore is ignored,
+    /// so the projection is rebuilt after its state changed shape.
+    pub async fn load(&self) -> Result<(), DatabaseError> {
+        let checkpoint = self.store.load(self.projection.name()).await?
+            .and_then(|checkpoint| serde_json::from_value::<Checkpoint<P::State>>(checkpoint).ok());
+
+        if let Some(checkpoint) = checkpoint {
+            *self.current.write().unwrap() = checkpoint;
+        }
+        Ok(())
+    }
+
+    /// The position of the last event applied to the projection
+    pub fn position(&self) -> Option<u64> {
+        self.current.read().unwrap().position
+    }
+
+    /// Query the state of the projection
+    pub fn read<R, F: FnOnce(&P::State) -> R>(&self, query: F) -> R {
+        query(&self.current.read().unwrap().state)
+    }
+
+    /// Subscribe to the source of the projection, after the last event that was applied to it
+    pub async fn subscribe<C: DbConnection>(&self, conn: &C) -> Result<EventStream<Value>, DatabaseError> {
+        match self.projection.source() {
+            Source::Stream(stream) => conn.subscribe(stream, self.position()).await,
+            Source::Category(category) => conn.subscribe_category(category, self.position()).await
+        }
+    }
+
+    /// Apply events until the stream ends, saving a checkpoint at the end
+    pub async fn run<E>(&self, mut events: E) -> Result<(), DatabaseError>
+        where E: Stream<Item = Result<SubscriptionEvent<Value>, DatabaseError>> + Unpin {
+
+        while let Some(event) = events.next().await {
+            self.process(event?).await?;
+        }
+        self.save().await
+    }
+
+    /// Apply a single event. Events at or before the current position were already applied, so they're ignored.
+    pub async fn process(&self, event: SubscriptionEvent<Value>) -> Result<(), DatabaseError> {
+        {
+            let mut current = self.current.write().unwrap();
+            if matches!(current.position, Some(position) if event.position <= position) { return Ok(()) }
+
+            self.projection.apply(&mut current.state, &event.event)?;
+            current.position = Some(event.position);
+        }
+
+        let due = {
+            let mut unsaved = self.unsaved.lock().unwrap();
+            *unsaved += 1;
+            *unsaved >= self.checkpoint_interval
+        };
+        if due { self.save().await?; }
+        Ok(())
+    }
+
+    /// Save a checkpoint of the current state
+    pub async fn save(&self) -> Result<(), DatabaseError> {
+        let checkpoint = serde_json::to_value(&*self.current.read().unwrap())?;
+        self.store.save(self.projection.name(), checkpoint).await?;
+        *self.unsaved.lock().unwrap() = 0;
+        Ok(())
+    }
+
+    /// Throw away the state and checkpoint of the projection, so it's rebuilt from the first event
+    /// the next time it subscribes
+    pub async fn rebuild(&self) -> Result<(), DatabaseError> {
+        self.store.clear(self.projection.name()).await?;
+        *self.current.write().unwrap() = Checkpoint { position: None, state: P::State::default() };
+        *self.unsaved.lock().unwrap() = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use serde_json::json;
+    use uuid::Uuid;
+    use super::{CheckpointStore, FileCheckpoints};
+
+    #[test]
+    fn stores_checkpoints_in_files() {
+        block_on(async {
+            let directory = std::env::temp_dir().join(format!("liquidity-{}", Uuid::new_v4()));
+            let store = FileCheckpoints::new(&directory).unwrap();
+
+            assert_eq!(store.load("test").await.unwrap(), None);
+            store.save("test", json!({ "position": 1 })).await.unwrap();
+            store.save("test", json!({ "position": 2 })).await.unwrap();
+            assert_eq!(FileCheckpoints::new(&directory).unwrap().load("test").await.unwrap(), Some(json!({ "position": 2 })));
+            assert!(!directory.join("test.tmp").exists());
+
+            store.clear("test").await.unwrap();
+            store.clear("test").await.unwrap();
+            assert_eq!(store.load("test").await.unwrap(), None);
+        })
+    }
+}
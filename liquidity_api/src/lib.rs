@@ -1,54 +1,68 @@
 pub use liquidity_elections as elections;
-pub use liquidity_elections::ElectionResolvers;
+pub use liquidity_elections::{ElectionResolvers, DelegationResolvers};
 
-use liquidity::{Connection, Context};
+use liquidity::Context;
+use liquidity::db::{Backend, DbConnection};
 use std::sync::Arc;
-use liquidity::context::User;
+use liquidity::context::{RequestInfo, User};
 use std::fmt;
 
-pub struct APIContext {
-    db: Arc<Connection>,
+/// The context of an API request. Generic over the database so the whole API can run against any backend,
+/// defaults to the backend picked by configuration.
+pub struct APIContext<DB: DbConnection = Backend> {
+    db: DB,
     user: Option<User>,
-    elections: Arc<ElectionResolvers>
+    request: RequestInfo,
+    elections: Arc<ElectionResolvers>,
+    delegations: Arc<DelegationResolvers>
 }
 
-impl APIContext {
-    pub fn new(db: Arc<Connection>, user: Option<User>, elections: Arc<ElectionResolvers>) -> Self {
+impl<DB: DbConnection> APIContext<DB> {
+    pub fn new(db: DB, user: Option<User>, elections: Arc<ElectionResolvers>, delegations: Arc<DelegationResolvers>) -> Self {
         APIContext {
             db,
             user,
-            elections
+            request: RequestInfo::default(),
+            elections,
+            delegations
         }
     }
 
-    pub fn clone_with_user(&self, user: User) -> Self {
+    /// Create a context for a single request, sharing the database connection and resolvers
+    pub fn clone_for_request(&self, user: Option<User>, request: RequestInfo) -> Self {
         APIContext {
             db: self.db.clone(),
-            user: Some(user),
-            elections: self.elections.clone()
+            user,
+            request,
+            elections: self.elections.clone(),
+            delegations: self.delegations.clone()
         }
     }
 
     pub fn elections(&self) -> Arc<ElectionResolvers> { self.elections.clone() }
+    pub fn delegations(&self) -> Arc<DelegationResolvers> { self.delegations.clone() }
 }
 
-impl Clone for APIContext {
+impl<DB: DbConnection> Clone for APIContext<DB> {
     fn clone(&self) -> Self {
         APIContext {
             db: self.db.clone(),
             user: self.user.clone(),
-            elections: self.elections.clone()
+            request: self.request.clone(),
+            elections: self.elections.clone(),
+            delegations: self.delegations.clone()
         }
     }
 }
 
-impl Context<Arc<Connection>> for APIContext {
-    fn db(&self) -> Arc<Connection> { self.db.clone() }
+impl<DB: DbConnection> Context<DB> for APIContext<DB> {
+    fn db(&self) -> DB { self.db.clone() }
     fn user(&self) -> &Option<User> { &self.user }
+    fn request(&self) -> &RequestInfo { &self.request }
 }
 
-impl fmt::Debug for APIContext {
+impl<DB: DbConnection> fmt::Debug for APIContext<DB> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "user: {:?}", self.user)
+        write!(f, "user: {:?}, request: {:?}", self.user, self.request)
     }
 }
\ No newline at end of file
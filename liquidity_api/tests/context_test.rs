@@ -0,0 +1,78 @@
+use chrono::{Duration as ChronoDuration, Utc};
+use liquidity::Context;
+use liquidity::context::{RequestInfo, User};
+use liquidity_api::{APIContext, DelegationResolvers, ElectionResolvers};
+use liquidity_api::elections::delegation::schema::DelegationScopeInput;
+use liquidity_api::elections::schema::ElectionInput;
+use liquidity_test_utils::connection::MockConnection;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_test::block_on;
+
+fn user(id: &str) -> Option<User> {
+    Some(User {
+        id: id.to_string(),
+        permissions: vec!["create:election".to_string(), "view:election".to_string(), "vote:election".to_string()],
+        roles: vec![]
+    })
+}
+
+fn context(conn: &MockConnection) -> APIContext<MockConnection> {
+    let elections = Arc::new(ElectionResolvers::new(10, Duration::from_secs(600)));
+    let delegations = Arc::new(DelegationResolvers::new(elections.repository()));
+    APIContext::new(conn.clone(), user("test_user_id"), elections, delegations)
+}
+
+fn election_input() -> ElectionInput {
+    ElectionInput {
+        name: Some("test_name".to_string()),
+        choices: Some(vec!["test1".to_string(), "test2".to_string()]),
+        start_date: Some(Utc::now()),
+        end_date: Some(Utc::now() + ChronoDuration::days(7)),
+        ..ElectionInput::default()
+    }
+}
+
+#[test]
+fn resolvers_run_against_any_connection() {
+    let conn = MockConnection::default();
+    let ctx = context(&conn);
+
+    block_on(async {
+        let created = ctx.elections().create_election(election_input(), &ctx)
+            .await
+            .expect("Creating the election shouldn't fail");
+
+        let found = ctx.elections().election(created.id, None, &ctx)
+            .await
+            .expect("Reading the election shouldn't fail")
+            .expect("The election should exist");
+        assert_eq!(found.name, "test_name");
+
+        assert_eq!(conn.events(format!("election-{}", created.id)).len(), 1);
+    });
+}
+
+#[test]
+fn request_contexts_share_the_connection() {
+    let conn = MockConnection::default();
+    let base = context(&conn);
+    let request = RequestInfo::new("test_request_id", None, None);
+    let ctx = base.clone_for_request(user("other_user_id"), request.clone());
+
+    block_on(async {
+        let created = base.elections().create_election(election_input(), &base)
+            .await
+            .expect("Creating the election shouldn't fail");
+
+        let delegation = ctx.delegations().delegate(
+            "test_user_id".to_string(),
+            DelegationScopeInput { election_id: Some(created.id), importance: None },
+            &ctx
+        ).await.expect("Delegating shouldn't fail");
+        assert_eq!(delegation.user_id, "other_user_id");
+        assert_eq!(delegation.delegate_id, "test_user_id");
+    });
+
+    assert_eq!(ctx.request(), &request);
+}
@@ -0,0 +1,93 @@
+//! Per-election access control
+//!
+//! These checks come in addition to the base permissions like `view:election`. The creator of an
+//! election always has admin access. Admin access implies edit access, and edit access implies
+//! view access. Voting is separate, admins need a vote role to vote like everyone else.
+
+use liquidity::context::User;
+use liquidity::permissions::{self, PermissionError};
+use crate::schema::Election;
+
+/// The level of access required for an action on an election
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    View,
+    Vote,
+    Edit,
+    Admin
+}
+
+/// Check the user's roles against the permissions of an election
+///
+/// # Arguments
+///
+/// * `access` - The level of access required
+/// * `election` - The election to check the permissions of
+/// * `user` - The user object that holds the roles
+///
+/// # Returns
+///
+/// Ok if the user has the required access, PermissionError if they don't or aren't logged in
+pub fn check(access: Access, election: &Election, user: &Option<User>) -> Result<(), PermissionError> {
+    let user_id = &user.as_ref().ok_or(PermissionError::NotLoggedIn)?.id;
+    if access != Access::Vote && *user_id == election.created_by_id { return Ok(()) }
+
+    let election_permissions = &election.permissions;
+    let allowed_roles = match access {
+        Access::View => vec![&election_permissions.view_roles, &election_permissions.edit_roles, &election_permissions.admin_roles],
+        Access::Vote => vec![&election_permissions.vote_roles],
+        Access::Edit => vec![&election_permissions.edit_roles, &election_permissions.admin_roles],
+        Access::Admin => vec![&election_permissions.admin_roles]
+    };
+
+    if allowed_roles.into_iter().any(|roles| permissions::check_roles(roles, user).is_ok()) {
+        Ok(())
+    } else {
+        Err(PermissionError::NotAllowed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use liquidity::{context::User, permissions::PermissionError};
+    use crate::schema::{Election, ElectionPermissions};
+    use super::{check, Access};
+
+    fn user(id: &str, roles: &[&str]) -> Option<User> {
+        Some(User {
+            id: id.to_string(),
+            permissions: Vec::new(),
+            roles: roles.iter().map(|role| role.to_string()).collect()
+        })
+    }
+
+    #[test]
+    fn default_permissions() {
+        let election = Election::test();
+
+        assert_eq!(check(Access::View, &election, &user("anyone", &[])), Ok(()));
+        assert_eq!(check(Access::Vote, &election, &user("anyone", &[])), Ok(()));
+        assert_eq!(check(Access::Edit, &election, &user("anyone", &[])), Err(PermissionError::NotAllowed));
+        assert_eq!(check(Access::Admin, &election, &user("creator", &[])), Ok(()));
+        assert_eq!(check(Access::View, &election, &None), Err(PermissionError::NotLoggedIn));
+    }
+
+    #[test]
+    fn roles_imply_lower_access() {
+        let permissions = ElectionPermissions {
+            view_roles: vec!["viewer".to_string()],
+            vote_roles: vec!["voter".to_string()],
+            edit_roles: vec!["editor".to_string()],
+            admin_roles: vec!["admin".to_string()]
+        };
+        let election = Election { permissions, ..Election::test() };
+
+        assert_eq!(check(Access::View, &election, &user("a", &["voter"])), Err(PermissionError::NotAllowed));
+        assert_eq!(check(Access::Vote, &election, &user("a", &["voter"])), Ok(()));
+        assert_eq!(check(Access::View, &election, &user("a", &["editor"])), Ok(()));
+        assert_eq!(check(Access::Admin, &election, &user("a", &["editor"])), Err(PermissionError::NotAllowed));
+        assert_eq!(check(Access::Edit, &election, &user("a", &["admin"])), Ok(()));
+        assert_eq!(check(Access::Vote, &election, &user("a", &["admin"])), Err(PermissionError::NotAllowed));
+        assert_eq!(check(Access::Vote, &election, &user("creator", &[])), Err(PermissionError::NotAllowed));
+    }
+}
@@ -0,0 +1,13 @@
+//! Liquid delegation
+//!
+//! Users can delegate their vote to another user, either for a single election or for every
+//! election of a given importance. A delegation for a single election takes precedence over one
+//! for the election's importance, and a direct vote always takes precedence over both.
+
+pub mod repository;
+pub mod resolution;
+pub mod resolvers;
+pub mod schema;
+mod models;
+
+pub use resolvers::DelegationResolvers;
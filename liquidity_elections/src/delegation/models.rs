@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug)]
+pub(crate) enum DelegationEventType {
+    Delegate,
+    Revoke
+}
+
+impl AsRef<str> for DelegationEventType {
+    fn as_ref(&self) -> &str {
+        match self {
+            DelegationEventType::Delegate => "delegation-create",
+            DelegationEventType::Revoke => "delegation-revoke"
+        }
+    }
+}
+
+impl DelegationEventType {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "delegation-create" => Some(DelegationEventType::Delegate),
+            "delegation-revoke" => Some(DelegationEventType::Revoke),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct DelegateEvent {
+    pub user_id: String,
+    /// The roles of the delegating user when they delegated. Roles only exist in their token, so this is the only
+    /// way to tell which elections of the importance they may vote in. Empty for delegations made before it was stored.
+    #[serde(default)]
+    pub roles: Vec<String>,
+    pub delegate_id: String,
+    pub created_at: DateTime<Utc>
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct RevokeDelegationEvent {
+    pub user_id: String,
+    pub revoked_at: DateTime<Utc>
+}
@@ -0,0 +1,275 @@
+use chrono::Utc;
+use std::collections::HashMap;
+use liquidity::context::User;
+use liquidity::db::{DatabaseError, DbConnection, EventMetadata, ExpectedVersion};
+use serde_json::Value;
+use crate::access::{self, Access};
+use crate::schema::Election;
+use super::models::{DelegationEventType, DelegateEvent, RevokeDelegationEvent};
+use super::schema::{Delegation, DelegationScope};
+
+#[derive(Debug, Default)]
+pub struct DelegationRepository;
+
+impl DelegationRepository {
+    pub fn new() -> DelegationRepository {
+        DelegationRepository
+    }
+
+    /// Delegate a user's vote to another user
+    ///
+    /// Any previous delegation the user made in the same scope is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The id of the user delegating their vote
+    /// * `roles` - The roles of the user delegating their vote, checked against the vote roles of every election in the scope
+    /// * `delegate_id` - The id of the user receiving the vote
+    /// * `scope` - The elections the delegation applies to
+    /// * `metadata` - The metadata to attach to the written event
+    /// * `conn` - The database connection to execute the insert on
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use liquidity_elections::{delegation::{repository::DelegationRepository, schema::DelegationScope}, schema::Importance};
+    /// # use liquidity::db::EventMetadata;
+    /// # use liquidity_test_utils::connection::MockConnection;
+    /// # let conn = MockConnection::default();
+    /// # let repository = DelegationRepository::new();
+    ///
+    /// let scope = DelegationScope::Importance(Importance::Minor);
+    ///
+    /// let delegation = repository.delegate("auth0|alice", &[], "auth0|bob", &scope, &EventMetadata::system(), conn)
+    ///     .await.unwrap();
+    ///
+    /// assert_eq!(delegation.delegate_id, "auth0|bob".to_string());
+    /// # })
+    /// ```
+    #[instrument(skip(conn))]
+    pub async fn delegate<T: DbConnection>(
+        &self,
+        user_id: &str,
+        roles: &[String],
+        delegate_id: &str,
+        scope: &DelegationScope,
+        metadata: &EventMetadata,
+        conn: T
+    ) -> Result<Delegation, DatabaseError> {
+        let event_data = DelegateEvent {
+            user_id: user_id.to_string(),
+            roles: roles.to_vec(),
+            delegate_id: delegate_id.to_string(),
+            created_at: Utc::now()
+        };
+
+        let result = conn
+            .write_event(scope.stream_id(), DelegationEventType::Delegate, event_data.clone(), metadata, ExpectedVersion::Any)
+            .await;
+
+        match &result {
+            Ok(event_data) => debug!("{:?}", event_data),
+            Err(e) => error!("{:?}", e)
+        };
+
+        result?;
+
+        let (election_id, importance) = match scope {
+            DelegationScope::Election(id) => (Some(id.to_owned()), None),
+            DelegationScope::Importance(importance) => (None, Some(importance.clone()))
+        };
+
+        Ok(Delegation {
+            user_id: event_data.user_id,
+            delegate_id: event_data.delegate_id,
+            election_id,
+            importance,
+            created_at: event_data.created_at
+        })
+    }
+
+    /// Revoke a user's delegation in a scope
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The id of the user revoking their delegation
+    /// * `scope` - The scope of the delegation to revoke
+    /// * `metadata` - The metadata to attach to the written event
+    /// * `conn` - The database connection to execute the insert on
+    #[instrument(skip(conn))]
+    pub async fn revoke<T: DbConnection>(&self, user_id: &str, scope: &DelegationScope, metadata: &EventMetadata, conn: T) -> Result<(), DatabaseError> {
+        let event_data = RevokeDelegationEvent {
+            user_id: user_id.to_string(),
+            revoked_at: Utc::now()
+        };
+
+        let result = conn
+            .write_event(scope.stream_id(), DelegationEventType::Revoke, event_data, metadata, ExpectedVersion::Any)
+            .await;
+
+        if let Err(e) = &result { error!("{:?}", e) }
+
+        result
+    }
+
+    /// Find the active delegations in a scope
+    ///
+    /// # Arguments
+    ///
+    /// * `scope` - The scope to look up
+    /// * `conn` - The database connection
+    ///
+    /// # Returns
+    ///
+    /// A map of user ids to the id of the user they delegated to
+    #[instrument(skip(conn))]
+    pub async fn find_delegations<T: DbConnection>(&self, scope: &DelegationScope, conn: T) -> Result<HashMap<String, String>, DatabaseError> {
+        let delegations = self.read_delegations(scope, conn).await?
+            .into_iter()
+            .map(|(user_id, delegation)| (user_id, delegation.delegate_id))
+            .collect();
+
+        Ok(delegations)
+    }
+
+    /// Fold the events of a scope into the latest delegation of each user
+    async fn read_delegations<T: DbConnection>(&self, scope: &DelegationScope, conn: T) -> Result<HashMap<String, DelegateEvent>, DatabaseError> {
+        let events = conn.read_all::<_, Value>(scope.stream_id()).await?;
+
+        let mut delegations = HashMap::new();
+        for event in events {
+            match DelegationEventType::parse(&event.event_type) {
+                Some(DelegationEventType::Delegate) => {
+                    let payload: DelegateEvent = serde_json::from_value(event.payload)?;
+                    delegations.insert(payload.user_id.clone(), payload);
+                },
+                Some(DelegationEventType::Revoke) => {
+                    let payload: RevokeDelegationEvent = serde_json::from_value(event.payload)?;
+                    delegations.remove(&payload.user_id);
+                },
+                None => warn!("Skipping unknown event type {} in {}", event.event_type, scope.stream_id())
+            }
+        }
+
+        Ok(delegations)
+    }
+
+    /// Find the delegations that apply to an election
+    ///
+    /// Delegations made for the election itself take precedence over delegations made for its importance.
+    /// Delegations made for the importance only count if the roles the user had when delegating let them vote in
+    /// the election, delegations for the election itself were checked when they were made.
+    /// Direct votes aren't taken into account here, they override any delegation when tallying.
+    ///
+    /// # Arguments
+    ///
+    /// * `election` - The election to look up the delegations for
+    /// * `conn` - The database connection
+    ///
+    /// # Returns
+    ///
+    /// A map of user ids to the id of the user they delegated to
+    #[instrument(skip(conn))]
+    pub async fn find_election_delegations<T: DbConnection>(&self, election: &Election, conn: T) -> Result<HashMap<String, String>, DatabaseError> {
+        let importance_scope = DelegationScope::Importance(election.importance.clone());
+        let election_scope = DelegationScope::Election(election.id);
+
+        let mut delegations: HashMap<String, String> = self.read_delegations(&importance_scope, conn.clone()).await?
+            .into_iter()
+            .filter(|(_, delegation)| may_vote(election, delegation))
+            .map(|(user_id, delegation)| (user_id, delegation.delegate_id))
+            .collect();
+        delegations.extend(self.find_delegations(&election_scope, conn).await?);
+
+        Ok(delegations)
+    }
+}
+
+/// Whether the user who made a delegation could vote in an election with the roles they had at the time
+fn may_vote(election: &Election, delegation: &DelegateEvent) -> bool {
+    let delegator = Some(User { id: delegation.user_id.clone(), permissions: Vec::new(), roles: delegation.roles.clone() });
+    access::check(Access::Vote, election, &delegator).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::block_on;
+    use liquidity::db::EventMetadata;
+    use liquidity_test_utils::connection::MockConnection;
+    use crate::schema::{Election, ElectionPermissions, Importance};
+    use crate::delegation::schema::DelegationScope;
+    use super::DelegationRepository;
+
+    #[test]
+    fn delegate_and_revoke_work() {
+        block_on(async {
+            let conn = MockConnection::default();
+            let repository = DelegationRepository::new();
+            let scope = DelegationScope::Importance(Importance::Regular);
+
+            repository.delegate("alice", &[], "bob", &scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.delegate("carol", &[], "bob", &scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.delegate("alice", &[], "dave", &scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.revoke("carol", &scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            let delegations = repository.find_delegations(&scope, conn.clone()).await.unwrap();
+
+            assert_eq!(delegations.len(), 1);
+            assert_eq!(delegations["alice"], "dave".to_string());
+        })
+    }
+
+    #[test]
+    fn election_scope_overrides_importance_scope() {
+        block_on(async {
+            let conn = MockConnection::default();
+            let repository = DelegationRepository::new();
+            let election = Election::test();
+            let importance_scope = DelegationScope::Importance(Importance::Regular);
+            let election_scope = DelegationScope::Election(election.id);
+            let other_importance = DelegationScope::Importance(Importance::Minor);
+
+            repository.delegate("alice", &[], "bob", &importance_scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.delegate("alice", &[], "carol", &election_scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.delegate("dave", &[], "bob", &importance_scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.delegate("erin", &[], "bob", &other_importance, &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            let delegations = repository.find_election_delegations(&election, conn.clone()).await.unwrap();
+
+            assert_eq!(delegations.len(), 2);
+            assert_eq!(delegations["alice"], "carol".to_string());
+            assert_eq!(delegations["dave"], "bob".to_string());
+
+            repository.revoke("alice", &election_scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            let delegations = repository.find_election_delegations(&election, conn.clone()).await.unwrap();
+
+            assert_eq!(delegations["alice"], "bob".to_string());
+        })
+    }
+
+    #[test]
+    fn importance_delegations_need_vote_access() {
+        block_on(async {
+            let conn = MockConnection::default();
+            let repository = DelegationRepository::new();
+            let restricted = Election {
+                permissions: ElectionPermissions { vote_roles: vec!["member".to_string()], ..ElectionPermissions::default() },
+                ..Election::test()
+            };
+            let scope = DelegationScope::Importance(Importance::Regular);
+
+            repository.delegate("alice", &["member".to_string()], "bob", &scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.delegate("carol", &["guest".to_string()], "bob", &scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.delegate("dave", &[], "bob", &scope, &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            let delegations = repository.find_election_delegations(&restricted, conn.clone()).await.unwrap();
+            assert_eq!(delegations.keys().collect::<Vec<_>>(), vec!["alice"]);
+
+            // Everyone may vote in an election open to all
+            let open = repository.find_election_delegations(&Election::test(), conn.clone()).await.unwrap();
+            assert_eq!(open.len(), 3);
+        })
+    }
+}
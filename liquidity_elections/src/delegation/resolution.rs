@@ -0,0 +1,207 @@
+//! Transitive delegation resolution
+//!
+//! Follows delegation chains until they reach someone who voted directly. A chain A → B → C where
+//! only C voted gives C a weight of 3. Chains that loop back on themselves or end at someone who
+//! neither voted nor delegated lose their votes, and are reported so outcomes can be explained.
+
+use std::collections::{HashMap, HashSet};
+
+/// Why a delegated vote wasn't counted
+#[derive(Debug, Clone, PartialEq)]
+pub enum LostReason {
+    /// The delegation chain ends in a cycle, listed in delegation order
+    Cycle(Vec<String>),
+    /// The delegation chain ends at this user, who neither voted nor delegated
+    DeadEnd(String)
+}
+
+/// A delegated vote that wasn't counted
+#[derive(Debug, Clone, PartialEq)]
+pub struct LostVote {
+    pub user_id: String,
+    pub reason: LostReason
+}
+
+/// The result of resolving delegations for an election
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Resolution {
+    /// The effective weight of every direct voter, including their own vote
+    pub weights: HashMap<String, u32>,
+    /// The direct voter each counted delegator's vote ended up with
+    pub represented_by: HashMap<String, String>,
+    /// Every delegation cycle found, listed in delegation order
+    pub cycles: Vec<Vec<String>>,
+    /// Every delegated vote that couldn't be counted
+    pub lost: Vec<LostVote>
+}
+
+#[derive(Clone)]
+enum Outcome {
+    Voter(String),
+    Lost(LostReason)
+}
+
+/// Resolve delegations into effective voter weights
+///
+/// Direct votes always take precedence, so a delegation made by someone who voted is ignored.
+///
+/// # Arguments
+///
+/// * `voters` - The ids of the users who voted directly. Duplicates are ignored.
+/// * `delegations` - A map of user ids to the id of the user they delegated to
+///
+/// # Returns
+///
+/// The weight of every direct voter and a report of the votes that were lost
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashMap;
+/// use liquidity_elections::delegation::resolution::resolve;
+///
+/// let mut delegations = HashMap::new();
+/// delegations.insert("alice".to_string(), "bob".to_string());
+/// delegations.insert("bob".to_string(), "carol".to_string());
+///
+/// let resolution = resolve(vec!["carol"], &delegations);
+///
+/// assert_eq!(resolution.weights["carol"], 3);
+/// assert!(resolution.lost.is_empty());
+/// ```
+pub fn resolve<'a, I>(voters: I, delegations: &HashMap<String, String>) -> Resolution
+    where I: IntoIterator<Item = &'a str> {
+
+    let voters: HashSet<&str> = voters.into_iter().collect();
+    let mut resolution = Resolution::default();
+    let mut outcomes: HashMap<&str, Outcome> = HashMap::new();
+
+    for voter in &voters {
+        resolution.weights.insert(voter.to_string(), 1);
+    }
+
+    let mut delegators: Vec<&str> = delegations.keys()
+        .map(String::as_str)
+        .filter(|user| !voters.contains(user))
+        .collect();
+    delegators.sort();
+
+    for delegator in delegators {
+        if outcomes.contains_key(delegator) { continue }
+
+        let mut path = vec![delegator];
+        let outcome = loop {
+            let current = *path.last().unwrap();
+            let next = match delegations.get(current) {
+                Some(next) => next.as_str(),
+                None => {
+                    // The last user in the chain didn't delegate, so they aren't part of the path
+                    path.pop();
+                    break Outcome::Lost(LostReason::DeadEnd(current.to_string()))
+                }
+            };
+
+            if voters.contains(next) { break Outcome::Voter(next.to_string()) }
+            if let Some(outcome) = outcomes.get(next) { break outcome.clone() }
+            if let Some(start) = path.iter().position(|user| *user == next) {
+                let cycle: Vec<String> = path[start..].iter().map(|user| user.to_string()).collect();
+                resolution.cycles.push(cycle.clone());
+                break Outcome::Lost(LostReason::Cycle(cycle))
+            }
+
+            path.push(next);
+        };
+
+        for user in path {
+            match &outcome {
+                Outcome::Voter(voter) => {
+                    *resolution.weights.get_mut(voter).unwrap() += 1;
+                    resolution.represented_by.insert(user.to_string(), voter.to_owned());
+                },
+                Outcome::Lost(reason) => {
+                    resolution.lost.push(LostVote { user_id: user.to_string(), reason: reason.clone() });
+                }
+            }
+            outcomes.insert(user, outcome.clone());
+        }
+    }
+
+    resolution.lost.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+    resolution
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use super::{resolve, LostReason, LostVote};
+
+    fn delegations(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(from, to)| (from.to_string(), to.to_string())).collect()
+    }
+
+    #[test]
+    fn follows_chains() {
+        let resolution = resolve(vec!["c"], &delegations(&[("a", "b"), ("b", "c")]));
+
+        assert_eq!(resolution.weights.len(), 1);
+        assert_eq!(resolution.weights["c"], 3);
+        assert_eq!(resolution.represented_by["a"], "c".to_string());
+        assert_eq!(resolution.represented_by["b"], "c".to_string());
+        assert!(resolution.cycles.is_empty());
+        assert!(resolution.lost.is_empty());
+    }
+
+    #[test]
+    fn direct_votes_override_delegations() {
+        let resolution = resolve(vec!["b", "c"], &delegations(&[("a", "b"), ("b", "c")]));
+
+        assert_eq!(resolution.weights["b"], 2);
+        assert_eq!(resolution.weights["c"], 1);
+        assert_eq!(resolution.represented_by["a"], "b".to_string());
+        assert!(!resolution.represented_by.contains_key("b"));
+    }
+
+    #[test]
+    fn breaks_cycles() {
+        let resolution = resolve(vec!["e"], &delegations(&[("a", "b"), ("b", "a"), ("d", "a"), ("f", "e")]));
+
+        assert_eq!(resolution.weights["e"], 2);
+        assert_eq!(resolution.cycles, vec![vec!["a".to_string(), "b".to_string()]]);
+
+        let cycle = LostReason::Cycle(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(resolution.lost, vec![
+            LostVote { user_id: "a".to_string(), reason: cycle.clone() },
+            LostVote { user_id: "b".to_string(), reason: cycle.clone() },
+            LostVote { user_id: "d".to_string(), reason: cycle }
+        ]);
+    }
+
+    #[test]
+    fn self_delegation_is_a_cycle() {
+        let resolution = resolve(vec![], &delegations(&[("a", "a")]));
+
+        assert_eq!(resolution.cycles, vec![vec!["a".to_string()]]);
+        assert_eq!(resolution.lost.len(), 1);
+    }
+
+    #[test]
+    fn reports_dead_ends() {
+        let resolution = resolve(vec!["x"], &delegations(&[("a", "b"), ("b", "c"), ("d", "b")]));
+
+        assert_eq!(resolution.weights["x"], 1);
+        let dead_end = LostReason::DeadEnd("c".to_string());
+        assert_eq!(resolution.lost, vec![
+            LostVote { user_id: "a".to_string(), reason: dead_end.clone() },
+            LostVote { user_id: "b".to_string(), reason: dead_end.clone() },
+            LostVote { user_id: "d".to_string(), reason: dead_end }
+        ]);
+    }
+
+    #[test]
+    fn ignores_duplicate_voters() {
+        let resolution = resolve(vec!["a", "a", "b"], &delegations(&[("c", "a")]));
+
+        assert_eq!(resolution.weights["a"], 2);
+        assert_eq!(resolution.weights["b"], 1);
+    }
+}
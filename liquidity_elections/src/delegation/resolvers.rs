@@ -0,0 +1,122 @@
+use std::sync::Arc;
+use liquidity::{Context, Error, permissions};
+use liquidity::db::DbConnection;
+use crate::repository::ElectionRepository;
+use crate::access::{self, Access};
+use super::repository::DelegationRepository;
+use super::schema::{Delegation, DelegationScope, DelegationScopeInput};
+
+#[derive(Debug)]
+pub struct DelegationResolvers {
+    repository: DelegationRepository,
+    elections: Arc<ElectionRepository>
+}
+
+impl DelegationResolvers {
+    pub fn new(elections: Arc<ElectionRepository>) -> DelegationResolvers {
+        DelegationResolvers {
+            repository: DelegationRepository::new(),
+            elections
+        }
+    }
+
+    /// Validate a scope input, making sure the election exists and the user may vote in it if it's scoped to one
+    async fn scope<T: DbConnection, C: Context<T>>(&self, scope: DelegationScopeInput, context: &C) -> Result<DelegationScope, Error> {
+        let scope = scope.scope().ok_or("Exactly one of electionId and importance must be set")?;
+
+        if let DelegationScope::Election(id) = &scope {
+            let election = self.elections.find_election(id, context.db()).await?
+                .ok_or("Election doesn't exist")?;
+            access::check(Access::Vote, &election, context.user())?;
+        }
+
+        Ok(scope)
+    }
+
+    /// Delegate your vote to another user
+    ///
+    /// # Arguments
+    ///
+    /// `to_user_id` - The id of the user to delegate to
+    /// `scope` - The elections the delegation applies to
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `vote:election`, and one of the election's vote roles if the delegation is scoped to an election.
+    /// Delegations scoped to an importance only count in the elections the user's current roles let them vote in.
+    ///
+    /// # Returns
+    ///
+    /// The new delegation or an error if the delegation failed
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// mutation {
+    ///     delegate(toUserId: "auth0|5dd50524bdb77c0f17fc7543", scope: {importance: REGULAR}) {
+    ///         delegateId
+    ///         importance
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn delegate<T: DbConnection, C: Context<T>>(
+        &self,
+        to_user_id: String,
+        scope: DelegationScopeInput,
+        context: &C
+    ) -> Result<Delegation, Error> {
+        permissions::check("vote:election", context.user())?;
+
+        let db = context.db();
+        let user = context.user().as_ref().unwrap();
+
+        if user.id == to_user_id { return Err("You can't delegate to yourself".into()) }
+        let scope = self.scope(scope, context).await?;
+
+        let result = self.repository.delegate(&user.id, &user.roles, &to_user_id, &scope, &context.metadata(), db).await?;
+        Ok(result)
+    }
+
+    /// Revoke your delegation
+    ///
+    /// # Arguments
+    ///
+    /// `scope` - The scope of the delegation to revoke
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `vote:election`
+    ///
+    /// # Returns
+    ///
+    /// True if a delegation was revoked, false if there was none in this scope
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// mutation {
+    ///     revokeDelegation(scope: {electionId: "54c8ed41-b4f4-4f1c-8903-f9bbbe2d992d"})
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn revoke_delegation<T: DbConnection, C: Context<T>>(
+        &self,
+        scope: DelegationScopeInput,
+        context: &C
+    ) -> Result<bool, Error> {
+        permissions::check("vote:election", context.user())?;
+
+        let db = context.db();
+        let user = context.user().as_ref().unwrap();
+        let scope = scope.scope().ok_or("Exactly one of electionId and importance must be set")?;
+
+        let delegations = self.repository.find_delegations(&scope, db.clone()).await?;
+        if !delegations.contains_key(&user.id) { return Ok(false) }
+
+        self.repository.revoke(&user.id, &scope, &context.metadata(), db).await?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,68 @@
+use chrono::{DateTime, Utc};
+use liquidity::Uuid;
+use crate::schema::Importance;
+
+/// The elections a delegation applies to
+#[derive(Debug, Clone, PartialEq)]
+pub enum DelegationScope {
+    /// A single election
+    Election(Uuid),
+    /// All elections of an importance level
+    Importance(Importance)
+}
+
+impl DelegationScope {
+    /// The id of the stream holding the delegations for this scope
+    pub fn stream_id(&self) -> String {
+        match self {
+            DelegationScope::Election(id) => format!("delegation-election-{}", id),
+            DelegationScope::Importance(importance) => {
+                let importance = match importance {
+                    Importance::Important => "important",
+                    Importance::Regular => "regular",
+                    Importance::Minor => "minor"
+                };
+                format!("delegation-importance-{}", importance)
+            }
+        }
+    }
+}
+
+#[derive(juniper::GraphQLInputObject, Debug, PartialEq)]
+/// The scope of a delegation. Exactly one of the fields must be set.
+pub struct DelegationScopeInput {
+    /// Delegate for a single election
+    pub election_id: Option<Uuid>,
+    /// Delegate for all elections of this importance
+    pub importance: Option<Importance>
+}
+
+impl DelegationScopeInput {
+    /// Validate the input and turn it into a scope
+    ///
+    /// # Returns
+    ///
+    /// The scope, or None if not exactly one field was set
+    pub fn scope(self) -> Option<DelegationScope> {
+        match (self.election_id, self.importance) {
+            (Some(id), None) => Some(DelegationScope::Election(id)),
+            (None, Some(importance)) => Some(DelegationScope::Importance(importance)),
+            _ => None
+        }
+    }
+}
+
+#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+/// A delegation of a user's vote to another user
+pub struct Delegation {
+    /// The id of the user delegating their vote
+    pub user_id: String,
+    /// The id of the user receiving the vote
+    pub delegate_id: String,
+    /// The election this delegation applies to, if it's scoped to a single election
+    pub election_id: Option<Uuid>,
+    /// The importance level this delegation applies to, if it's scoped to an importance level
+    pub importance: Option<Importance>,
+    /// The time the delegation was made at
+    pub created_at: DateTime<Utc>
+}
@@ -0,0 +1,208 @@
+//! The change history of an election, built from the raw events in its stream
+
+use liquidity::db::{Aggregate, DatabaseError, DomainEvent, StoredEvent};
+use serde::Serialize;
+use serde_json::Value;
+use crate::models::ElectionEvent;
+use crate::schema::{Election, ElectionChange, ElectionChangeType, FieldChange};
+
+pub(crate) struct ElectionHistory {
+    /// The last state of the election. If it was deleted, this is the state right before that
+    pub election: Election,
+    /// Every change made to the election, oldest first
+    pub changes: Vec<ElectionChange>
+}
+
+/// Replay the events of an election stream, recording what every event changed
+///
+/// # Returns
+///
+/// The history of the election, None if the stream doesn't contain a create event
+pub(crate) fn build(events: Vec<StoredEvent<Value>>) -> Result<Option<ElectionHistory>, DatabaseError> {
+    let mut election: Option<Election> = None;
+    let mut changes = Vec::new();
+
+    for (version, event) in events.into_iter().enumerate() {
+        // Events written before metadata was stored only know who made them from their payload
+        let (actor_id, timestamp, request_id) = match event.metadata.clone() {
+            Some(metadata) => (metadata.actor_id, Some(metadata.timestamp), metadata.request_id),
+            None => (None, None, None)
+        };
+        let mut change = ElectionChange {
+            version: version as i32,
+            change_type: ElectionChangeType::Updated,
+            actor_id,
+            timestamp,
+            request_id,
+            changes: Vec::new(),
+            reason: None
+        };
+
+        let event = match ElectionEvent::from_stored(&event.event_type, event.payload, event.metadata.as_ref())? {
+            Some(event) => event,
+            None => continue
+        };
+
+        match event {
+            ElectionEvent::Created(created) => {
+                let created: Election = created.into();
+                change.change_type = ElectionChangeType::Created;
+                change.actor_id = change.actor_id.or_else(|| Some(created.created_by_id.clone()));
+                change.changes = diff(None, &created);
+                election = Some(created);
+            },
+            ElectionEvent::Deleted(deleted) => {
+                if election.is_none() { continue }
+                change.change_type = ElectionChangeType::Deleted;
+                change.actor_id = change.actor_id.or(Some(deleted.deleted_by_id));
+                change.timestamp = change.timestamp.or(Some(deleted.deleted_at));
+                change.reason = deleted.reason;
+            },
+            event => {
+                let original = match election.take() {
+                    Some(original) => original,
+                    None => continue
+                };
+                let updated = Election::apply(Some(original.clone()), event).unwrap_or_else(|| original.clone());
+                change.changes = diff(Some(&original), &updated);
+                election = Some(updated);
+            }
+        }
+
+        changes.push(change);
+    }
+
+    Ok(election.map(|election| ElectionHistory { election, changes }))
+}
+
+/// Render a value for a field change. Text is kept as is, everything else is encoded as JSON.
+fn render<T: Serialize>(value: &T) -> Option<String> {
+    match serde_json::to_value(value).ok()? {
+        Value::Null => None,
+        Value::String(text) => Some(text),
+        value => Some(value.to_string())
+    }
+}
+
+/// Reads a single field of an election, rendered for its history
+type FieldReader = fn(&Election) -> Option<String>;
+
+/// The fields of an election that show up in its history, by their name in the API
+fn fields() -> Vec<(&'static str, FieldReader)> {
+    vec![
+        ("name", |election| render(&election.name)),
+        ("description", |election| render(&election.description)),
+        ("choices", |election| render(&election.choices)),
+        ("votingMethod", |election| render(&election.voting_method)),
+        ("startDate", |election| render(&election.start_date)),
+        ("endDate", |election| render(&election.end_date)),
+        ("importance", |election| render(&election.importance)),
+        ("hideResults", |election| render(&election.hide_results)),
+        ("createdById", |election| render(&election.created_by_id)),
+        ("permissions", |election| render(&election.permissions)),
+        ("status", |election| render(&election.status)),
+        ("cancellationReason", |election| render(&election.cancellation_reason)),
+        ("certifiedVersion", |election| render(&election.certified_version))
+    ]
+}
+
+/// Compare two states of an election field by field
+///
+/// # Arguments
+///
+/// * `old` - The election before the change, None if it was just created
+/// * `new` - The election after the change
+fn diff(old: Option<&Election>, new: &Election) -> Vec<FieldChange> {
+    fields().into_iter()
+        .filter_map(|(field, value)| {
+            let old_value = old.and_then(value);
+            let new_value = value(new);
+            if old_value == new_value { return None }
+
+            Some(FieldChange { field: field.to_string(), old_value, new_value })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use liquidity::db::{EventMetadata, EventType, StoredEvent};
+    use liquidity::Uuid;
+    use serde_json::json;
+    use crate::schema::{ElectionChangeType, ElectionStatus, FieldChange};
+    use super::build;
+
+    fn event(event_type: EventType, payload: serde_json::Value, metadata: Option<EventMetadata>) -> StoredEvent<serde_json::Value> {
+        StoredEvent { stream_id: "election-test".to_string(), event_type: event_type.as_ref().to_string(), payload, metadata }
+    }
+
+    fn create_payload() -> serde_json::Value {
+        json!({
+            "id": Uuid::new_v4(),
+            "name": "test_name",
+            "description": "test_description",
+            "start_date": Utc::now(),
+            "end_date": Utc::now(),
+            "importance": "Regular",
+            "created_by_id": "alice",
+            "choices": ["test1", "test2"],
+            "status": "Draft"
+        })
+    }
+
+    #[test]
+    fn records_field_changes() {
+        let metadata = EventMetadata { actor_id: Some("bob".to_string()), ..EventMetadata::system() };
+        let events = vec![
+            event(EventType::Create, create_payload(), None),
+            event(EventType::Update, json!({ "name": "renamed", "choices": ["test1", "test3"] }), Some(metadata.clone())),
+            event(EventType::Update, json!({ "status": "Cancelled", "cancellation_reason": "Postponed" }), Some(metadata))
+        ];
+
+        let history = build(events).unwrap().unwrap();
+        let changes = history.changes;
+
+        assert_eq!(history.election.status, ElectionStatus::Cancelled);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0].change_type, ElectionChangeType::Created);
+        assert_eq!(changes[0].actor_id, Some("alice".to_string()));
+        assert_eq!(changes[0].timestamp, None);
+        assert!(changes[0].changes.iter().all(|change| change.old_value.is_none()));
+
+        assert_eq!(changes[1].version, 1);
+        assert_eq!(changes[1].actor_id, Some("bob".to_string()));
+        assert_eq!(changes[1].changes, vec![
+            FieldChange { field: "name".to_string(), old_value: Some("test_name".to_string()), new_value: Some("renamed".to_string()) },
+            FieldChange {
+                field: "choices".to_string(),
+                old_value: Some("[\"test1\",\"test2\"]".to_string()),
+                new_value: Some("[\"test1\",\"test3\"]".to_string())
+            }
+        ]);
+        assert_eq!(changes[2].changes.iter().map(|change| change.field.as_str()).collect::<Vec<_>>(), vec!["status", "cancellationReason"]);
+    }
+
+    #[test]
+    fn keeps_deleted_elections() {
+        let events = vec![
+            event(EventType::Create, create_payload(), None),
+            event(EventType::Delete, json!({ "deleted_by_id": "carol", "reason": "Duplicate", "deleted_at": Utc::now() }), None)
+        ];
+
+        let history = build(events).unwrap().unwrap();
+        let deleted = &history.changes[1];
+
+        assert_eq!(history.election.name, "test_name");
+        assert_eq!(deleted.change_type, ElectionChangeType::Deleted);
+        assert_eq!(deleted.actor_id, Some("carol".to_string()));
+        assert_eq!(deleted.reason, Some("Duplicate".to_string()));
+        assert!(deleted.timestamp.is_some());
+        assert!(deleted.changes.is_empty());
+    }
+
+    #[test]
+    fn empty_stream_has_no_history() {
+        assert!(build(Vec::new()).unwrap().is_none());
+    }
+}
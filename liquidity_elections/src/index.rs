@@ -0,0 +1,333 @@
+//! An in-memory read model of every election, used to list and search elections
+//!
+//! The index is the `ElectionList` projection, kept up to date from a subscription to the `election` category by
+//! `run`, so it sees the writes of every instance sharing the database. The elections this instance writes show
+//! up right away, before the subscription delivers them.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, TimeZone, Utc};
+use liquidity::Uuid;
+use liquidity::db::{DatabaseError, DbConnection};
+use liquidity::projection::{CheckpointStore, MemoryCheckpoints, Projector};
+use crate::projections::ElectionList;
+use crate::schema::{Election, ElectionFilter, ElectionOrder, ElectionOrderField, Importance, OrderDirection};
+
+/// An election this instance wrote, None if it was deleted. Deletions are kept around until the projection moves past
+/// the position it was at when the election was deleted, since a projection without the election can't tell whether it
+/// saw the deletion or not even the creation yet.
+struct Written {
+    version: i32,
+    election: Option<Election>,
+    /// The position of the projection when the election was deleted. None if it hadn't projected any event yet, which
+    /// every position is past
+    removed_at: Option<u64>
+}
+
+pub struct ElectionIndex {
+    projector: Projector<ElectionList>,
+    /// The writes of this instance the projection may not have caught up with yet
+    written: Mutex<HashMap<Uuid, Written>>
+}
+
+impl Default for ElectionIndex {
+    fn default() -> Self {
+        ElectionIndex::new()
+    }
+}
+
+impl ElectionIndex {
+    /// Create an index that's rebuilt from the first event whenever the process restarts
+    pub fn new() -> Self {
+        ElectionIndex::with_checkpoints(Arc::new(MemoryCheckpoints::new()))
+    }
+
+    /// Create an index that continues from the checkpoints in a store when it's run
+    pub fn with_checkpoints(store: Arc<dyn CheckpointStore>) -> Self {
+        ElectionIndex {
+            projector: Projector::new(ElectionList, store),
+            written: Mutex::new(HashMap::new())
+        }
+    }
+
+    /// The projector building the index
+    pub fn projector(&self) -> &Projector<ElectionList> {
+        &self.projector
+    }
+
+    /// Continue from the last checkpoint and keep the index up to date with the `election` category. Until this runs,
+    /// the index only holds the elections this instance wrote.
+    ///
+    /// # Returns
+    ///
+    /// Nothing once the subscription ends, or the database error that ended it
+    pub async fn run<T: DbConnection>(&self, conn: &T) -> Result<(), DatabaseError> {
+        self.projector.load().await?;
+        let events = self.projector.subscribe(conn).await?;
+        self.projector.run(events).await
+    }
+
+    /// Find every election matching a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter the elections have to match
+    /// * `order` - The order to sort the elections in. Ties are broken by id so the order is stable
+    ///
+    /// # Returns
+    ///
+    /// The sorted elections
+    pub fn search(&self, filter: &ElectionFilter, order: &ElectionOrder) -> Vec<Election> {
+        let mut written = self.written.lock().unwrap();
+        // Read before the state, so the state is at least this far along
+        let position = self.projector.position();
+        let mut result: Vec<Election> = self.projector.read(|projected| {
+            // Writes the projection caught up with don't need to be kept around anymore. A deletion it doesn't hold the
+            // election for once it moved on from where it was when the election was deleted was projected already.
+            written.retain(|id, write| match (projected.get(id), &write.election) {
+                (Some(election), _) => election.version < write.version,
+                (None, Some(_)) => true,
+                (None, None) => position <= write.removed_at
+            });
+
+            projected.iter()
+                .filter(|(id, _)| !written.contains_key(id))
+                .map(|(_, election)| election)
+                .chain(written.values().filter_map(|write| write.election.as_ref()))
+                .filter(|election| matches(filter, election))
+                .cloned()
+                .collect()
+        });
+        result.sort_by(|a, b| compare(order, &Cursor::of(a), &Cursor::of(b)));
+
+        result
+    }
+
+    /// Add or replace an election this instance wrote
+    pub fn put(&self, election: &Election) {
+        let write = Written { version: election.version, election: Some(election.clone()), removed_at: None };
+        self.written.lock().unwrap().insert(election.id, write);
+    }
+
+    /// Remove an election this instance deleted
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The version of the election after it was deleted
+    pub fn remove(&self, id: &Uuid, version: i32) {
+        let write = Written { version, election: None, removed_at: self.projector.position() };
+        self.written.lock().unwrap().insert(*id, write);
+    }
+}
+
+fn matches(filter: &ElectionFilter, election: &Election) -> bool {
+    let search = filter.search.as_ref().map(|search| search.to_lowercase());
+
+    filter.status.iter().all(|status| status.contains(&election.status)) &&
+        filter.importance.iter().all(|importance| importance.contains(&election.importance)) &&
+        filter.from.iter().all(|from| election.end_date >= *from) &&
+        filter.to.iter().all(|to| election.start_date <= *to) &&
+        filter.created_by_id.iter().all(|creator| *creator == election.created_by_id) &&
+        search.iter().all(|search| {
+            election.name.to_lowercase().contains(search) || election.description.to_lowercase().contains(search)
+        })
+}
+
+fn importance_rank(importance: &Importance) -> u8 {
+    match importance {
+        Importance::Minor => 0,
+        Importance::Regular => 1,
+        Importance::Important => 2
+    }
+}
+
+/// Where a page of elections ends. It holds what the elections are sorted by rather than a position in the list, so
+/// the next page stays right when elections are added or removed, including the one the cursor was taken from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    importance: u8,
+    start_date: DateTime<Utc>,
+    id: Uuid
+}
+
+impl Cursor {
+    pub fn of(election: &Election) -> Self {
+        Cursor {
+            importance: importance_rank(&election.importance),
+            start_date: election.start_date,
+            id: election.id
+        }
+    }
+
+    /// Whether an election comes after the cursor when the elections are sorted in `order`
+    pub fn is_before(&self, order: &ElectionOrder, election: &Election) -> bool {
+        compare(order, self, &Cursor::of(election)) == Ordering::Less
+    }
+
+    fn parse(cursor: &str) -> Option<Cursor> {
+        let mut parts = cursor.splitn(3, '_');
+        let importance = parts.next()?.parse().ok()?;
+        let (seconds, nanoseconds) = parts.next()?.split_once('.')?;
+        let start_date = Utc.timestamp_opt(seconds.parse().ok()?, nanoseconds.parse().ok()?).single()?;
+        let id = Uuid::parse_str(parts.next()?).ok()?;
+
+        Some(Cursor { importance, start_date, id })
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}.{:09}_{}", self.importance, self.start_date.timestamp(), self.start_date.timestamp_subsec_nanos(), self.id)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = &'static str;
+
+    /// # Example
+    ///
+    /// ```
+    /// use liquidity_elections::index::Cursor;
+    ///
+    /// let cursor: Cursor = "1_1577836800.000000000_936da01f-9abd-4d9d-80c7-02af85c822a8".parse().unwrap();
+    /// assert_eq!(cursor.to_string(), "1_1577836800.000000000_936da01f-9abd-4d9d-80c7-02af85c822a8");
+    /// assert!("936da01f-9abd-4d9d-80c7-02af85c822a8".parse::<Cursor>().is_err());
+    /// ```
+    fn from_str(cursor: &str) -> Result<Self, Self::Err> {
+        Cursor::parse(cursor).ok_or("Invalid cursor")
+    }
+}
+
+fn compare(order: &ElectionOrder, a: &Cursor, b: &Cursor) -> Ordering {
+    let ordering = match order.field {
+        ElectionOrderField::Importance => a.importance.cmp(&b.importance)
+            .then(a.start_date.cmp(&b.start_date)),
+        ElectionOrderField::StartDate => a.start_date.cmp(&b.start_date)
+    };
+    let ordering = match order.direction {
+        OrderDirection::Asc => ordering,
+        OrderDirection::Desc => ordering.reverse()
+    };
+
+    ordering.then(a.id.cmp(&b.id))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use chrono::{Utc, Duration};
+    use futures::StreamExt;
+    use tokio_test::block_on;
+    use liquidity::db::EventMetadata;
+    use liquidity_test_utils::connection::MockConnection;
+    use crate::repository::ElectionRepository;
+    use crate::schema::{ElectionInput, ElectionFilter, ElectionOrder, ElectionOrderField, ElectionStatus, Importance, OrderDirection};
+    use super::ElectionIndex;
+
+    fn input(name: &str, importance: Importance, start_offset: i64) -> ElectionInput {
+        ElectionInput {
+            name: Some(name.to_string()),
+            importance: Some(importance),
+            start_date: Some(Utc::now() + Duration::hours(start_offset)),
+            end_date: Some(Utc::now() + Duration::hours(start_offset + 1)),
+            ..ElectionInput::default()
+        }
+    }
+
+    #[test]
+    fn follows_the_category() {
+        block_on(async {
+            let conn = MockConnection::default();
+            let repository = ElectionRepository::new(10, std::time::Duration::from_secs(600));
+
+            let first = repository.create_election(input("First", Importance::Minor, 0), "alice", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let second = repository.create_election(input("Second", Importance::Important, 2), "bob", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let deleted = repository.create_election(input("Deleted", Importance::Regular, 4), "bob", &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.update_election(&first.id, first.version, ElectionInput { description: Some("Budget".to_string()), ..ElectionInput::default() }, &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.delete_election(&deleted.id, deleted.version, None, "bob", &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.cast_vote(&first.id, "alice", vec!["test1".to_string()], &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            // An index of another instance only sees the writes through its subscription
+            let index = ElectionIndex::new();
+            assert!(index.search(&ElectionFilter::default(), &ElectionOrder::default()).is_empty());
+
+            index.projector().run(index.projector().subscribe(&conn).await.unwrap().take(6)).await.unwrap();
+            let all = index.search(&ElectionFilter::default(), &ElectionOrder::default());
+            assert_eq!(all.iter().map(|election| election.id).collect::<Vec<_>>(), vec![second.id, first.id]);
+            assert_eq!(all[1].description, "Budget");
+            assert_eq!(all[1].version, 1);
+        })
+    }
+
+    #[test]
+    fn shows_its_own_writes_before_the_projection() {
+        block_on(async {
+            let conn = MockConnection::default();
+            let index = Arc::new(ElectionIndex::new());
+            let repository = ElectionRepository::new(10, std::time::Duration::from_secs(600)).with_index(index.clone());
+            let names = || index.search(&ElectionFilter::default(), &ElectionOrder::default())
+                .into_iter().map(|election| election.name).collect::<Vec<_>>();
+
+            let kept = repository.create_election(input("Kept", Importance::Minor, 0), "alice", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let deleted = repository.create_election(input("Deleted", Importance::Minor, 2), "alice", &EventMetadata::system(), conn.clone()).await.unwrap();
+            assert_eq!(names(), vec!["Deleted", "Kept"]);
+
+            // The projection catching up with older events doesn't undo newer writes
+            let events = index.projector().subscribe(&conn).await.unwrap();
+            index.projector().run(events.take(1)).await.unwrap();
+            repository.update_election(&kept.id, kept.version, ElectionInput { name: Some("Renamed".to_string()), ..ElectionInput::default() }, &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.delete_election(&deleted.id, deleted.version, None, "alice", &EventMetadata::system(), conn.clone()).await.unwrap();
+            index.projector().run(index.projector().subscribe(&conn).await.unwrap().take(1)).await.unwrap();
+            assert_eq!(names(), vec!["Renamed"]);
+
+            index.projector().run(index.projector().subscribe(&conn).await.unwrap().take(2)).await.unwrap();
+            assert_eq!(names(), vec!["Renamed"]);
+            // Once the projection has the writes, including the deletion, they aren't kept around anymore
+            assert!(index.written.lock().unwrap().is_empty());
+        })
+    }
+
+    #[test]
+    fn filters_and_sorts() {
+        block_on(async {
+            let conn = MockConnection::default();
+            let repository = ElectionRepository::new(10, std::time::Duration::from_secs(600));
+
+            let minor = repository.create_election(input("Minor budget", Importance::Minor, 4), "alice", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let important = repository.create_election(input("Important", Importance::Important, 0), "bob", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let regular = repository.create_election(input("Regular BUDGET", Importance::Regular, 8), "bob", &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.transition_election(&regular.id, regular.version, &[ElectionStatus::Scheduled], &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            let index = ElectionIndex::new();
+            index.projector().run(index.projector().subscribe(&conn).await.unwrap().take(4)).await.unwrap();
+            let search = |filter: ElectionFilter, order: ElectionOrder| {
+                index.search(&filter, &order).into_iter().map(|election| election.id).collect::<Vec<_>>()
+            };
+            let by_importance = ElectionOrder { field: ElectionOrderField::Importance, direction: OrderDirection::Desc };
+            let by_start = ElectionOrder { field: ElectionOrderField::StartDate, direction: OrderDirection::Asc };
+
+            assert_eq!(search(ElectionFilter::default(), by_importance.clone()), vec![important.id, regular.id, minor.id]);
+            assert_eq!(search(ElectionFilter::default(), by_start.clone()), vec![important.id, minor.id, regular.id]);
+
+            let budget = ElectionFilter { search: Some("budget".to_string()), ..ElectionFilter::default() };
+            assert_eq!(search(budget, by_start.clone()), vec![minor.id, regular.id]);
+
+            let bob_drafts = ElectionFilter {
+                created_by_id: Some("bob".to_string()),
+                status: Some(vec![ElectionStatus::Draft]),
+                ..ElectionFilter::default()
+            };
+            assert_eq!(search(bob_drafts, by_start.clone()), vec![important.id]);
+
+            let range = ElectionFilter {
+                from: Some(Utc::now() + Duration::hours(2)),
+                to: Some(Utc::now() + Duration::hours(6)),
+                ..ElectionFilter::default()
+            };
+            assert_eq!(search(range, by_start.clone()), vec![minor.id]);
+        })
+    }
+}
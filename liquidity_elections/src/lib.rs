@@ -1,8 +1,17 @@
 #[macro_use] extern crate tracing;
 
+pub mod access;
+pub mod delegation;
+mod history;
+pub mod index;
+pub mod lifecycle;
 pub mod repository;
 pub mod resolvers;
 pub mod schema;
 mod models;
+pub mod projections;
+mod results;
+pub mod tally;
 
-pub use resolvers::ElectionResolvers;
\ No newline at end of file
+pub use resolvers::ElectionResolvers;
+pub use delegation::DelegationResolvers;
\ No newline at end of file
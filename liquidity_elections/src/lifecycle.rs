@@ -0,0 +1,77 @@
+//! The lifecycle of an election
+//!
+//! Elections start out as drafts, get published once they're ready, are opened for voting, closed,
+//! and finally certified. Any election that isn't certified yet can be cancelled. Every transition
+//! is recorded as an update event carrying the new status.
+
+use crate::schema::ElectionStatus;
+
+impl ElectionStatus {
+    /// Whether an election with this status may move to the next status
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use liquidity_elections::schema::ElectionStatus;
+    ///
+    /// assert!(ElectionStatus::Draft.can_transition_to(&ElectionStatus::Scheduled));
+    /// assert!(!ElectionStatus::Draft.can_transition_to(&ElectionStatus::Open));
+    /// ```
+    pub fn can_transition_to(&self, next: &ElectionStatus) -> bool {
+        use ElectionStatus::*;
+
+        matches!(
+            (self, next),
+            (Draft, Scheduled) | (Scheduled, Open) | (Open, Closed) | (Closed, Certified) |
+            (Draft, Cancelled) | (Scheduled, Cancelled) | (Open, Cancelled) | (Closed, Cancelled)
+        )
+    }
+
+    /// Whether voting has started, after which the choices and voting method are locked
+    pub fn has_started(&self) -> bool {
+        !matches!(self, ElectionStatus::Draft | ElectionStatus::Scheduled)
+    }
+
+    /// Whether voting has ended and the results are final. A cancelled election never ends, so its results
+    /// aren't final and hidden results stay hidden.
+    pub fn has_ended(&self) -> bool {
+        matches!(self, ElectionStatus::Closed | ElectionStatus::Certified)
+    }
+
+    /// Whether the election may still be edited
+    pub fn is_editable(&self) -> bool {
+        matches!(self, ElectionStatus::Draft | ElectionStatus::Scheduled | ElectionStatus::Open)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::schema::ElectionStatus::{self, *};
+
+    const ALL: [ElectionStatus; 6] = [Draft, Scheduled, Open, Closed, Certified, Cancelled];
+
+    #[test]
+    fn follows_the_lifecycle() {
+        let allowed: Vec<(ElectionStatus, ElectionStatus)> = ALL.iter()
+            .flat_map(|from| ALL.iter().map(move |to| (from.clone(), to.clone())))
+            .filter(|(from, to)| from.can_transition_to(to))
+            .collect();
+
+        assert_eq!(allowed, vec![
+            (Draft, Scheduled), (Draft, Cancelled),
+            (Scheduled, Open), (Scheduled, Cancelled),
+            (Open, Closed), (Open, Cancelled),
+            (Closed, Certified), (Closed, Cancelled)
+        ]);
+    }
+
+    #[test]
+    fn final_statuses_are_locked() {
+        assert!(!Certified.is_editable());
+        assert!(!Cancelled.is_editable());
+        assert!(!Closed.is_editable());
+        assert!(Open.is_editable() && Open.has_started());
+        assert!(Closed.has_ended() && Certified.has_ended());
+        assert!(!Cancelled.has_ended());
+    }
+}
@@ -1,34 +1,100 @@
 use chrono::{DateTime, Utc};
-use crate::schema::{Importance, Election};
-use serde::{Serialize, Deserialize};
+use crate::schema::{Importance, Election, VotingMethod, ElectionPermissions, PermissionSet, ElectionStatus};
+use serde::{Serialize, Deserialize, Deserializer};
+use serde_json::Value;
 use liquidity::{Uuid, Merge};
+use liquidity::db::{decode, Aggregate, DatabaseError, DomainEvent, EventMetadata, EventType, NewEvent, UnknownEvents, Upcasters, Versioned};
+use std::str::FromStr;
 
+#[derive(Debug)]
 pub(crate) enum ElectionEventType {
     Create,
-    Update
+    Update,
+    Delete,
+    Rename,
+    ChangeChoices,
+    MoveVotingWindow,
+    Vote
 }
 
 impl AsRef<str> for ElectionEventType {
     fn as_ref(&self) -> &str {
         match self {
-            ElectionEventType::Create => "election-create",
-            ElectionEventType::Update => "election-update"
+            // Elections were written with the generic event types before they had their own
+            ElectionEventType::Create => EventType::Create.as_ref(),
+            ElectionEventType::Update => EventType::Update.as_ref(),
+            ElectionEventType::Delete => EventType::Delete.as_ref(),
+            ElectionEventType::Rename => "election-renamed",
+            ElectionEventType::ChangeChoices => "election-choices-changed",
+            ElectionEventType::MoveVotingWindow => "election-voting-window-moved",
+            ElectionEventType::Vote => "election-vote"
         }
     }
 }
 
-impl From<String> for ElectionEventType {
-    fn from(s: String) -> Self {
-        match s.as_str() {
-            "election-create" => ElectionEventType::Create,
-            "election-update" => ElectionEventType::Update,
-            _ => ElectionEventType::Create
+impl FromStr for ElectionEventType {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(ElectionEventType::Create),
+            "update" => Ok(ElectionEventType::Update),
+            "delete" => Ok(ElectionEventType::Delete),
+            "election-renamed" => Ok(ElectionEventType::Rename),
+            "election-choices-changed" => Ok(ElectionEventType::ChangeChoices),
+            "election-voting-window-moved" => Ok(ElectionEventType::MoveVotingWindow),
+            "election-vote" => Ok(ElectionEventType::Vote),
+            _ => Err(DatabaseError::UnknownEventType(s.to_string()))
+        }
+    }
+}
+
+/// The events an election is folded from
+#[derive(Clone, Debug)]
+pub enum ElectionEvent {
+    Created(CreateElectionEvent),
+    /// A change to any number of fields, used for everything without a more specific event
+    Updated(UpdateElectionEvent),
+    Deleted(DeleteElectionEvent),
+    Renamed(ElectionRenamedEvent),
+    ChoicesChanged(ChoicesChangedEvent),
+    VotingWindowMoved(VotingWindowMovedEvent)
+}
+
+impl DomainEvent for ElectionEvent {
+    fn from_stored(event_type: &str, payload: Value, metadata: Option<&EventMetadata>) -> Result<Option<Self>, DatabaseError> {
+        let event_type = match event_type.parse::<ElectionEventType>() {
+            Ok(event_type) => event_type,
+            Err(_) => return Ok(None)
+        };
+
+        let event = match event_type {
+            ElectionEventType::Create => ElectionEvent::Created(decode(payload, metadata)?),
+            ElectionEventType::Update => ElectionEvent::Updated(decode(payload, metadata)?),
+            ElectionEventType::Delete => ElectionEvent::Deleted(decode(payload, metadata)?),
+            ElectionEventType::Rename => ElectionEvent::Renamed(decode(payload, metadata)?),
+            ElectionEventType::ChangeChoices => ElectionEvent::ChoicesChanged(decode(payload, metadata)?),
+            ElectionEventType::MoveVotingWindow => ElectionEvent::VotingWindowMoved(decode(payload, metadata)?),
+            // Votes live in their own stream
+            ElectionEventType::Vote => return Ok(None)
+        };
+        Ok(Some(event))
+    }
+
+    fn to_new_event(&self, metadata: &EventMetadata) -> Result<NewEvent, DatabaseError> {
+        match self {
+            ElectionEvent::Created(payload) => NewEvent::new(ElectionEventType::Create, payload, &metadata.versioned::<CreateElectionEvent>()),
+            ElectionEvent::Updated(payload) => NewEvent::new(ElectionEventType::Update, payload, &metadata.versioned::<UpdateElectionEvent>()),
+            ElectionEvent::Deleted(payload) => NewEvent::new(ElectionEventType::Delete, payload, &metadata.versioned::<DeleteElectionEvent>()),
+            ElectionEvent::Renamed(payload) => NewEvent::new(ElectionEventType::Rename, payload, &metadata.versioned::<ElectionRenamedEvent>()),
+            ElectionEvent::ChoicesChanged(payload) => NewEvent::new(ElectionEventType::ChangeChoices, payload, &metadata.versioned::<ChoicesChangedEvent>()),
+            ElectionEvent::VotingWindowMoved(payload) => NewEvent::new(ElectionEventType::MoveVotingWindow, payload, &metadata.versioned::<VotingWindowMovedEvent>())
         }
     }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
-pub(crate) struct CreateElectionEvent {
+pub struct CreateElectionEvent {
     pub id: Uuid,
     pub name: String,
     pub description: String,
@@ -36,7 +102,29 @@ pub(crate) struct CreateElectionEvent {
     pub end_date: DateTime<Utc>,
     pub importance: Importance,
     pub created_by_id: String,
-    pub choices: Vec<String>
+    pub choices: Vec<String>,
+    #[serde(default)]
+    pub voting_method: VotingMethod,
+    #[serde(default)]
+    pub hide_results: bool,
+    #[serde(default)]
+    pub permissions: ElectionPermissions,
+    pub status: ElectionStatus
+}
+
+impl Versioned for CreateElectionEvent {
+    /// Version 2 always stores the status
+    const SCHEMA_VERSION: u32 = 2;
+
+    fn upcasters() -> Upcasters {
+        Upcasters::new().register(1, |mut payload: Value| {
+            // Elections created before the lifecycle existed were opened right away
+            if payload["status"].is_null() {
+                payload["status"] = Value::from("Open");
+            }
+            payload
+        })
+    }
 }
 
 impl From<CreateElectionEvent> for Election {
@@ -48,13 +136,21 @@ impl From<CreateElectionEvent> for Election {
             start_date: e.start_date,
             end_date: e.end_date,
             importance: e.importance,
-            choices: e.choices
+            choices: e.choices,
+            voting_method: e.voting_method,
+            hide_results: e.hide_results,
+            created_by_id: e.created_by_id,
+            permissions: e.permissions,
+            status: e.status,
+            cancellation_reason: None,
+            certified_version: None,
+            version: 0
         }
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub(crate) struct UpdateElectionEvent {
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct UpdateElectionEvent {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub name: Option<String>,
@@ -72,7 +168,119 @@ pub(crate) struct UpdateElectionEvent {
     pub importance: Option<Importance>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
-    pub choices: Option<Vec<String>>
+    pub choices: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub voting_method: Option<VotingMethod>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub hide_results: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub permissions: Option<ElectionPermissions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub status: Option<ElectionStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub cancellation_reason: Option<String>,
+    /// Set by the event certifying the results, to the version of the election they were certified against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub certified_version: Option<i32>
+}
+
+impl Versioned for UpdateElectionEvent {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct DeleteElectionEvent {
+    pub deleted_by_id: String,
+    pub reason: Option<String>,
+    pub deleted_at: DateTime<Utc>
+}
+
+impl Versioned for DeleteElectionEvent {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ElectionRenamedEvent {
+    pub name: String
+}
+
+impl Versioned for ElectionRenamedEvent {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ChoicesChangedEvent {
+    pub choices: Vec<String>
+}
+
+impl Versioned for ChoicesChangedEvent {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct VotingWindowMovedEvent {
+    pub start_date: DateTime<Utc>,
+    pub end_date: DateTime<Utc>
+}
+
+impl Versioned for VotingWindowMovedEvent {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct VoteEvent {
+    pub voter_id: String,
+    /// Votes cast before voting methods existed stored a single `choice`
+    #[serde(alias = "choice", deserialize_with = "one_or_many")]
+    pub ballot: Vec<String>,
+    pub cast_at: DateTime<Utc>
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>)
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(choice) => vec![choice],
+        OneOrMany::Many(choices) => choices
+    })
+}
+
+impl Aggregate for Election {
+    type Event = ElectionEvent;
+
+    /// Events added by newer versions of the service shouldn't make elections unreadable during a rollout
+    const UNKNOWN_EVENTS: UnknownEvents = UnknownEvents::Skip;
+
+    fn apply(state: Option<Self>, event: ElectionEvent) -> Option<Self> {
+        let update = match event {
+            ElectionEvent::Created(created) => return Some(created.into()),
+            ElectionEvent::Deleted(_) => return None,
+            ElectionEvent::Updated(update) => update,
+            ElectionEvent::Renamed(renamed) => UpdateElectionEvent { name: Some(renamed.name), ..UpdateElectionEvent::default() },
+            ElectionEvent::ChoicesChanged(changed) => UpdateElectionEvent { choices: Some(changed.choices), ..UpdateElectionEvent::default() },
+            ElectionEvent::VotingWindowMoved(moved) => UpdateElectionEvent {
+                start_date: Some(moved.start_date),
+                end_date: Some(moved.end_date),
+                ..UpdateElectionEvent::default()
+            }
+        };
+        state.map(|election| election.merge_with(update))
+    }
+
+    fn set_version(&mut self, version: u64) {
+        self.version = version as i32;
+    }
 }
 
 impl Merge<UpdateElectionEvent> for Election {
@@ -82,9 +290,28 @@ impl Merge<UpdateElectionEvent> for Election {
             name: new.name.unwrap_or(self.name),
             description: new.description.unwrap_or(self.description),
             choices: new.choices.unwrap_or(self.choices),
+            voting_method: new.voting_method.unwrap_or(self.voting_method),
             start_date: new.start_date.unwrap_or(self.start_date),
             end_date: new.end_date.unwrap_or(self.end_date),
-            importance: new.importance.unwrap_or(self.importance)
+            importance: new.importance.unwrap_or(self.importance),
+            hide_results: new.hide_results.unwrap_or(self.hide_results),
+            created_by_id: self.created_by_id,
+            permissions: new.permissions.unwrap_or(self.permissions),
+            status: new.status.unwrap_or(self.status),
+            cancellation_reason: new.cancellation_reason.or(self.cancellation_reason),
+            certified_version: new.certified_version.or(self.certified_version),
+            version: self.version
+        }
+    }
+}
+
+impl Merge<PermissionSet> for ElectionPermissions {
+    fn merge_with(self, new: PermissionSet) -> Self {
+        ElectionPermissions {
+            view_roles: new.view_roles.unwrap_or(self.view_roles),
+            vote_roles: new.vote_roles.unwrap_or(self.vote_roles),
+            edit_roles: new.edit_roles.unwrap_or(self.edit_roles),
+            admin_roles: new.admin_roles.unwrap_or(self.admin_roles)
         }
     }
 }
\ No newline at end of file
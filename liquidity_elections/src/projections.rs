@@ -0,0 +1,134 @@
+//! Projections of the election streams, for read models that span every election
+
+use std::collections::HashMap;
+use liquidity::Uuid;
+use liquidity::db::{Aggregate, DatabaseError, DomainEvent, StoredEvent};
+use liquidity::projection::{Projection, Source};
+use serde_json::Value;
+use crate::models::ElectionEvent;
+use crate::schema::Election;
+
+/// The current state of every election, keyed by id. Deleted elections are removed.
+#[derive(Default)]
+pub struct ElectionList;
+
+impl Projection for ElectionList {
+    type State = HashMap<Uuid, Election>;
+
+    fn name(&self) -> &str {
+        "election-list"
+    }
+
+    fn source(&self) -> Source {
+        Source::Category("election".to_string())
+    }
+
+    /// Vote streams share the category, but their ids don't parse as an election id, so they're skipped
+    fn apply(&self, elections: &mut Self::State, event: &StoredEvent<Value>) -> Result<(), DatabaseError> {
+        let id = match Uuid::parse_str(event.stream_id.trim_start_matches("election-")) {
+            Ok(id) => id,
+            Err(_) => return Ok(())
+        };
+
+        // Every event of the stream comes through here, so counting them gives the number of the last one
+        let election = elections.remove(&id);
+        let version = election.as_ref().map_or(0, |election| election.version + 1);
+        let election = match ElectionEvent::from_stored(&event.event_type, event.payload.clone(), event.metadata.as_ref())? {
+            Some(event) => Election::apply(election, event),
+            None => election
+        };
+        if let Some(mut election) = election {
+            election.version = version;
+            elections.insert(id, election);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use chrono::{Duration, Utc};
+    use futures::StreamExt;
+    use tokio_test::block_on;
+    use liquidity::db::EventMetadata;
+    use liquidity::projection::{CheckpointStore, MemoryCheckpoints, Projector};
+    use liquidity_test_utils::connection::MockConnection;
+    use crate::repository::ElectionRepository;
+    use crate::schema::ElectionInput;
+    use super::ElectionList;
+
+    fn input(name: &str) -> ElectionInput {
+        ElectionInput {
+            name: Some(name.to_string()),
+            start_date: Some(Utc::now()),
+            end_date: Some(Utc::now() + Duration::days(7)),
+            ..ElectionInput::default()
+        }
+    }
+
+    #[test]
+    fn builds_from_the_category() {
+        block_on(async {
+            let conn = MockConnection::default();
+            let repository = ElectionRepository::new(10, std::time::Duration::from_secs(600));
+
+            let first = repository.create_election(input("First"), "alice", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let deleted = repository.create_election(input("Deleted"), "bob", &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.delete_election(&deleted.id, deleted.version, None, "bob", &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.cast_vote(&first.id, "alice", vec!["test1".to_string()], &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            let projector = Projector::new(ElectionList, Arc::new(MemoryCheckpoints::new()));
+            let events = projector.subscribe(&conn).await.unwrap();
+            projector.run(events.take(4)).await.unwrap();
+
+            assert_eq!(projector.read(|elections| elections.keys().cloned().collect::<Vec<_>>()), vec![first.id]);
+            assert_eq!(projector.position(), Some(3));
+        })
+    }
+
+    #[test]
+    fn continues_from_the_checkpoint() {
+        block_on(async {
+            let conn = MockConnection::default();
+            let repository = ElectionRepository::new(10, std::time::Duration::from_secs(600));
+            let store: Arc<dyn CheckpointStore> = Arc::new(MemoryCheckpoints::new());
+
+            let first = repository.create_election(input("First"), "alice", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let projector = Projector::new(ElectionList, store.clone());
+            projector.run(projector.subscribe(&conn).await.unwrap().take(1)).await.unwrap();
+
+            repository.update_election(&first.id, first.version, input("Renamed"), &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            let restarted = Projector::new(ElectionList, store.clone());
+            restarted.load().await.unwrap();
+            assert_eq!(restarted.position(), Some(0));
+            assert_eq!(restarted.read(|elections| elections[&first.id].name.clone()), "First");
+
+            restarted.run(restarted.subscribe(&conn).await.unwrap().take(1)).await.unwrap();
+            assert_eq!(restarted.position(), Some(1));
+            assert_eq!(restarted.read(|elections| elections[&first.id].name.clone()), "Renamed");
+        })
+    }
+
+    #[test]
+    fn rebuilds_from_scratch() {
+        block_on(async {
+            let conn = MockConnection::default();
+            let repository = ElectionRepository::new(10, std::time::Duration::from_secs(600));
+            let store: Arc<dyn CheckpointStore> = Arc::new(MemoryCheckpoints::new());
+
+            repository.create_election(input("First"), "alice", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let projector = Projector::new(ElectionList, store.clone()).with_checkpoint_interval(10);
+            projector.run(projector.subscribe(&conn).await.unwrap().take(1)).await.unwrap();
+
+            projector.rebuild().await.unwrap();
+            assert_eq!(projector.position(), None);
+            assert!(projector.read(|elections| elections.is_empty()));
+            assert_eq!(store.load("election-list").await.unwrap(), None);
+
+            projector.run(projector.subscribe(&conn).await.unwrap().take(1)).await.unwrap();
+            assert_eq!(projector.read(|elections| elections.len()), 1);
+        })
+    }
+}
@@ -1,20 +1,35 @@
-use chrono::Utc;
-use crate::schema::{Election, Importance::Regular, ElectionInput};
+use chrono::{DateTime, Utc};
+use crate::schema::{Election, Importance::Regular, ElectionInput, Vote, ElectionPermissions, ElectionStatus, ElectionFilter, ElectionOrder};
+use crate::index::ElectionIndex;
+use crate::history::{self, ElectionHistory};
 use super::models::CreateElectionEvent;
 use liquidity::{Uuid, Merge};
-use crate::models::{UpdateElectionEvent};
-use liquidity::db::{DatabaseError, DbConnection};
+use crate::models::{
+    UpdateElectionEvent, DeleteElectionEvent, VoteEvent, ElectionEventType, ElectionEvent, ElectionRenamedEvent, ChoicesChangedEvent,
+    VotingWindowMovedEvent
+};
+use liquidity::db::{Aggregate, DatabaseError, DbConnection, DomainEvent, EventMetadata, ExpectedVersion, SnapshotPolicy};
 use futures::lock::Mutex;
 use std::sync::Arc;
 use std::fmt;
 use ttl_cache::TtlCache;
 use std::time::Duration;
+use std::collections::HashMap;
+use serde_json::Value;
 
 type Cache = Arc<Mutex<TtlCache<Uuid, Election>>>;
 
+/// The number of events folded into an election before a new snapshot is stored
+const DEFAULT_SNAPSHOT_INTERVAL: u64 = 50;
+/// The version of the shape of `Election` in snapshots. Bump it whenever `Election` or the way events
+/// are folded into it changes, so the old snapshots are ignored.
+const SNAPSHOT_SCHEMA_VERSION: u32 = 3;
+
 pub struct ElectionRepository {
     cache: Cache,
-    time_to_live: Duration
+    time_to_live: Duration,
+    index: Arc<ElectionIndex>,
+    snapshots: SnapshotPolicy
 }
 
 impl fmt::Debug for ElectionRepository {
@@ -27,10 +42,27 @@ impl ElectionRepository {
     pub fn new(cache_capacity: usize, time_to_live: Duration) -> ElectionRepository {
         ElectionRepository {
             cache: Arc::new(Mutex::new(TtlCache::new(cache_capacity))),
-            time_to_live
+            time_to_live,
+            index: Arc::new(ElectionIndex::new()),
+            snapshots: SnapshotPolicy::new(DEFAULT_SNAPSHOT_INTERVAL, SNAPSHOT_SCHEMA_VERSION)
         }
     }
 
+    /// Set the number of events to fold into an election before storing a new snapshot of it. 0 disables snapshots
+    pub fn with_snapshot_interval(mut self, interval: u64) -> ElectionRepository {
+        self.snapshots.interval = interval;
+        self
+    }
+
+    /// Use an index of the elections that's shared with whatever keeps it up to date, see `ElectionIndex::run`
+    pub fn with_index(mut self, index: Arc<ElectionIndex>) -> ElectionRepository {
+        self.index = index;
+        self
+    }
+
+    /// The index elections are searched in
+    pub fn index(&self) -> Arc<ElectionIndex> { self.index.clone() }
+
     /// Create a new election in the database
     ///
     /// This inserts a new election given an election input and the ID of the user creating it.
@@ -40,6 +72,7 @@ impl ElectionRepository {
     ///
     /// * `election` - The input object with the user input data for the new election
     /// * `creator_id` - The id of the user calling the creation function
+    /// * `metadata` - The metadata to attach to the written events
     /// * `conn` - The database connection to execute the insert on
     ///
     /// # Example
@@ -48,26 +81,30 @@ impl ElectionRepository {
     /// # futures::executor::block_on(async {
     /// # use liquidity::{Connection, Credentials, Uuid};
     /// # use liquidity_elections::{repository::ElectionRepository, schema::ElectionInput};
+    /// # use liquidity::db::EventMetadata;
     /// # use liquidity_test_utils::connection::MockConnection;
-    /// # use std::time::Duration;
+    /// # use std::time::Duration as StdDuration;
+    /// use chrono::{Duration, Utc};
     /// # let conn = MockConnection::default();
-    /// # let repository = ElectionRepository::new(0, Duration::from_secs(0));
+    /// # let repository = ElectionRepository::new(0, StdDuration::from_secs(0));
     ///
     /// let election_input = ElectionInput {
     ///     name: Some("test_name".to_string()),
     ///     description: Some("This is a test description".to_string()),
+    ///     start_date: Some(Utc::now()),
+    ///     end_date: Some(Utc::now() + Duration::days(7)),
     ///     choices: Some(vec!["test1".to_string(), "test2".to_string()]),
     ///     ..ElectionInput::default()
     /// };
     ///
-    /// let result = repository.create_election(election_input, "auth0|test", conn)
+    /// let result = repository.create_election(election_input, "auth0|test", &EventMetadata::system(), conn)
     ///     .await.unwrap();
     ///
     /// assert_eq!(result.name, "test_name".to_string());
     /// # })
     /// ```
     #[instrument(skip(conn))]
-    pub async fn create_election<T: DbConnection>(&self, election: ElectionInput, creator_id: &str, conn: T) -> Result<Election, DatabaseError> {
+    pub async fn create_election<T: DbConnection>(&self, election: ElectionInput, creator_id: &str, metadata: &EventMetadata, conn: T) -> Result<Election, DatabaseError> {
         let id = Uuid::new_v4();
         let stream_id = format!("election-{}", id);
 
@@ -76,14 +113,20 @@ impl ElectionRepository {
             created_by_id: creator_id.to_string(),
             name: election.name.unwrap(),
             description: election.description.unwrap_or_else(|| "".to_string()),
-            start_date: election.start_date.unwrap_or_else(Utc::now),
-            end_date: election.end_date.unwrap_or_else(Utc::now),
+            start_date: election.start_date.unwrap(),
+            end_date: election.end_date.unwrap(),
             importance: election.importance.unwrap_or(Regular),
-            choices: election.choices.unwrap_or_else(|| vec![])
+            choices: election.choices.unwrap_or_else(|| vec![]),
+            voting_method: election.voting_method.unwrap_or_default(),
+            hide_results: election.hide_results.unwrap_or(false),
+            permissions: election.permissions
+                .map(|permissions| ElectionPermissions::default().merge_with(permissions))
+                .unwrap_or_default(),
+            status: ElectionStatus::Draft
         };
 
         let result = conn
-            .create(stream_id, event_data.clone())
+            .create(stream_id, event_data.clone(), metadata)
             .await;
 
         match &result {
@@ -94,6 +137,7 @@ impl ElectionRepository {
         result?;
 
         let election: Election = event_data.into();
+        self.index.put(&election);
 
         Ok(election)
     }
@@ -101,12 +145,15 @@ impl ElectionRepository {
     /// Update an election in the database
     ///
     /// This update an election in the database given an election input and the ID of the election.
-    /// This will error if the stream doesn't exist or the update fails because of connection errors.
+    /// This will error if the stream doesn't exist, the election was changed since `expected_version`
+    /// or the update fails because of connection errors.
     ///
     /// # Arguments
     ///
     /// * `id` - The id of the election to be updated
+    /// * `expected_version` - The version of the election the update is based on
     /// * `election` - The input object with the fields to update
+    /// * `metadata` - The metadata to attach to the written events
     /// * `conn` - The database connection to execute the update on
     ///
     /// # Example
@@ -117,6 +164,7 @@ impl ElectionRepository {
     /// futures::executor::block_on(async {
     /// # use liquidity::{Connection, Credentials, Uuid};
     /// # use liquidity_elections::{repository::ElectionRepository, schema::ElectionInput};
+    /// # use liquidity::db::EventMetadata;
     /// # use liquidity_test_utils::connection::MockConnection;
     /// # use std::time::Duration;
     /// # let conn = MockConnection::default();
@@ -128,31 +176,193 @@ impl ElectionRepository {
     /// };
     ///
     /// let id = Uuid::new_v4();
-    /// let result = repository.update_election(&id, election_input, conn).await;
+    /// let result = repository.update_election(&id, 0, election_input, &EventMetadata::system(), conn).await;
     ///
     /// assert!(result.is_err())
     /// # })
     /// ```
     #[instrument(skip(conn))]
-    pub async fn update_election<T: DbConnection>(&self, id: &Uuid, input: ElectionInput, conn: T) -> Result<Election, DatabaseError> {
-        let stream_id = format!("election-{}", id);
-
+    pub async fn update_election<T: DbConnection>(
+        &self,
+        id: &Uuid,
+        expected_version: i32,
+        input: ElectionInput,
+        metadata: &EventMetadata,
+        conn: T
+    ) -> Result<Election, DatabaseError> {
         let original = self.find_election(id, conn.clone()).await?
             .ok_or(DatabaseError::NotFound)?;
 
         if input.eq(&ElectionInput::default()) { return Ok(original) }
 
-        let event_data = UpdateElectionEvent {
-            name: input.name,
+        let mut events = Vec::new();
+        if let Some(name) = input.name {
+            events.push(ElectionEvent::Renamed(ElectionRenamedEvent { name }));
+        }
+        if let Some(choices) = input.choices {
+            events.push(ElectionEvent::ChoicesChanged(ChoicesChangedEvent { choices }));
+        }
+        if input.start_date.is_some() || input.end_date.is_some() {
+            events.push(ElectionEvent::VotingWindowMoved(VotingWindowMovedEvent {
+                start_date: input.start_date.unwrap_or(original.start_date),
+                end_date: input.end_date.unwrap_or(original.end_date)
+            }));
+        }
+
+        let update = UpdateElectionEvent {
             description: input.description,
-            choices: input.choices,
-            start_date: input.start_date,
-            end_date: input.end_date,
-            importance: input.importance
+            voting_method: input.voting_method,
+            importance: input.importance,
+            hide_results: input.hide_results,
+            permissions: input.permissions.map(|permissions| original.permissions.clone().merge_with(permissions)),
+            ..UpdateElectionEvent::default()
         };
+        if update != UpdateElectionEvent::default() {
+            events.push(ElectionEvent::Updated(update));
+        }
+
+        self.append_events(original, expected_version, events, metadata, conn).await
+    }
+
+    /// Move an election through one or more statuses in its lifecycle
+    ///
+    /// Every step is recorded as its own event, but they're written atomically so the election never
+    /// gets stuck halfway. This doesn't check whether the transitions are allowed, that's up to the caller.
+    /// Opening an election moves its start date to now, and closing it moves its end date to now.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the election
+    /// * `expected_version` - The version of the election the transitions were checked against
+    /// * `statuses` - The statuses to move the election through, in order
+    /// * `metadata` - The metadata to attach to the written events
+    /// * `conn` - The database connection to execute the update on
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use liquidity_elections::{repository::ElectionRepository, schema::{ElectionInput, ElectionStatus}};
+    /// # use liquidity::db::EventMetadata;
+    /// # use liquidity_test_utils::connection::MockConnection;
+    /// # use std::time::Duration;
+    /// # let conn = MockConnection::default();
+    /// # let repository = ElectionRepository::new(0, Duration::from_secs(0));
+    /// # let input = ElectionInput { name: Some("test_name".to_string()), start_date: Some(chrono::Utc::now()), end_date: Some(chrono::Utc::now() + chrono::Duration::days(7)), ..ElectionInput::default() };
+    /// # let election = repository.create_election(input, "auth0|test", &EventMetadata::system(), conn.clone()).await.unwrap();
+    ///
+    /// let published = repository.transition_election(&election.id, election.version, &[ElectionStatus::Scheduled], &EventMetadata::system(), conn)
+    ///     .await.unwrap();
+    ///
+    /// assert_eq!(published.status, ElectionStatus::Scheduled);
+    /// # })
+    /// ```
+    #[instrument(skip(conn))]
+    pub async fn transition_election<T: DbConnection>(
+        &self,
+        id: &Uuid,
+        expected_version: i32,
+        statuses: &[ElectionStatus],
+        metadata: &EventMetadata,
+        conn: T
+    ) -> Result<Election, DatabaseError> {
+        let original = self.find_election(id, conn.clone()).await?
+            .ok_or(DatabaseError::NotFound)?;
+
+        let now = Utc::now();
+        let events = statuses.iter()
+            .zip(expected_version..)
+            .map(|(status, version)| ElectionEvent::Updated(UpdateElectionEvent {
+                start_date: if *status == ElectionStatus::Open { Some(now) } else { None },
+                end_date: if *status == ElectionStatus::Closed { Some(now) } else { None },
+                status: Some(status.clone()),
+                // The results are certified against the election as it was right before
+                certified_version: if *status == ElectionStatus::Certified { Some(version) } else { None },
+                ..UpdateElectionEvent::default()
+            }))
+            .collect();
+
+        self.append_events(original, expected_version, events, metadata, conn).await
+    }
+
+    /// Cancel an election
+    ///
+    /// The election stays readable with a `Cancelled` status, but can't be voted in or edited anymore.
+    /// This will error if the stream doesn't exist, the election was changed since `expected_version`
+    /// or the update fails because of connection errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the election to cancel
+    /// * `expected_version` - The version of the election the cancellation was checked against
+    /// * `reason` - The reason for cancelling the election, shown to users
+    /// * `metadata` - The metadata to attach to the written events
+    /// * `conn` - The database connection to execute the update on
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use liquidity_elections::{repository::ElectionRepository, schema::{ElectionInput, ElectionStatus}};
+    /// # use liquidity::db::EventMetadata;
+    /// # use liquidity_test_utils::connection::MockConnection;
+    /// # use std::time::Duration;
+    /// # let conn = MockConnection::default();
+    /// # let repository = ElectionRepository::new(0, Duration::from_secs(0));
+    /// # let input = ElectionInput { name: Some("test_name".to_string()), start_date: Some(chrono::Utc::now()), end_date: Some(chrono::Utc::now() + chrono::Duration::days(7)), ..ElectionInput::default() };
+    /// # let election = repository.create_election(input, "auth0|test", &EventMetadata::system(), conn.clone()).await.unwrap();
+    ///
+    /// let reason = Some("Postponed".to_string());
+    /// let cancelled = repository.cancel_election(&election.id, election.version, reason, &EventMetadata::system(), conn).await.unwrap();
+    ///
+    /// assert_eq!(cancelled.status, ElectionStatus::Cancelled);
+    /// # })
+    /// ```
+    #[instrument(skip(conn))]
+    pub async fn cancel_election<T: DbConnection>(
+        &self,
+        id: &Uuid,
+        expected_version: i32,
+        reason: Option<String>,
+        metadata: &EventMetadata,
+        conn: T
+    ) -> Result<Election, DatabaseError> {
+        let original = self.find_election(id, conn.clone()).await?
+            .ok_or(DatabaseError::NotFound)?;
+
+        let event_data = UpdateElectionEvent {
+            status: Some(ElectionStatus::Cancelled),
+            cancellation_reason: reason,
+            ..UpdateElectionEvent::default()
+        };
+
+        self.append_events(original, expected_version, vec![ElectionEvent::Updated(event_data)], metadata, conn).await
+    }
+
+    /// Append events to an election, as long as nobody else changed it since `expected_version`.
+    /// The cached election is dropped either way, so a conflicting write is picked up on the next read.
+    async fn append_events<T: DbConnection>(
+        &self,
+        original: Election,
+        expected_version: i32,
+        events: Vec<ElectionEvent>,
+        metadata: &EventMetadata,
+        conn: T
+    ) -> Result<Election, DatabaseError> {
+        let stream_id = format!("election-{}", original.id);
+
+        let mut cache = self.cache.lock().await;
+        cache.remove(&original.id);
+        drop(cache);
+
+        if original.version != expected_version { return Err(DatabaseError::WrongExpectedVersion(stream_id)) }
+
+        let new_events = events.iter()
+            .map(|event| event.to_new_event(metadata))
+            .collect::<Result<Vec<_>, _>>()?;
 
         let result = conn
-            .update(stream_id, event_data.clone())
+            .write_events(stream_id, new_events, ExpectedVersion::Exact(expected_version as u64))
             .await;
 
         match &result {
@@ -162,10 +372,94 @@ impl ElectionRepository {
 
         result?;
 
+        let version = expected_version + events.len() as i32;
+        let mut election = events.into_iter()
+            .fold(Some(original), Election::apply)
+            .ok_or(DatabaseError::NotFound)?;
+        election.version = version;
+        self.index.put(&election);
+
+        Ok(election)
+    }
+
+    /// Delete an election
+    ///
+    /// This appends a delete event to the election stream, after which the election can't be found anymore.
+    /// This will error if the stream doesn't exist, the election was changed since `expected_version`
+    /// or the delete fails because of connection errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the election to delete
+    /// * `expected_version` - The version of the election the deletion was checked against
+    /// * `reason` - The reason for deleting the election, kept for auditing
+    /// * `deleted_by_id` - The id of the user deleting the election
+    /// * `metadata` - The metadata to attach to the written events
+    /// * `conn` - The database connection to execute the delete on
+    ///
+    /// # Returns
+    ///
+    /// The election as it was before it was deleted
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use liquidity_elections::{repository::ElectionRepository, schema::ElectionInput};
+    /// # use liquidity::db::EventMetadata;
+    /// # use liquidity_test_utils::connection::MockConnection;
+    /// # use std::time::Duration;
+    /// # let conn = MockConnection::default();
+    /// # let repository = ElectionRepository::new(0, Duration::from_secs(0));
+    /// # let input = ElectionInput { name: Some("test_name".to_string()), start_date: Some(chrono::Utc::now()), end_date: Some(chrono::Utc::now() + chrono::Duration::days(7)), ..ElectionInput::default() };
+    /// # let election = repository.create_election(input, "auth0|test", &EventMetadata::system(), conn.clone()).await.unwrap();
+    ///
+    /// repository.delete_election(&election.id, election.version, None, "auth0|test", &EventMetadata::system(), conn.clone()).await.unwrap();
+    ///
+    /// assert_eq!(repository.find_election(&election.id, conn).await.unwrap(), None);
+    /// # })
+    /// ```
+    #[instrument(skip(conn))]
+    pub async fn delete_election<T: DbConnection>(
+        &self,
+        id: &Uuid,
+        expected_version: i32,
+        reason: Option<String>,
+        deleted_by_id: &str,
+        metadata: &EventMetadata,
+        conn: T
+    ) -> Result<Election, DatabaseError> {
+        let stream_id = format!("election-{}", id);
+
+        let original = self.find_election(id, conn.clone()).await?
+            .ok_or(DatabaseError::NotFound)?;
+
         let mut cache = self.cache.lock().await;
         cache.remove(id);
+        drop(cache);
 
-        Ok(original.merge_with(event_data))
+        if original.version != expected_version { return Err(DatabaseError::WrongExpectedVersion(stream_id)) }
+
+        let event = ElectionEvent::Deleted(DeleteElectionEvent {
+            deleted_by_id: deleted_by_id.to_string(),
+            reason,
+            deleted_at: Utc::now()
+        });
+
+        let result = conn
+            .write_events(stream_id, vec![event.to_new_event(metadata)?], ExpectedVersion::Exact(expected_version as u64))
+            .await;
+
+        match &result {
+            Ok(event_data) => debug!("{:?}", event_data),
+            Err(e) => error!("{:?}", e)
+        };
+
+        result?;
+
+        self.index.remove(id, expected_version + 1);
+
+        Ok(original)
     }
 
     /// Find an election by its id
@@ -208,7 +502,7 @@ impl ElectionRepository {
                 let stream_id = format!("election-{}", id);
 
                 let result = conn
-                    .read::<_, Election, CreateElectionEvent, UpdateElectionEvent>(stream_id)
+                    .read_with_snapshots::<_, Election>(stream_id, &self.snapshots)
                     .await?;
 
                 if let Some(ref election) = result {
@@ -219,19 +513,245 @@ impl ElectionRepository {
             }
         }
     }
+
+    /// Find an election as it was at a point in time
+    ///
+    /// This always reads from the database, the cache only holds the latest version of an election.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the election
+    /// * `as_of` - The point in time to read the election at
+    /// * `conn` - The database connection
+    ///
+    /// # Returns
+    ///
+    /// The election, None if it didn't exist at that time, or the database error if one occurred
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use chrono::{Duration as ChronoDuration, Utc};
+    /// # use liquidity::db::EventMetadata;
+    /// # use liquidity_elections::{repository::ElectionRepository, schema::ElectionInput};
+    /// # use liquidity_test_utils::connection::MockConnection;
+    /// # use std::time::Duration;
+    /// # let conn = MockConnection::default();
+    /// # let repository = ElectionRepository::new(0, Duration::from_secs(0));
+    /// # let input = ElectionInput { name: Some("test_name".to_string()), start_date: Some(chrono::Utc::now()), end_date: Some(chrono::Utc::now() + chrono::Duration::days(7)), ..ElectionInput::default() };
+    /// # let election = repository.create_election(input, "auth0|test", &EventMetadata::system(), conn.clone()).await.unwrap();
+    ///
+    /// let yesterday = Utc::now() - ChronoDuration::days(1);
+    /// let election = repository.find_election_as_of(&election.id, yesterday, conn).await.unwrap();
+    ///
+    /// assert_eq!(election, None);
+    /// # })
+    /// ```
+    #[instrument(skip(conn))]
+    pub async fn find_election_as_of<T: DbConnection>(&self, id: &Uuid, as_of: DateTime<Utc>, conn: T) -> Result<Option<Election>, DatabaseError> {
+        let stream_id = format!("election-{}", id);
+
+        conn.read_as_of::<_, Election>(stream_id, as_of).await
+    }
+
+    /// Find a specific version of an election
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the election
+    /// * `version` - The version of the election, as in `Election::version`
+    /// * `conn` - The database connection
+    ///
+    /// # Returns
+    ///
+    /// The election at that version, the latest version if it has fewer changes, None if it doesn't exist,
+    /// or the database error if one occurred
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use liquidity::db::EventMetadata;
+    /// # use liquidity_elections::{repository::ElectionRepository, schema::ElectionInput};
+    /// # use liquidity_test_utils::connection::MockConnection;
+    /// # use std::time::Duration;
+    /// # let conn = MockConnection::default();
+    /// # let repository = ElectionRepository::new(0, Duration::from_secs(0));
+    /// # let input = ElectionInput { name: Some("test_name".to_string()), start_date: Some(chrono::Utc::now()), end_date: Some(chrono::Utc::now() + chrono::Duration::days(7)), ..ElectionInput::default() };
+    /// # let election = repository.create_election(input, "auth0|test", &EventMetadata::system(), conn.clone()).await.unwrap();
+    /// let rename = ElectionInput { name: Some("renamed".to_string()), ..ElectionInput::default() };
+    /// repository.update_election(&election.id, election.version, rename, &EventMetadata::system(), conn.clone()).await.unwrap();
+    ///
+    /// let original = repository.find_election_at_version(&election.id, 0, conn).await.unwrap().unwrap();
+    ///
+    /// assert_eq!(original.name, "test_name".to_string());
+    /// # })
+    /// ```
+    #[instrument(skip(conn))]
+    pub async fn find_election_at_version<T: DbConnection>(&self, id: &Uuid, version: i32, conn: T) -> Result<Option<Election>, DatabaseError> {
+        let stream_id = format!("election-{}", id);
+
+        conn.read_at_version::<_, Election>(stream_id, version as u64).await
+    }
+
+    /// Find every election matching a filter
+    ///
+    /// This uses the in-memory index instead of reading every election stream, so it doesn't check
+    /// the permissions of the elections, that's up to the caller. Elections written by other instances
+    /// only show up while the index is running.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter the elections have to match
+    /// * `order` - The order to sort the elections in
+    ///
+    /// # Returns
+    ///
+    /// The sorted elections
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use liquidity_elections::{repository::ElectionRepository, schema::{ElectionInput, ElectionFilter, ElectionOrder}};
+    /// # use liquidity::db::EventMetadata;
+    /// # use liquidity_test_utils::connection::MockConnection;
+    /// # use std::time::Duration;
+    /// # let conn = MockConnection::default();
+    /// # let repository = ElectionRepository::new(0, Duration::from_secs(0));
+    /// # let input = ElectionInput { name: Some("Budget 2020".to_string()), start_date: Some(chrono::Utc::now()), end_date: Some(chrono::Utc::now() + chrono::Duration::days(7)), ..ElectionInput::default() };
+    /// # repository.create_election(input, "auth0|test", &EventMetadata::system(), conn.clone()).await.unwrap();
+    ///
+    /// let filter = ElectionFilter { search: Some("budget".to_string()), ..ElectionFilter::default() };
+    /// let elections = repository.find_elections(&filter, &ElectionOrder::default());
+    ///
+    /// assert_eq!(elections[0].name, "Budget 2020".to_string());
+    /// # })
+    /// ```
+    #[instrument]
+    pub fn find_elections(&self, filter: &ElectionFilter, order: &ElectionOrder) -> Vec<Election> {
+        self.index.search(filter, order)
+    }
+
+    /// Cast a vote in an election
+    ///
+    /// This appends a vote event to the election's vote stream. It doesn't validate the vote,
+    /// that's up to the caller. If a user votes more than once, only the latest vote counts.
+    ///
+    /// # Arguments
+    ///
+    /// * `election_id` - The id of the election to vote in
+    /// * `voter_id` - The id of the user casting the vote
+    /// * `ballot` - The choices on the ballot
+    /// * `metadata` - The metadata to attach to the written events
+    /// * `conn` - The database connection to execute the insert on
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use liquidity::Uuid;
+    /// # use liquidity_elections::repository::ElectionRepository;
+    /// # use liquidity::db::EventMetadata;
+    /// # use liquidity_test_utils::connection::MockConnection;
+    /// # use std::time::Duration;
+    /// # let conn = MockConnection::default();
+    /// # let repository = ElectionRepository::new(0, Duration::from_secs(0));
+    ///
+    /// let election_id = Uuid::new_v4();
+    ///
+    /// let vote = repository.cast_vote(&election_id, "auth0|test", vec!["test1".to_string()], &EventMetadata::system(), conn)
+    ///     .await.unwrap();
+    ///
+    /// assert_eq!(vote.ballot, vec!["test1".to_string()]);
+    /// # })
+    /// ```
+    #[instrument(skip(conn))]
+    pub async fn cast_vote<T: DbConnection>(&self, election_id: &Uuid, voter_id: &str, ballot: Vec<String>, metadata: &EventMetadata, conn: T) -> Result<Vote, DatabaseError> {
+        let stream_id = format!("election-{}-votes", election_id);
+
+        let event_data = VoteEvent {
+            voter_id: voter_id.to_string(),
+            ballot,
+            cast_at: Utc::now()
+        };
+
+        let result = conn
+            .write_event(stream_id, ElectionEventType::Vote, event_data.clone(), metadata, ExpectedVersion::Any)
+            .await;
+
+        match &result {
+            Ok(event_data) => debug!("{:?}", event_data),
+            Err(e) => error!("{:?}", e)
+        };
+
+        result?;
+
+        Ok(Vote {
+            election_id: election_id.to_owned(),
+            ballot: event_data.ballot,
+            cast_at: event_data.cast_at
+        })
+    }
+
+    /// Find the history of an election, including elections that were deleted
+    ///
+    /// This reads the raw events of the election instead of the cached state, so every change shows up.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the election
+    /// * `conn` - The database connection
+    ///
+    /// # Returns
+    ///
+    /// The history of the election, None if it never existed
+    #[instrument(skip(conn))]
+    pub(crate) async fn find_history<T: DbConnection>(&self, id: &Uuid, conn: T) -> Result<Option<ElectionHistory>, DatabaseError> {
+        let stream_id = format!("election-{}", id);
+        let events = conn.read_all::<_, Value>(stream_id).await?;
+
+        history::build(events)
+    }
+
+    /// Find the votes cast in an election
+    ///
+    /// # Arguments
+    ///
+    /// * `election_id` - The id of the election
+    /// * `conn` - The database connection
+    ///
+    /// # Returns
+    ///
+    /// A map of voter ids to the latest vote they cast
+    #[instrument(skip(conn))]
+    pub(crate) async fn find_votes<T: DbConnection>(&self, election_id: &Uuid, conn: T) -> Result<HashMap<String, VoteEvent>, DatabaseError> {
+        let stream_id = format!("election-{}-votes", election_id);
+
+        let votes = conn.read_all::<_, VoteEvent>(stream_id).await?
+            .into_iter()
+            .map(|event| (event.payload.voter_id.to_owned(), event.payload))
+            .collect();
+
+        Ok(votes)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::sync::Arc;
     use tokio_test::block_on;
-    use crate::schema::{ElectionInput, Importance, Election};
-    use liquidity::db::EventType;
-    use crate::models::{UpdateElectionEvent, CreateElectionEvent};
+    use chrono::Utc;
+    use crate::schema::{ElectionInput, Importance, Election, ElectionStatus};
+    use liquidity::db::{
+        DatabaseError, DbConnection, EventMetadata, EventType, ExpectedVersion, NewEvent, Snapshot, Versioned
+    };
+    use crate::models::{UpdateElectionEvent, CreateElectionEvent, VoteEvent, ElectionEventType};
     use crate::repository::ElectionRepository;
     use std::time::Duration;
     use liquidity_test_utils::connection::MockConnection;
-    use serde_json::Value;
+    use liquidity::Uuid;
 
     fn conn() -> MockConnection {
         MockConnection::default()
@@ -246,6 +766,8 @@ mod test {
             name: Some("test_name".to_string()),
             description: Some("test_description".to_string()),
             choices: Some(vec!["test1".to_string(), "test2".to_string()]),
+            start_date: Some(Utc::now()),
+            end_date: Some(Utc::now() + chrono::Duration::days(7)),
             ..ElectionInput::default()
         }
     }
@@ -263,7 +785,7 @@ mod test {
             let conn = conn();
             let repository = repository();
 
-            let election = repository.create_election(test_election_input(), "test_creator_id", conn.clone())
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone())
                 .await
                 .expect("Creating the election shouldn't fail");
 
@@ -272,12 +794,11 @@ mod test {
             assert_eq!(election.choices, vec!["test1".to_string(), "test2".to_string()]);
             assert_eq!(election.importance, Importance::Regular);
 
-            let sent = conn.data.lock().unwrap();
             let stream_id = format!("election-{}", election.id);
-            let (event_type, value): (EventType, Value) = sent[&stream_id][0].clone();
-            let payload: CreateElectionEvent = serde_json::from_value(value).expect("The event payload should have the right type");
+            let NewEvent { event_type, payload, .. } = conn.events(&stream_id)[0].clone();
+            let payload: CreateElectionEvent = serde_json::from_value(payload).expect("The event payload should have the right type");
 
-            assert_eq!(event_type, EventType::Create);
+            assert_eq!(event_type, EventType::Create.as_ref());
             assert_eq!(payload.id, election.id);
             assert_eq!(payload.description, "test_description");
             assert_eq!(payload.choices, vec!["test1".to_string(), "test2".to_string()])
@@ -290,11 +811,11 @@ mod test {
             let conn = conn();
             let repository = repository();
 
-            let election = repository.create_election(test_election_input(), "test_creator_id", conn.clone())
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone())
                 .await
                 .expect("Creating the election shouldn't fail");
 
-            let updated = repository.update_election(&election.id, test_update_input(), conn.clone())
+            let updated = repository.update_election(&election.id, election.version, test_update_input(), &EventMetadata::system(), conn.clone())
                 .await
                 .expect("Updating the election shouldn't fail");
 
@@ -304,32 +825,63 @@ mod test {
             assert_ne!(election.description, updated.description);
 
             let stream_id = format!("election-{}", election.id);
-            let events = conn.data.lock().unwrap()
-                .get(&stream_id)
-                .expect("Stream should've been created")
-                .clone();
+            let events = conn.events(&stream_id);
 
             let create_event = events[0].clone();
-            let create_payload = serde_json::from_value::<CreateElectionEvent>(create_event.1)
+            let create_payload = serde_json::from_value::<CreateElectionEvent>(create_event.payload)
                 .expect("Create event should be of the right type");
             let update_event = events[1].clone();
-            let update_payload = serde_json::from_value::<UpdateElectionEvent>(update_event.1)
+            let update_payload = serde_json::from_value::<UpdateElectionEvent>(update_event.payload)
                 .expect("Update event should be of the right type");
 
-            assert_eq!(create_event.0, EventType::Create);
+            assert_eq!(create_event.event_type, EventType::Create.as_ref());
             assert_eq!(create_payload.name, "test_name".to_string());
             assert_eq!(update_payload.name, None);
             assert_eq!(update_payload.description, Some("test_description_2".to_string()));
         })
     }
 
+    #[test]
+    fn update_writes_domain_events() {
+        block_on(async {
+            let conn = conn();
+            let repository = ElectionRepository::new(0, Duration::from_secs(600));
+
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let input = ElectionInput {
+                name: Some("test_name_2".to_string()),
+                choices: Some(vec!["test3".to_string()]),
+                end_date: Some(Utc::now()),
+                description: Some("test_description_2".to_string()),
+                ..ElectionInput::default()
+            };
+
+            let updated = repository.update_election(&election.id, election.version, input, &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            let stream_id = format!("election-{}", election.id);
+            let event_types = conn.events(&stream_id).iter()
+                .map(|event| event.event_type.clone())
+                .collect::<Vec<_>>();
+            assert_eq!(event_types, vec![
+                ElectionEventType::Create.as_ref(),
+                ElectionEventType::Rename.as_ref(),
+                ElectionEventType::ChangeChoices.as_ref(),
+                ElectionEventType::MoveVotingWindow.as_ref(),
+                ElectionEventType::Update.as_ref()
+            ]);
+            assert_eq!(updated.version, 4);
+            assert_eq!(updated.start_date, election.start_date);
+            assert_eq!(repository.find_election(&election.id, conn.clone()).await.unwrap(), Some(updated));
+        })
+    }
+
     #[test]
     fn find_works() {
         block_on(async {
             let conn = conn();
             let repository = repository();
 
-            let election = repository.create_election(test_election_input(), "test_creator_id", conn.clone())
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone())
                 .await
                 .expect("Creating the election shouldn't fail");
 
@@ -347,7 +899,7 @@ mod test {
             assert_eq!(cache_entry.name, election.name);
             assert_eq!(cache_entry.description, election.description);
 
-            let updated = repository.update_election(&election.id, test_update_input(), conn.clone())
+            let updated = repository.update_election(&election.id, election.version, test_update_input(), &EventMetadata::system(), conn.clone())
                 .await
                 .expect("Updating the election shouldn't fail");
 
@@ -366,4 +918,333 @@ mod test {
             assert_eq!(cache_entry.name, election.name);
         })
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn cast_vote_works() {
+        block_on(async {
+            let conn = conn();
+            let repository = repository();
+
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Creating the election shouldn't fail");
+
+            let vote = repository.cast_vote(&election.id, "test_voter_id", vec!["test1".to_string()], &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Casting the vote shouldn't fail");
+
+            assert_eq!(vote.election_id, election.id);
+            assert_eq!(vote.ballot, vec!["test1".to_string()]);
+
+            let stream_id = format!("election-{}-votes", election.id);
+            let NewEvent { event_type, payload, .. } = conn.events(&stream_id)[0].clone();
+            let payload: VoteEvent = serde_json::from_value(payload).expect("The event payload should have the right type");
+
+            assert_eq!(event_type, ElectionEventType::Vote.as_ref());
+            assert_eq!(payload.voter_id, "test_voter_id".to_string());
+            assert_eq!(payload.ballot, vec!["test1".to_string()]);
+        })
+    }
+
+    #[test]
+    fn find_votes_reads_legacy_votes() {
+        block_on(async {
+            let conn = conn();
+            let repository = repository();
+            let election_id = Uuid::new_v4();
+            let stream_id = format!("election-{}-votes", election_id);
+
+            let legacy = serde_json::json!({ "voter_id": "test_voter_id", "choice": "test1", "cast_at": "2020-01-01T00:00:00Z" });
+            let legacy = NewEvent::new(ElectionEventType::Vote, &legacy, &EventMetadata::system()).unwrap();
+            conn.write_events(stream_id, vec![legacy], ExpectedVersion::NoStream).await.unwrap();
+            repository.cast_vote(&election_id, "test_voter_id_2", vec!["test2".to_string()], &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            let votes = repository.find_votes(&election_id, conn.clone()).await.unwrap();
+
+            assert_eq!(votes["test_voter_id"].ballot, vec!["test1".to_string()]);
+            assert_eq!(votes["test_voter_id_2"].ballot, vec!["test2".to_string()]);
+        })
+    }
+
+    #[test]
+    fn find_election_upcasts_legacy_events() {
+        block_on(async {
+            let conn = conn();
+            let repository = repository();
+            let id = Uuid::new_v4();
+            let stream_id = format!("election-{}", id);
+
+            // Written before schema versions or the lifecycle existed
+            let legacy = serde_json::json!({
+                "id": id,
+                "name": "test_name",
+                "description": "test_description",
+                "start_date": "2020-01-01T00:00:00Z",
+                "end_date": "2020-01-02T00:00:00Z",
+                "importance": "Regular",
+                "created_by_id": "test_creator_id",
+                "choices": ["test1", "test2"]
+            });
+            let legacy_event = NewEvent::new(EventType::Create, &legacy, &EventMetadata::system()).unwrap();
+            conn.write_events(stream_id.clone(), vec![legacy_event], ExpectedVersion::NoStream).await.unwrap();
+
+            let election = repository.find_election(&id, conn.clone()).await.unwrap().expect("The legacy election should be found");
+            assert_eq!(election.status, ElectionStatus::Open);
+
+            // Version 1 events that already have a status keep it
+            let mut draft = legacy.clone();
+            draft["status"] = serde_json::json!("Draft");
+            let draft_id = Uuid::new_v4();
+            draft["id"] = serde_json::json!(draft_id);
+            let draft_event = NewEvent::new(EventType::Create, &draft, &EventMetadata { schema_version: Some(1), ..EventMetadata::system() }).unwrap();
+            conn.write_events(format!("election-{}", draft_id), vec![draft_event], ExpectedVersion::NoStream).await.unwrap();
+
+            let election = repository.find_election(&draft_id, conn.clone()).await.unwrap().unwrap();
+            assert_eq!(election.status, ElectionStatus::Draft);
+
+            // Events from a newer version of the schema can't be read
+            let future = EventMetadata { schema_version: Some(CreateElectionEvent::SCHEMA_VERSION + 1), ..EventMetadata::system() };
+            let future_id = Uuid::new_v4();
+            let future_event = NewEvent::new(EventType::Create, &legacy, &future).unwrap();
+            conn.write_events(format!("election-{}", future_id), vec![future_event], ExpectedVersion::NoStream).await.unwrap();
+
+            let result = repository.find_election(&future_id, conn.clone()).await;
+            assert!(matches!(result, Err(DatabaseError::UnsupportedSchemaVersion(_))));
+        })
+    }
+
+    #[test]
+    fn find_election_skips_unknown_events() {
+        block_on(async {
+            let conn = conn();
+            let repository = ElectionRepository::new(0, Duration::from_secs(600));
+
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let stream_id = format!("election-{}", election.id);
+            // Written by a newer version of the service, and looks nothing like a create event
+            conn.write_event(&stream_id, "election-archive", serde_json::json!({ "archived": true }), &EventMetadata::system(), ExpectedVersion::Any)
+                .await
+                .unwrap();
+
+            let found = repository.find_election(&election.id, conn.clone()).await.unwrap();
+
+            assert_eq!(found, Some(Election { version: 1, ..election }));
+        })
+    }
+
+    #[test]
+    fn update_works_after_unknown_events() {
+        block_on(async {
+            let conn = conn();
+            let repository = ElectionRepository::new(0, Duration::from_secs(600));
+
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone()).await.unwrap();
+            let stream_id = format!("election-{}", election.id);
+            conn.write_event(&stream_id, "election-archive", serde_json::json!({ "archived": true }), &EventMetadata::system(), ExpectedVersion::Any)
+                .await
+                .unwrap();
+
+            let found = repository.find_election(&election.id, conn.clone()).await.unwrap().unwrap();
+            let updated = repository.update_election(&election.id, found.version, test_update_input(), &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Skipped events shouldn't make the election read-only");
+
+            assert_eq!(updated.version, 2);
+            assert_eq!(updated.description, "test_description_2");
+            assert_eq!(repository.find_election_at_version(&election.id, 1, conn.clone()).await.unwrap().unwrap().description, "test_description");
+            assert_eq!(repository.find_election(&election.id, conn.clone()).await.unwrap(), Some(updated));
+        })
+    }
+
+    #[test]
+    fn create_stores_the_schema_version() {
+        block_on(async {
+            let conn = conn();
+            let repository = repository();
+
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone()).await.unwrap();
+            repository.update_election(&election.id, election.version, test_update_input(), &EventMetadata::system(), conn.clone()).await.unwrap();
+
+            let events = conn.events(format!("election-{}", election.id));
+            assert_eq!(events[0].metadata.schema_version, Some(CreateElectionEvent::SCHEMA_VERSION));
+            assert_eq!(events[1].metadata.schema_version, Some(UpdateElectionEvent::SCHEMA_VERSION));
+        })
+    }
+
+    #[test]
+    fn cancel_works() {
+        block_on(async {
+            let conn = conn();
+            let repository = repository();
+
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Creating the election shouldn't fail");
+            repository.find_election(&election.id, conn.clone()).await.unwrap();
+
+            let cancelled = repository.cancel_election(&election.id, election.version, Some("test_reason".to_string()), &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Cancelling the election shouldn't fail");
+            let found = repository.find_election(&election.id, conn.clone()).await.unwrap()
+                .expect("A cancelled election should still be readable");
+
+            assert_eq!(cancelled.status, ElectionStatus::Cancelled);
+            assert_eq!(found.status, ElectionStatus::Cancelled);
+            assert_eq!(found.cancellation_reason, Some("test_reason".to_string()));
+            assert_eq!(found.name, election.name);
+        })
+    }
+
+    #[test]
+    fn delete_works() {
+        block_on(async {
+            let conn = conn();
+            let repository = repository();
+
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Creating the election shouldn't fail");
+            repository.find_election(&election.id, conn.clone()).await.unwrap();
+
+            repository.delete_election(&election.id, election.version, Some("test_reason".to_string()), "test_creator_id", &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Deleting the election shouldn't fail");
+
+            let stream_id = format!("election-{}", election.id);
+            let event_type = conn.events(&stream_id)[1].event_type.clone();
+
+            assert_eq!(event_type, EventType::Delete.as_ref());
+            assert_eq!(repository.find_election(&election.id, conn.clone()).await.unwrap(), None);
+            assert!(repository.delete_election(&election.id, election.version, None, "test_creator_id", &EventMetadata::system(), conn.clone()).await.is_err());
+        })
+    }
+
+    #[test]
+    fn update_detects_conflicts() {
+        block_on(async {
+            let conn = conn();
+            let repository = repository();
+
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Creating the election shouldn't fail");
+            let updated = repository.update_election(&election.id, election.version, test_update_input(), &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Updating the latest version shouldn't fail");
+            assert_eq!(updated.version, election.version + 1);
+
+            let stale = repository.update_election(&election.id, election.version, test_update_input(), &EventMetadata::system(), conn.clone()).await;
+            assert!(matches!(stale, Err(DatabaseError::WrongExpectedVersion(_))));
+
+            // A write the cache doesn't know about is caught by the database
+            repository.find_election(&election.id, conn.clone()).await.unwrap();
+            let stream_id = format!("election-{}", election.id);
+            conn.write_event(&stream_id, EventType::Update, serde_json::json!({ "name": "test_name_2" }), &EventMetadata::system(), ExpectedVersion::Any)
+                .await
+                .unwrap();
+
+            let conflict = repository.update_election(&election.id, updated.version, test_update_input(), &EventMetadata::system(), conn.clone()).await;
+            assert!(matches!(conflict, Err(DatabaseError::WrongExpectedVersion(_))));
+
+            let latest = repository.find_election(&election.id, conn.clone()).await.unwrap().unwrap();
+            assert_eq!(latest.name, "test_name_2");
+            assert_eq!(latest.version, updated.version + 1);
+        })
+    }
+
+    #[test]
+    fn transition_writes_all_steps_or_none() {
+        block_on(async {
+            let conn = conn();
+            let repository = repository();
+
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Creating the election shouldn't fail");
+            let steps = [ElectionStatus::Scheduled, ElectionStatus::Open, ElectionStatus::Closed, ElectionStatus::Certified];
+
+            let stale = repository.transition_election(&election.id, election.version + 1, &steps, &EventMetadata::system(), conn.clone()).await;
+            assert!(matches!(stale, Err(DatabaseError::WrongExpectedVersion(_))));
+
+            let stream_id = format!("election-{}", election.id);
+            assert_eq!(conn.events(&stream_id).len(), 1);
+
+            let certified = repository.transition_election(&election.id, election.version, &steps, &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Moving through the lifecycle shouldn't fail");
+
+            assert_eq!(certified.status, ElectionStatus::Certified);
+            assert_eq!(certified.version, 4);
+            assert_eq!(certified.certified_version, Some(3));
+            assert_eq!(conn.events(&stream_id).len(), 5);
+            assert_eq!(repository.find_election(&election.id, conn.clone()).await.unwrap(), Some(certified));
+        })
+    }
+
+    #[test]
+    fn resumes_from_snapshots() {
+        block_on(async {
+            let conn = conn();
+            // Without a cache every lookup folds the stream
+            let repository = ElectionRepository::new(0, Duration::from_secs(600)).with_snapshot_interval(2);
+
+            let mut election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Creating the election shouldn't fail");
+            for _ in 0..3 {
+                election = repository.update_election(&election.id, election.version, test_update_input(), &EventMetadata::system(), conn.clone())
+                    .await
+                    .expect("Updating the election shouldn't fail");
+            }
+
+            let stream_id = format!("election-{}", election.id);
+            assert_eq!(repository.find_election(&election.id, conn.clone()).await.unwrap(), Some(election.clone()));
+            let latest: Snapshot<Election> = conn.read_snapshot(&stream_id).await.unwrap()
+                .map(|snapshot| serde_json::from_value(snapshot).unwrap())
+                .expect("A snapshot should have been stored");
+            assert_eq!(latest.version, 3);
+            assert_eq!(latest.state, election);
+
+            // A snapshot is trusted over replaying the events before it
+            let tampered = Election { name: "from_snapshot".to_string(), ..election.clone() };
+            let snapshot = Snapshot { version: 3, schema_version: latest.schema_version, state: tampered };
+            conn.write_snapshot(&stream_id, serde_json::to_value(snapshot).unwrap()).await.unwrap();
+            let read = repository.find_election(&election.id, conn.clone()).await.unwrap().unwrap();
+            assert_eq!(read.name, "from_snapshot");
+
+            // Snapshots of another schema version are ignored
+            let outdated = Snapshot { version: 3, schema_version: latest.schema_version + 1, state: read };
+            conn.write_snapshot(&stream_id, serde_json::to_value(outdated).unwrap()).await.unwrap();
+            assert_eq!(repository.find_election(&election.id, conn.clone()).await.unwrap(), Some(election));
+        })
+    }
+
+    #[test]
+    fn reads_earlier_versions() {
+        block_on(async {
+            let conn = conn();
+            let repository = repository();
+
+            let election = repository.create_election(test_election_input(), "test_creator_id", &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Creating the election shouldn't fail");
+            let steps = [ElectionStatus::Scheduled, ElectionStatus::Open];
+            let opened = repository.transition_election(&election.id, election.version, &steps, &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Opening the election shouldn't fail");
+            let opened_at = Utc::now();
+            repository.delete_election(&election.id, opened.version, None, "test_creator_id", &EventMetadata::system(), conn.clone())
+                .await
+                .expect("Deleting the election shouldn't fail");
+
+            let scheduled = repository.find_election_at_version(&election.id, 1, conn.clone()).await.unwrap().unwrap();
+            assert_eq!(scheduled.status, ElectionStatus::Scheduled);
+            assert_eq!(scheduled.version, 1);
+            assert_eq!(repository.find_election_at_version(&election.id, 2, conn.clone()).await.unwrap(), Some(opened.clone()));
+            assert_eq!(repository.find_election_at_version(&election.id, 3, conn.clone()).await.unwrap(), None);
+
+            assert_eq!(repository.find_election_as_of(&election.id, opened_at, conn.clone()).await.unwrap(), Some(opened));
+            assert_eq!(repository.find_election_as_of(&election.id, Utc::now(), conn.clone()).await.unwrap(), None);
+        })
+    }
+}
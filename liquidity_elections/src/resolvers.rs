@@ -1,21 +1,43 @@
 use crate::repository::ElectionRepository;
 use liquidity::{Uuid, Context, Error, permissions};
-use crate::schema::{Election, ElectionInput};
+use crate::schema::{
+    Election, ElectionInput, Vote, ElectionResults, ElectionStatus, ElectionFilter, ElectionOrder,
+    ElectionConnection, ElectionEdge, PageInfo, ElectionChange
+};
+use crate::delegation::{repository::DelegationRepository, resolution};
+use crate::{results, tally};
+use crate::access::{self, Access};
+use crate::index::Cursor;
 use std::time::Duration;
-use liquidity::db::DbConnection;
+use chrono::{DateTime, Utc};
+use liquidity::db::{DatabaseError, DbConnection};
+use std::sync::Arc;
+
+/// How often an edit is attempted when the election keeps changing concurrently
+const EDIT_ATTEMPTS: usize = 3;
 
 #[derive(Debug)]
 pub struct ElectionResolvers {
-    repository: ElectionRepository
+    repository: Arc<ElectionRepository>,
+    delegations: DelegationRepository
 }
 
 impl ElectionResolvers {
     pub fn new(cache_capacity: usize, cache_ttl: Duration) -> ElectionResolvers {
+        ElectionResolvers::from_repository(ElectionRepository::new(cache_capacity, cache_ttl))
+    }
+
+    /// Create the resolvers on top of a configured repository
+    pub fn from_repository(repository: ElectionRepository) -> ElectionResolvers {
         ElectionResolvers {
-            repository: ElectionRepository::new(cache_capacity, cache_ttl)
+            repository: Arc::new(repository),
+            delegations: DelegationRepository::new()
         }
     }
 
+    /// The repository backing these resolvers, to share its cache with other subsystems
+    pub fn repository(&self) -> Arc<ElectionRepository> { self.repository.clone() }
+
     /// Create a new election
     ///
     /// # Arguments
@@ -29,13 +51,14 @@ impl ElectionResolvers {
     ///
     /// # Returns
     ///
-    /// The new Election or an error if the creation failed
+    /// The new Election or an error if the creation failed, the start or end date is missing or the election would end
+    /// before it starts
     ///
     /// # Example
     ///
     /// ```ignore
     /// mutation {
-    ///     createElection(input: {name: "test"}) {
+    ///     createElection(input: {name: "test", startDate: "2020-01-01T00:00:00Z", endDate: "2020-01-02T00:00:00Z"}) {
     ///        id
     ///        name
     ///        description
@@ -50,11 +73,15 @@ impl ElectionResolvers {
     ) -> Result<Election, Error> {
         permissions::check("create:election", context.user())?;
         if input.name.is_none() { return Err("Name cannot be null".into()) }
+        match (input.start_date, input.end_date) {
+            (Some(start_date), Some(end_date)) => check_dates(start_date, end_date)?,
+            _ => return Err("Start and end dates cannot be null".into())
+        }
 
         let db = context.db();
         let user = context.user().as_ref().unwrap();
 
-        let result = self.repository.create_election(input, &user.id, db).await?;
+        let result = self.repository.create_election(input, &user.id, &context.metadata(), db).await?;
         Ok(result)
     }
 
@@ -68,7 +95,10 @@ impl ElectionResolvers {
     ///
     /// # Permissions Required
     ///
-    /// `update:election`
+    /// `update:election` and one of the election's edit roles. Changing the timing, choices,
+    /// voting method, result visibility or permissions of the election requires one of its admin roles.
+    /// The choices and voting method can't be changed once the election is open, and closed, certified
+    /// or cancelled elections can't be edited at all.
     ///
     /// # Returns
     ///
@@ -95,7 +125,273 @@ impl ElectionResolvers {
         permissions::check("update:election", &context.user())?;
         let db = context.db();
 
-        let result = self.repository.update_election(&id, input, db).await?;
+        // Concurrent edits are retried against the latest version, as long as the edit is still allowed
+        let mut attempt = 1;
+        loop {
+            let election = self.repository.find_election(&id, db.clone()).await?
+                .ok_or("Election doesn't exist")?;
+            let required_access = if input.is_structural() { Access::Admin } else { Access::Edit };
+            access::check(required_access, &election, context.user())?;
+            if !election.status.is_editable() { return Err("Election can't be edited anymore".into()) }
+
+            let changes_choices = input.choices.iter().any(|choices| *choices != election.choices);
+            let changes_method = input.voting_method.iter().any(|method| *method != election.voting_method);
+            if (changes_choices || changes_method) && election.status.has_started() {
+                return Err("The choices and voting method can't be changed once voting has started".into());
+            }
+            check_dates(input.start_date.unwrap_or(election.start_date), input.end_date.unwrap_or(election.end_date))?;
+
+            match self.repository.update_election(&id, election.version, input.clone(), &context.metadata(), db.clone()).await {
+                Err(DatabaseError::WrongExpectedVersion(_)) if attempt < EDIT_ATTEMPTS => attempt += 1,
+                result => return Ok(result?)
+            }
+        }
+    }
+
+    /// Cancel an election. Cancelled elections stay readable, but can't be voted in or edited anymore.
+    ///
+    /// # Arguments
+    ///
+    /// `id` - The id of the election to cancel
+    /// `reason` - The reason for cancelling the election, shown to users
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `update:election` and one of the election's admin roles
+    ///
+    /// # Returns
+    ///
+    /// The cancelled election or an error if it doesn't exist or has been certified or cancelled already
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// mutation {
+    ///     cancelElection(id: "54c8ed41-b4f4-4f1c-8903-f9bbbe2d992d", reason: "Postponed") {
+    ///        id
+    ///        status
+    ///        cancellationReason
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn cancel_election<T: DbConnection, C: Context<T>>(
+        &self,
+        id: Uuid,
+        reason: Option<String>,
+        context: &C
+    ) -> Result<Election, Error> {
+        permissions::check("update:election", context.user())?;
+        let db = context.db();
+
+        let election = self.repository.find_election(&id, db.clone()).await?
+            .ok_or("Election doesn't exist")?;
+        access::check(Access::Admin, &election, context.user())?;
+        if !election.status.can_transition_to(&ElectionStatus::Cancelled) {
+            return Err(format!("Election is {:?} and can't be cancelled", election.status).into())
+        }
+
+        let result = self.repository.cancel_election(&id, election.version, reason, &context.metadata(), db).await?;
+        Ok(result)
+    }
+
+    /// Publish a draft election, scheduling it to be opened
+    ///
+    /// # Arguments
+    ///
+    /// `id` - The id of the election to publish
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `update:election` and one of the election's admin roles
+    ///
+    /// # Returns
+    ///
+    /// The scheduled election or an error if it isn't a draft or has less than two choices
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// mutation {
+    ///     publishElection(id: "54c8ed41-b4f4-4f1c-8903-f9bbbe2d992d") {
+    ///        id
+    ///        status
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn publish_election<T: DbConnection, C: Context<T>>(&self, id: Uuid, context: &C) -> Result<Election, Error> {
+        self.transition(id, &[ElectionStatus::Scheduled], context).await
+    }
+
+    /// Open a scheduled election for voting
+    ///
+    /// # Arguments
+    ///
+    /// `id` - The id of the election to open
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `update:election` and one of the election's admin roles
+    ///
+    /// # Returns
+    ///
+    /// The open election or an error if it isn't scheduled or its end date has passed
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// mutation {
+    ///     openElection(id: "54c8ed41-b4f4-4f1c-8903-f9bbbe2d992d") {
+    ///        id
+    ///        status
+    ///        startDate
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn open_election<T: DbConnection, C: Context<T>>(&self, id: Uuid, context: &C) -> Result<Election, Error> {
+        self.transition(id, &[ElectionStatus::Open], context).await
+    }
+
+    /// Close an open election, ending the vote
+    ///
+    /// # Arguments
+    ///
+    /// `id` - The id of the election to close
+    /// `certify` - Whether to certify the results right away, in the same transaction
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `update:election` and one of the election's admin roles
+    ///
+    /// # Returns
+    ///
+    /// The closed or certified election, or an error if it isn't open
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// mutation {
+    ///     closeElection(id: "54c8ed41-b4f4-4f1c-8903-f9bbbe2d992d", certify: true) {
+    ///        id
+    ///        status
+    ///        endDate
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn close_election<T: DbConnection, C: Context<T>>(&self, id: Uuid, certify: bool, context: &C) -> Result<Election, Error> {
+        if certify {
+            self.transition(id, &[ElectionStatus::Closed, ElectionStatus::Certified], context).await
+        } else {
+            self.transition(id, &[ElectionStatus::Closed], context).await
+        }
+    }
+
+    /// Certify the results of a closed election
+    ///
+    /// # Arguments
+    ///
+    /// `id` - The id of the election to certify
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `update:election` and one of the election's admin roles
+    ///
+    /// # Returns
+    ///
+    /// The certified election or an error if it isn't closed
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// mutation {
+    ///     certifyElection(id: "54c8ed41-b4f4-4f1c-8903-f9bbbe2d992d") {
+    ///        id
+    ///        status
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn certify_election<T: DbConnection, C: Context<T>>(&self, id: Uuid, context: &C) -> Result<Election, Error> {
+        self.transition(id, &[ElectionStatus::Certified], context).await
+    }
+
+    /// Move an election through one or more statuses after checking every transition is allowed
+    async fn transition<T: DbConnection, C: Context<T>>(&self, id: Uuid, statuses: &[ElectionStatus], context: &C) -> Result<Election, Error> {
+        permissions::check("update:election", context.user())?;
+        let db = context.db();
+
+        let election = self.repository.find_election(&id, db.clone()).await?
+            .ok_or("Election doesn't exist")?;
+        access::check(Access::Admin, &election, context.user())?;
+
+        let mut current = &election.status;
+        for status in statuses {
+            if !current.can_transition_to(status) {
+                return Err(format!("Election is {:?} and can't be moved to {:?}", current, status).into())
+            }
+            if *status == ElectionStatus::Scheduled && election.choices.len() < 2 {
+                return Err("An election needs at least two choices to be published".into())
+            }
+            if *status == ElectionStatus::Open && election.end_date <= Utc::now() {
+                return Err("The end date of the election has passed, move it before opening the election".into())
+            }
+            current = status;
+        }
+
+        let result = self.repository.transition_election(&id, election.version, statuses, &context.metadata(), db).await?;
+        Ok(result)
+    }
+
+    /// Delete an election. Deleted elections can't be found anymore.
+    ///
+    /// # Arguments
+    ///
+    /// `id` - The id of the election to delete
+    /// `reason` - The reason for deleting the election, kept for auditing
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `delete:election` and one of the election's admin roles
+    ///
+    /// # Returns
+    ///
+    /// The election as it was before it was deleted, or an error if it doesn't exist
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// mutation {
+    ///     deleteElection(id: "54c8ed41-b4f4-4f1c-8903-f9bbbe2d992d", reason: "Created by mistake") {
+    ///        id
+    ///        name
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn delete_election<T: DbConnection, C: Context<T>>(
+        &self,
+        id: Uuid,
+        reason: Option<String>,
+        context: &C
+    ) -> Result<Election, Error> {
+        permissions::check("delete:election", context.user())?;
+        let db = context.db();
+        let user = context.user().as_ref().unwrap();
+
+        let election = self.repository.find_election(&id, db.clone()).await?
+            .ok_or("Election doesn't exist")?;
+        access::check(Access::Admin, &election, context.user())?;
+
+        let result = self.repository.delete_election(&id, election.version, reason, &user.id, &context.metadata(), db).await?;
         Ok(result)
     }
 
@@ -104,21 +400,23 @@ impl ElectionResolvers {
     /// # Arguments
     ///
     /// `id` - The id to look up the election by
+    /// `as_of` - The point in time to fetch the election at. Defaults to now
     /// `context` - The request context, passed automatically
     ///
     /// # Permissions Required
     ///
-    /// `view:election`
+    /// `view:election` and one of the election's current view roles
     ///
     /// # Returns
     ///
-    /// The election if it exists, None if it doesn't, Error if an issue has occurred
+    /// The election if it exists, None if it doesn't, didn't exist yet at `as_of` or the user may not view it,
+    /// so hidden elections can't be told apart from missing ones. Error if an issue has occurred
     ///
     /// # Example
     ///
     /// ```ignore
     /// query {
-    ///     election(id: "some_uuid") {
+    ///     election(id: "some_uuid", asOf: "2020-01-01T00:00:00Z") {
     ///         id
     ///         name
     ///         choices
@@ -126,11 +424,264 @@ impl ElectionResolvers {
     /// }
     /// ```
     #[instrument]
-    pub async fn election<T: DbConnection, C: Context<T>>(&self, id: Uuid, context: &C) -> Result<Option<Election>, Error> {
+    pub async fn election<T: DbConnection, C: Context<T>>(
+        &self,
+        id: Uuid,
+        as_of: Option<DateTime<Utc>>,
+        context: &C
+    ) -> Result<Option<Election>, Error> {
         permissions::check("view:election", &context.user())?;
 
         let db = context.db();
-        let result = self.repository.find_election(&id, db).await?;
+        let result = self.repository.find_election(&id, db.clone()).await?
+            .filter(|election| access::check(Access::View, election, context.user()).is_ok());
+
+        // Access is checked against the current election, so removing a role also hides the earlier versions
+        match (result, as_of) {
+            (Some(_), Some(as_of)) => Ok(self.repository.find_election_as_of(&id, as_of, db).await?),
+            (result, _) => Ok(result)
+        }
+    }
+
+    /// List the elections the user may view
+    ///
+    /// # Arguments
+    ///
+    /// `filter` - Filters the elections have to match, defaults to no filters
+    /// `order_by` - The order to list the elections in, defaults to the latest start date first
+    /// `first` - The number of elections to return, between 1 and 100. Defaults to 20
+    /// `after` - The cursor of the election to continue after
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `view:election`. Elections the user has no view role for are left out.
+    ///
+    /// # Returns
+    ///
+    /// A page of elections or an error if the cursor is invalid or an issue has occurred
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// query {
+    ///     elections(filter: {status: [OPEN], search: "budget"}, orderBy: {field: IMPORTANCE, direction: DESC}, first: 10) {
+    ///         edges {
+    ///             cursor
+    ///             node {
+    ///                 id
+    ///                 name
+    ///             }
+    ///         }
+    ///         pageInfo {
+    ///             hasNextPage
+    ///             endCursor
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn elections<T: DbConnection, C: Context<T>>(
+        &self,
+        filter: Option<ElectionFilter>,
+        order_by: Option<ElectionOrder>,
+        first: Option<i32>,
+        after: Option<String>,
+        context: &C
+    ) -> Result<ElectionConnection, Error> {
+        permissions::check("view:election", context.user())?;
+
+        let first = first.unwrap_or(20);
+        if !(1..=100).contains(&first) { return Err("first has to be between 1 and 100".into()) }
+
+        let order = order_by.unwrap_or_default();
+        let elections: Vec<Election> = self.repository
+            .find_elections(&filter.unwrap_or_default(), &order)
+            .into_iter()
+            .filter(|election| access::check(Access::View, election, context.user()).is_ok())
+            .collect();
+
+        let start = match after {
+            Some(cursor) => {
+                let cursor: Cursor = cursor.parse()?;
+                elections.iter().position(|election| cursor.is_before(&order, election)).unwrap_or(elections.len())
+            },
+            None => 0
+        };
+
+        let edges: Vec<ElectionEdge> = elections.iter()
+            .skip(start)
+            .take(first as usize)
+            .map(|election| ElectionEdge { cursor: Cursor::of(election).to_string(), node: election.clone() })
+            .collect();
+
+        Ok(ElectionConnection {
+            page_info: PageInfo {
+                has_next_page: start + edges.len() < elections.len(),
+                has_previous_page: start > 0,
+                start_cursor: edges.first().map(|edge| edge.cursor.clone()),
+                end_cursor: edges.last().map(|edge| edge.cursor.clone())
+            },
+            edges,
+            total_count: elections.len() as i32
+        })
+    }
+
+    /// Cast a vote in an election
+    ///
+    /// # Arguments
+    ///
+    /// `election_id` - The id of the election to vote in
+    /// `ballot` - The choices to vote for. A single choice for plurality voting, any number of approved
+    /// choices for approval voting, or choices in order of preference for ranked voting methods
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `vote:election` and one of the election's vote roles
+    ///
+    /// # Returns
+    ///
+    /// The vote or an error if the election doesn't exist, isn't open for voting, is outside its start and end dates
+    /// or the ballot is invalid. Elections aren't closed automatically, so an open election whose end date passed
+    /// still rejects votes.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// mutation {
+    ///     castVote(electionId: "54c8ed41-b4f4-4f1c-8903-f9bbbe2d992d", ballot: ["test1"]) {
+    ///         electionId
+    ///         ballot
+    ///         castAt
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn cast_vote<T: DbConnection, C: Context<T>>(
+        &self,
+        election_id: Uuid,
+        ballot: Vec<String>,
+        context: &C
+    ) -> Result<Vote, Error> {
+        permissions::check("vote:election", context.user())?;
+
+        let db = context.db();
+        let user = context.user().as_ref().unwrap();
+
+        let election = self.repository.find_election(&election_id, db.clone()).await?
+            .ok_or("Election doesn't exist")?;
+        access::check(Access::Vote, &election, context.user())?;
+        if election.status != ElectionStatus::Open { return Err("Election isn't open for voting".into()) }
+        let now = Utc::now();
+        if now < election.start_date || now > election.end_date { return Err("Voting is only possible between the start and end date".into()) }
+        tally::method(&election.voting_method).validate(&election.choices, &ballot)?;
+
+        let result = self.repository.cast_vote(&election_id, &user.id, ballot, &context.metadata(), db).await?;
         Ok(result)
     }
+
+    /// Fetch the results of an election
+    ///
+    /// # Arguments
+    ///
+    /// `id` - The id of the election
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `view:election` and one of the election's view roles
+    ///
+    /// # Returns
+    ///
+    /// The live tally of the election, None if it doesn't exist or the user may not view it, Error if the results are hidden
+    /// until the election ends or an issue has occurred
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// query {
+    ///     electionResults(id: "some_uuid") {
+    ///         choices {
+    ///             choice
+    ///             score
+    ///         }
+    ///         turnout
+    ///         winner
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn election_results<T: DbConnection, C: Context<T>>(&self, id: Uuid, context: &C) -> Result<Option<ElectionResults>, Error> {
+        permissions::check("view:election", context.user())?;
+
+        let db = context.db();
+        // Like `election`, elections the user may not view look like they don't exist
+        let election = match self.repository.find_election(&id, db.clone()).await? {
+            Some(election) if access::check(Access::View, &election, context.user()).is_ok() => election,
+            _ => return Ok(None)
+        };
+
+        // A cancelled election never ends, so hidden results of a vote that was called off are never revealed
+        if election.hide_results && !election.status.has_ended() {
+            return Err("Results are hidden until the election has ended".into())
+        }
+
+        let votes = self.repository.find_votes(&id, db.clone()).await?;
+        let delegations = self.delegations.find_election_delegations(&election, db).await?;
+        let resolution = resolution::resolve(votes.keys().map(String::as_str), &delegations);
+
+        Ok(Some(results::tally(&election, &votes, &resolution)))
+    }
+
+    /// Fetch every change made to an election, including deleted elections
+    ///
+    /// # Arguments
+    ///
+    /// `id` - The id of the election
+    /// `context` - The request context, passed automatically
+    ///
+    /// # Permissions Required
+    ///
+    /// `view:election` and one of the election's admin roles
+    ///
+    /// # Returns
+    ///
+    /// The changes made to the election, oldest first, None if it never existed or the user may not view it, Error if an issue has occurred
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// query {
+    ///     electionHistory(id: "some_uuid") {
+    ///         version
+    ///         changeType
+    ///         actorId
+    ///         timestamp
+    ///         changes {
+    ///             field
+    ///             oldValue
+    ///             newValue
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    #[instrument]
+    pub async fn election_history<T: DbConnection, C: Context<T>>(&self, id: Uuid, context: &C) -> Result<Option<Vec<ElectionChange>>, Error> {
+        permissions::check("view:election", context.user())?;
+
+        let history = match self.repository.find_history(&id, context.db()).await? {
+            Some(history) if access::check(Access::View, &history.election, context.user()).is_ok() => history,
+            _ => return Ok(None)
+        };
+        access::check(Access::Admin, &history.election, context.user())?;
+
+        Ok(Some(history.changes))
+    }
+}
+
+/// Check an election ends after it starts
+fn check_dates(start_date: DateTime<Utc>, end_date: DateTime<Utc>) -> Result<(), Error> {
+    if end_date <= start_date { return Err("The end date has to be after the start date".into()) }
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use crate::schema::{Election, ElectionResults};
+use crate::models::VoteEvent;
+use crate::delegation::resolution::Resolution;
+use crate::tally::{self, WeightedBallot};
+
+/// Count the votes of an election with its voting method
+///
+/// Every direct vote is weighted by the delegations it carries. Choices that were removed from
+/// the election after a vote was cast are dropped from its ballot, and ballots left empty aren't counted.
+///
+/// # Arguments
+///
+/// * `election` - The election to count the votes for
+/// * `votes` - The latest vote of every direct voter
+/// * `resolution` - The resolved delegations of the election
+pub(crate) fn tally(election: &Election, votes: &HashMap<String, VoteEvent>, resolution: &Resolution) -> ElectionResults {
+    let ballots: Vec<WeightedBallot> = votes.iter()
+        .map(|(voter, vote)| WeightedBallot {
+            choices: vote.ballot.iter().filter(|choice| election.choices.contains(choice)).cloned().collect(),
+            weight: resolution.weights.get(voter).copied().unwrap_or(1) as i32
+        })
+        .filter(|ballot| !ballot.choices.is_empty())
+        .collect();
+
+    let tally = tally::method(&election.voting_method).tally(&election.choices, &ballots);
+
+    ElectionResults {
+        election_id: election.id,
+        voting_method: election.voting_method.clone(),
+        choices: tally.scores,
+        rounds: tally.rounds,
+        total_weight: ballots.iter().map(|ballot| ballot.weight).sum(),
+        turnout: votes.len() as i32,
+        lost_votes: resolution.lost.len() as i32,
+        winner: tally.winner,
+        is_final: election.status.has_ended()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use chrono::Utc;
+    use crate::schema::{Election, VotingMethod};
+    use crate::models::VoteEvent;
+    use crate::delegation::resolution::resolve;
+    use super::tally;
+
+    fn election(voting_method: VotingMethod) -> Election {
+        let choices = vec!["test1".to_string(), "test2".to_string(), "test3".to_string()];
+        Election { choices, voting_method, ..Election::test() }
+    }
+
+    fn votes(votes: &[(&str, &[&str])]) -> HashMap<String, VoteEvent> {
+        votes.iter()
+            .map(|(voter, ballot)| {
+                let ballot = ballot.iter().map(|choice| choice.to_string()).collect();
+                let vote = VoteEvent { voter_id: voter.to_string(), ballot, cast_at: Utc::now() };
+                (voter.to_string(), vote)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn counts_delegated_votes() {
+        let votes = votes(&[("alice", &["test1"]), ("bob", &["test2"]), ("carol", &["test2"]), ("dave", &["removed"])]);
+        let delegations = vec![("erin", "alice"), ("frank", "erin"), ("gina", "nobody")].into_iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect();
+        let resolution = resolve(votes.keys().map(String::as_str), &delegations);
+
+        let results = tally(&election(VotingMethod::Plurality), &votes, &resolution);
+
+        assert_eq!(results.choices[0].score, 3);
+        assert_eq!(results.choices[1].score, 2);
+        assert_eq!(results.total_weight, 5);
+        assert_eq!(results.turnout, 4);
+        assert_eq!(results.lost_votes, 1);
+        assert_eq!(results.winner, Some("test1".to_string()));
+        assert!(!results.is_final);
+    }
+
+    #[test]
+    fn uses_the_voting_method() {
+        let votes = votes(&[("alice", &["test1", "test3"]), ("bob", &["test2", "test3"]), ("carol", &["test3", "removed"])]);
+        let resolution = resolve(votes.keys().map(String::as_str), &HashMap::new());
+
+        let results = tally(&election(VotingMethod::Approval), &votes, &resolution);
+
+        assert_eq!(results.voting_method, VotingMethod::Approval);
+        assert_eq!(results.choices.iter().map(|result| result.score).collect::<Vec<_>>(), vec![1, 1, 3]);
+        assert_eq!(results.winner, Some("test3".to_string()));
+        assert_eq!(results.total_weight, 3);
+    }
+
+    #[test]
+    fn ties_have_no_winner() {
+        let votes = votes(&[("alice", &["test1"]), ("bob", &["test2"])]);
+        let resolution = resolve(votes.keys().map(String::as_str), &HashMap::new());
+
+        let results = tally(&election(VotingMethod::Plurality), &votes, &resolution);
+        assert_eq!(results.winner, None);
+
+        let results = tally(&election(VotingMethod::Plurality), &HashMap::new(), &resolve(vec![], &HashMap::new()));
+        assert_eq!(results.winner, None);
+        assert_eq!(results.total_weight, 0);
+    }
+}
@@ -2,14 +2,14 @@ use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 use liquidity::Uuid;
 
-#[derive(juniper::GraphQLInputObject, Debug, PartialEq)]
+#[derive(juniper::GraphQLInputObject, Clone, Debug, PartialEq)]
 pub struct PermissionSet {
     /// The roles allowed to view the election and its results. Defaults to all.
     pub view_roles: Option<Vec<String>>,
     /// The roles allowed to vote in the election. Defaults to all
     pub vote_roles: Option<Vec<String>>,
-    /// The roles allowed to edit the election metadata, but not the election timing or choices.
-    /// Defaults to none
+    /// The roles allowed to edit the election metadata, but not the election timing, choices,
+    /// voting method or permissions. Defaults to none
     pub edit_roles: Option<Vec<String>>,
     /// The roles allowed full access to the election, to modify and delete it.
     /// Defaults to none
@@ -27,6 +27,30 @@ impl Default for PermissionSet {
     }
 }
 
+#[derive(juniper::GraphQLObject, Serialize, Deserialize, Clone, PartialEq, Debug)]
+/// The roles allowed to interact with an election. The creator of an election always has full access.
+pub struct ElectionPermissions {
+    /// The roles allowed to view the election and its results
+    pub view_roles: Vec<String>,
+    /// The roles allowed to vote in the election
+    pub vote_roles: Vec<String>,
+    /// The roles allowed to edit the election metadata, but not the election timing or choices
+    pub edit_roles: Vec<String>,
+    /// The roles allowed full access to the election, to modify and delete it
+    pub admin_roles: Vec<String>
+}
+
+impl Default for ElectionPermissions {
+    fn default() -> Self {
+        ElectionPermissions {
+            view_roles: vec!["@all".to_string()],
+            vote_roles: vec!["@all".to_string()],
+            edit_roles: Vec::new(),
+            admin_roles: Vec::new()
+        }
+    }
+}
+
 #[derive(juniper::GraphQLEnum, Debug, Serialize, Deserialize, Clone, PartialEq)]
 /// The importance of an election. Affects sorting and filtering.
 pub enum Importance {
@@ -35,7 +59,51 @@ pub enum Importance {
     Minor
 }
 
-#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+#[derive(juniper::GraphQLEnum, Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// The method used to cast and count votes
+pub enum VotingMethod {
+    /// Vote for a single choice, the choice with the most votes wins
+    Plurality,
+    /// Vote for any number of choices, the choice with the most approvals wins
+    Approval,
+    /// Rank the choices, the choices with the fewest votes are eliminated until one has a majority
+    InstantRunoff,
+    /// Rank the choices, the choice that beats every other in pairwise comparisons wins
+    Schulze,
+    /// Rank the choices, each rank is worth points and the choice with the most points wins
+    Borda
+}
+
+impl Default for VotingMethod {
+    fn default() -> Self {
+        VotingMethod::Plurality
+    }
+}
+
+#[derive(juniper::GraphQLEnum, Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// The status of an election in its lifecycle
+pub enum ElectionStatus {
+    /// The election is still being prepared and can be edited freely
+    Draft,
+    /// The election was published and is waiting to be opened
+    Scheduled,
+    /// The election is open for voting
+    Open,
+    /// Voting has ended and the results are waiting to be certified
+    Closed,
+    /// The results of the election have been certified
+    Certified,
+    /// The election was cancelled. It stays readable, but can't be voted in or edited anymore
+    Cancelled
+}
+
+impl Default for ElectionStatus {
+    fn default() -> Self {
+        ElectionStatus::Draft
+    }
+}
+
+#[derive(juniper::GraphQLObject, Serialize, Deserialize, Clone, PartialEq, Debug)]
 /// An election
 pub struct Election {
     /// The id of the election (not user facing)
@@ -46,15 +114,232 @@ pub struct Election {
     pub description: String,
     /// The available choices to vote for
     pub choices: Vec<String>,
+    /// The method used to cast and count votes
+    pub voting_method: VotingMethod,
     /// The start date of the vote
     pub start_date: DateTime<Utc>,
     /// The end date of the vote
     pub end_date: DateTime<Utc>,
     /// The importance of the election
-    pub importance: Importance
+    pub importance: Importance,
+    /// Whether the results are hidden until the election has ended
+    pub hide_results: bool,
+    /// The id of the user who created the election
+    pub created_by_id: String,
+    /// The roles allowed to interact with the election
+    pub permissions: ElectionPermissions,
+    /// The status of the election
+    pub status: ElectionStatus,
+    /// The reason the election was cancelled, if it was
+    pub cancellation_reason: Option<String>,
+    /// The version of the election the results were certified against, if they were
+    #[serde(default)]
+    pub certified_version: Option<i32>,
+    /// The number of the last event in the election's stream, pass it back when changing the election
+    pub version: i32
+}
+
+#[cfg(test)]
+impl Election {
+    /// An open election with two choices that everyone may vote in, for tests to change with struct update syntax
+    pub(crate) fn test() -> Self {
+        Election {
+            id: Uuid::new_v4(),
+            name: "test_name".to_string(),
+            description: "test_description".to_string(),
+            choices: vec!["test1".to_string(), "test2".to_string()],
+            voting_method: VotingMethod::Plurality,
+            start_date: Utc::now() - chrono::Duration::hours(1),
+            end_date: Utc::now() + chrono::Duration::hours(1),
+            importance: Importance::Regular,
+            hide_results: false,
+            created_by_id: "creator".to_string(),
+            permissions: ElectionPermissions::default(),
+            status: ElectionStatus::Open,
+            cancellation_reason: None,
+            certified_version: None,
+            version: 0
+        }
+    }
+}
+
+#[derive(juniper::GraphQLInputObject, Clone, Debug, PartialEq, Default)]
+/// Filters for listing elections. Every field that's set has to match.
+pub struct ElectionFilter {
+    /// Only elections with one of these statuses
+    pub status: Option<Vec<ElectionStatus>>,
+    /// Only elections with one of these importances
+    pub importance: Option<Vec<Importance>>,
+    /// Only elections that end at or after this date
+    pub from: Option<DateTime<Utc>>,
+    /// Only elections that start at or before this date
+    pub to: Option<DateTime<Utc>>,
+    /// Only elections created by this user
+    pub created_by_id: Option<String>,
+    /// Only elections with this text in their name or description, ignoring case
+    pub search: Option<String>
+}
+
+#[derive(juniper::GraphQLEnum, Debug, Clone, Copy, PartialEq)]
+/// The field to sort elections by
+pub enum ElectionOrderField {
+    /// Sort by importance, then by start date
+    Importance,
+    /// Sort by start date
+    StartDate
+}
+
+#[derive(juniper::GraphQLEnum, Debug, Clone, Copy, PartialEq)]
+/// The direction to sort in
+pub enum OrderDirection {
+    Asc,
+    Desc
 }
 
-#[derive(juniper::GraphQLInputObject, Debug, PartialEq)]
+#[derive(juniper::GraphQLInputObject, Clone, Debug, PartialEq)]
+/// The order to list elections in
+pub struct ElectionOrder {
+    /// The field to sort by
+    pub field: ElectionOrderField,
+    /// The direction to sort in
+    pub direction: OrderDirection
+}
+
+impl Default for ElectionOrder {
+    fn default() -> Self {
+        ElectionOrder {
+            field: ElectionOrderField::StartDate,
+            direction: OrderDirection::Desc
+        }
+    }
+}
+
+#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+/// Information about the current page of a connection
+pub struct PageInfo {
+    /// Whether there are more items after this page
+    pub has_next_page: bool,
+    /// Whether there are items before this page
+    pub has_previous_page: bool,
+    /// The cursor of the first item in the page
+    pub start_cursor: Option<String>,
+    /// The cursor of the last item in the page
+    pub end_cursor: Option<String>
+}
+
+#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+/// An election in a connection
+pub struct ElectionEdge {
+    /// The cursor to continue paginating after this election
+    pub cursor: String,
+    /// The election
+    pub node: Election
+}
+
+#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+/// A page of elections
+pub struct ElectionConnection {
+    /// The elections in this page
+    pub edges: Vec<ElectionEdge>,
+    /// Information about this page
+    pub page_info: PageInfo,
+    /// The number of elections matching the filter across all pages
+    pub total_count: i32
+}
+
+#[derive(juniper::GraphQLEnum, Debug, Clone, Copy, PartialEq)]
+/// The kind of change made to an election
+pub enum ElectionChangeType {
+    Created,
+    Updated,
+    Deleted
+}
+
+#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+/// A single field changed on an election
+pub struct FieldChange {
+    /// The name of the field, as it's called in the API
+    pub field: String,
+    /// The value before the change, null if it wasn't set. Values that aren't text are encoded as JSON
+    pub old_value: Option<String>,
+    /// The value after the change, null if it isn't set. Values that aren't text are encoded as JSON
+    pub new_value: Option<String>
+}
+
+#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+/// A change made to an election
+pub struct ElectionChange {
+    /// The version of the election after the change
+    pub version: i32,
+    /// The kind of change
+    pub change_type: ElectionChangeType,
+    /// The id of the user who made the change, if it's known
+    pub actor_id: Option<String>,
+    /// The time the change was made at, if it's known
+    pub timestamp: Option<DateTime<Utc>>,
+    /// The id of the request that made the change, if it's known
+    pub request_id: Option<String>,
+    /// The fields that were changed. Empty for deletions
+    pub changes: Vec<FieldChange>,
+    /// The reason given for deleting the election
+    pub reason: Option<String>
+}
+
+#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+/// A vote cast in an election
+pub struct Vote {
+    /// The id of the election the vote was cast in
+    pub election_id: Uuid,
+    /// The choices on the ballot, their meaning depends on the voting method
+    pub ballot: Vec<String>,
+    /// The time the vote was cast at
+    pub cast_at: DateTime<Utc>
+}
+
+#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+/// The score of a single choice
+pub struct ChoiceResult {
+    /// The choice
+    pub choice: String,
+    /// The score of the choice, including delegated votes. Its meaning depends on the voting method
+    pub score: i32
+}
+
+#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+/// A single round of counting votes
+pub struct TallyRound {
+    /// The number of the round, starting at 1
+    pub round: i32,
+    /// What happened in this round
+    pub description: String,
+    /// The score of every choice at the end of this round
+    pub scores: Vec<ChoiceResult>
+}
+
+#[derive(juniper::GraphQLObject, Clone, PartialEq, Debug)]
+/// The results of an election
+pub struct ElectionResults {
+    /// The id of the election
+    pub election_id: Uuid,
+    /// The method the votes were counted with
+    pub voting_method: VotingMethod,
+    /// The final score of each choice, in the order the choices are listed in the election
+    pub choices: Vec<ChoiceResult>,
+    /// How the result was reached
+    pub rounds: Vec<TallyRound>,
+    /// The total voting weight of the counted ballots, including delegated votes
+    pub total_weight: i32,
+    /// The number of users who voted directly
+    pub turnout: i32,
+    /// The number of delegated votes that couldn't be counted because of delegation cycles or dead ends
+    pub lost_votes: i32,
+    /// The winning choice, or null if nobody voted or the top choices are tied
+    pub winner: Option<String>,
+    /// Whether the election has ended and these results are final
+    pub is_final: bool
+}
+
+#[derive(juniper::GraphQLInputObject, Clone, Debug, PartialEq)]
 /// Input to create a new election
 pub struct ElectionInput {
     /// The name of the election
@@ -66,12 +351,16 @@ pub struct ElectionInput {
     pub description: Option<String>,
     /// The choices to make available to voters
     pub choices: Option<Vec<String>>,
+    /// The method used to cast and count votes. Defaults to plurality
+    pub voting_method: Option<VotingMethod>,
     /// The date for voting to start at
     pub start_date: Option<DateTime<Utc>>,
     /// The date for voting to end at
     pub end_date: Option<DateTime<Utc>>,
     /// The importance of the election
-    pub importance: Option<Importance>
+    pub importance: Option<Importance>,
+    /// Whether to hide the results until the election has ended. Defaults to false
+    pub hide_results: Option<bool>
 }
 
 impl Default for ElectionInput {
@@ -81,9 +370,20 @@ impl Default for ElectionInput {
             permissions: None,
             description: None,
             choices: None,
+            voting_method: None,
             start_date: None,
             end_date: None,
-            importance: None
+            importance: None,
+            hide_results: None
         }
     }
+}
+
+impl ElectionInput {
+    /// Whether the input changes the structure of the election rather than just its metadata.
+    /// Structural changes are the timing, choices, voting method, result visibility and permissions.
+    pub fn is_structural(&self) -> bool {
+        self.start_date.is_some() || self.end_date.is_some() || self.choices.is_some() ||
+            self.voting_method.is_some() || self.hide_results.is_some() || self.permissions.is_some()
+    }
 }
\ No newline at end of file
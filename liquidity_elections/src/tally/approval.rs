@@ -0,0 +1,46 @@
+use super::{TallyMethod, Tally, WeightedBallot, score, single_round};
+
+/// Every voter approves any number of choices, the choice with the most approvals wins
+pub struct Approval;
+
+impl TallyMethod for Approval {
+    fn tally(&self, choices: &[String], ballots: &[WeightedBallot]) -> Tally {
+        let scores = score(choices, |choice| {
+            ballots.iter()
+                .filter(|ballot| ballot.choices.contains(choice))
+                .map(|ballot| ballot.weight)
+                .sum()
+        });
+
+        single_round(scores, "Counted the approvals for each choice")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tally::{TallyMethod, test_utils::{ballot, choices}};
+    use super::Approval;
+
+    #[test]
+    fn validates_ballots() {
+        let choices = choices(&["a", "b"]);
+
+        assert!(Approval.validate(&choices, &["a".to_string(), "b".to_string()]).is_ok());
+        assert!(Approval.validate(&choices, &["a".to_string(), "a".to_string()]).is_err());
+        assert!(Approval.validate(&choices, &[]).is_err());
+    }
+
+    #[test]
+    fn most_approvals_win() {
+        let ballots = [ballot(2, &["a"]), ballot(1, &["b", "c"]), ballot(2, &["c", "b"])];
+        let tally = Approval.tally(&choices(&["a", "b", "c"]), &ballots);
+
+        assert_eq!(tally.scores.iter().map(|s| s.score).collect::<Vec<_>>(), vec![2, 3, 3]);
+        assert_eq!(tally.winner, None);
+
+        let ballots = [ballot(2, &["a", "b"]), ballot(1, &["b"])];
+        let tally = Approval.tally(&choices(&["a", "b"]), &ballots);
+
+        assert_eq!(tally.winner, Some("b".to_string()));
+    }
+}
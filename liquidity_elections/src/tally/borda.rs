@@ -0,0 +1,36 @@
+use super::{TallyMethod, Tally, WeightedBallot, score, single_round};
+
+/// Every voter ranks the choices. With n choices, a first preference is worth n - 1 points,
+/// a second preference n - 2 and so on. Unranked choices get no points.
+pub struct Borda;
+
+impl TallyMethod for Borda {
+    fn tally(&self, choices: &[String], ballots: &[WeightedBallot]) -> Tally {
+        let max_points = choices.len() as i32 - 1;
+        let scores = score(choices, |choice| {
+            ballots.iter()
+                .filter_map(|ballot| {
+                    let rank = ballot.choices.iter().position(|ranked| ranked == choice)?;
+                    Some((max_points - rank as i32) * ballot.weight)
+                })
+                .sum()
+        });
+
+        single_round(scores, &format!("Awarded {} points for a first preference, one less for each preference after it", max_points))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tally::{TallyMethod, test_utils::{ballot, choices}};
+    use super::Borda;
+
+    #[test]
+    fn awards_points_by_rank() {
+        let ballots = [ballot(3, &["a", "b", "c"]), ballot(2, &["b", "c", "a"]), ballot(2, &["c", "b"])];
+        let tally = Borda.tally(&choices(&["a", "b", "c"]), &ballots);
+
+        assert_eq!(tally.scores.iter().map(|s| s.score).collect::<Vec<_>>(), vec![6, 9, 6]);
+        assert_eq!(tally.winner, Some("b".to_string()));
+    }
+}
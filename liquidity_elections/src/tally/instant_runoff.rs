@@ -0,0 +1,119 @@
+use super::{TallyMethod, Tally, WeightedBallot, score};
+use crate::schema::TallyRound;
+
+/// Every voter ranks the choices. Each round counts every ballot for its highest ranked choice
+/// that's still in the running. If a choice has a majority of those votes it wins, otherwise the
+/// choices with the fewest votes are eliminated and the next round starts.
+pub struct InstantRunoff;
+
+impl InstantRunoff {
+    /// Pick the choice to eliminate from the choices tied for the fewest votes. Ties are broken by
+    /// the most recent earlier round where the tied choices had different scores, falling back to
+    /// the choice listed last in the election.
+    fn break_tie<'a>(lowest: &[&'a String], rounds: &[TallyRound]) -> &'a String {
+        let mut candidates = lowest.to_vec();
+
+        for round in rounds.iter().rev() {
+            let score = |choice: &String| round.scores.iter()
+                .find(|result| result.choice == *choice)
+                .map(|result| result.score)
+                .unwrap_or(0);
+            let min_score = candidates.iter().map(|choice| score(choice)).min().unwrap_or(0);
+            candidates.retain(|choice| score(choice) == min_score);
+            if candidates.len() == 1 { break }
+        }
+
+        candidates.last().unwrap()
+    }
+}
+
+impl TallyMethod for InstantRunoff {
+    fn tally(&self, choices: &[String], ballots: &[WeightedBallot]) -> Tally {
+        let mut remaining: Vec<&String> = choices.iter().collect();
+        let mut rounds = Vec::new();
+
+        loop {
+            let scores = score(choices, |choice| {
+                if !remaining.contains(&choice) { return 0 }
+                ballots.iter()
+                    .filter(|ballot| ballot.choices.iter().find(|ranked| remaining.contains(ranked)) == Some(choice))
+                    .map(|ballot| ballot.weight)
+                    .sum()
+            });
+            let round = rounds.len() as i32 + 1;
+            let active: i32 = scores.iter().map(|result| result.score).sum();
+            let remaining_scores: Vec<_> = scores.iter().filter(|result| remaining.contains(&&result.choice)).collect();
+            let max_score = remaining_scores.iter().map(|result| result.score).max().unwrap_or(0);
+            let min_score = remaining_scores.iter().map(|result| result.score).min().unwrap_or(0);
+
+            let (description, winner, done) = if active == 0 {
+                ("Nobody voted for any of the remaining choices".to_string(), None, true)
+            } else if let Some(leader) = remaining_scores.iter().find(|result| result.score * 2 > active) {
+                let description = format!("{} has a majority with {} of {} votes", leader.choice, leader.score, active);
+                (description, Some(leader.choice.to_owned()), true)
+            } else if max_score == min_score {
+                ("All remaining choices are tied".to_string(), None, true)
+            } else {
+                let lowest: Vec<&String> = remaining_scores.iter()
+                    .filter(|result| result.score == min_score)
+                    .map(|result| &result.choice)
+                    .collect();
+                let eliminated = InstantRunoff::break_tie(&lowest, &rounds).to_owned();
+                remaining.retain(|choice| **choice != eliminated);
+                let description = format!("No majority, eliminated {} with {} votes", eliminated, min_score);
+                (description, None, false)
+            };
+
+            rounds.push(TallyRound { round, description, scores: scores.clone() });
+
+            if done {
+                return Tally { scores, winner, rounds }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tally::{TallyMethod, test_utils::{ballot, choices}};
+    use super::InstantRunoff;
+
+    #[test]
+    fn majority_wins_in_first_round() {
+        let ballots = [ballot(3, &["a", "b"]), ballot(2, &["b", "a"])];
+        let tally = InstantRunoff.tally(&choices(&["a", "b"]), &ballots);
+
+        assert_eq!(tally.winner, Some("a".to_string()));
+        assert_eq!(tally.rounds.len(), 1);
+    }
+
+    #[test]
+    fn transfers_eliminated_votes() {
+        let ballots = [
+            ballot(4, &["a"]),
+            ballot(3, &["b", "c"]),
+            ballot(2, &["c", "b"]),
+            ballot(1, &["d", "c", "b"])
+        ];
+        let tally = InstantRunoff.tally(&choices(&["a", "b", "c", "d"]), &ballots);
+
+        assert_eq!(tally.rounds.len(), 3);
+        assert_eq!(tally.rounds[0].scores.iter().map(|s| s.score).collect::<Vec<_>>(), vec![4, 3, 2, 1]);
+        assert_eq!(tally.rounds[1].scores.iter().map(|s| s.score).collect::<Vec<_>>(), vec![4, 3, 3, 0]);
+        assert_eq!(tally.rounds[2].scores.iter().map(|s| s.score).collect::<Vec<_>>(), vec![4, 6, 0, 0]);
+        assert_eq!(tally.winner, Some("b".to_string()));
+    }
+
+    #[test]
+    fn exhausted_ballots_and_ties() {
+        let ballots = [ballot(2, &["a"]), ballot(2, &["b"]), ballot(1, &["c"])];
+        let tally = InstantRunoff.tally(&choices(&["a", "b", "c"]), &ballots);
+
+        assert_eq!(tally.rounds.len(), 2);
+        assert_eq!(tally.winner, None);
+
+        let tally = InstantRunoff.tally(&choices(&["a", "b"]), &[]);
+        assert_eq!(tally.winner, None);
+        assert_eq!(tally.rounds.len(), 1);
+    }
+}
@@ -0,0 +1,116 @@
+//! Voting methods
+//!
+//! Every voting method validates the shape of a ballot and tallies weighted ballots into a
+//! result with a round-by-round explanation. Ballots are lists of choices, their meaning depends
+//! on the method: a single choice for plurality, the approved choices for approval voting, or the
+//! choices in order of preference for ranked methods.
+
+use std::collections::HashSet;
+use crate::schema::{ChoiceResult, TallyRound, VotingMethod};
+
+mod approval;
+mod borda;
+mod instant_runoff;
+mod plurality;
+mod schulze;
+
+pub use approval::Approval;
+pub use borda::Borda;
+pub use instant_runoff::InstantRunoff;
+pub use plurality::Plurality;
+pub use schulze::Schulze;
+
+/// A ballot and the voting weight behind it
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedBallot {
+    pub choices: Vec<String>,
+    pub weight: i32
+}
+
+/// The outcome of a tally
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tally {
+    /// The final score of every choice, in the order the choices are listed in the election
+    pub scores: Vec<ChoiceResult>,
+    /// The winning choice, None if nobody voted or there's a tie
+    pub winner: Option<String>,
+    /// How the result was reached
+    pub rounds: Vec<TallyRound>
+}
+
+pub trait TallyMethod {
+    /// Check that a ballot has the right shape for this method
+    ///
+    /// The default implementation requires a non-empty list of distinct, valid choices.
+    fn validate(&self, choices: &[String], ballot: &[String]) -> Result<(), String> {
+        if ballot.is_empty() { return Err("Ballot must contain at least one choice".to_string()) }
+
+        let mut seen = HashSet::new();
+        for choice in ballot {
+            if !choices.contains(choice) { return Err(format!("{} isn't available in this election", choice)) }
+            if !seen.insert(choice) { return Err(format!("{} is on the ballot more than once", choice)) }
+        }
+
+        Ok(())
+    }
+
+    /// Tally weighted ballots. Ballots may only contain valid choices.
+    fn tally(&self, choices: &[String], ballots: &[WeightedBallot]) -> Tally;
+}
+
+/// Get the tallying implementation of a voting method
+pub fn method(voting_method: &VotingMethod) -> &'static dyn TallyMethod {
+    match voting_method {
+        VotingMethod::Plurality => &Plurality,
+        VotingMethod::Approval => &Approval,
+        VotingMethod::InstantRunoff => &InstantRunoff,
+        VotingMethod::Schulze => &Schulze,
+        VotingMethod::Borda => &Borda
+    }
+}
+
+/// Score every choice with the given function
+fn score<F: Fn(&String) -> i32>(choices: &[String], score: F) -> Vec<ChoiceResult> {
+    choices.iter()
+        .map(|choice| ChoiceResult { choice: choice.to_owned(), score: score(choice) })
+        .collect()
+}
+
+/// The choice with the highest score, None if there's a tie for first or nobody scored
+fn leader(scores: &[ChoiceResult]) -> Option<String> {
+    let max_score = scores.iter().map(|result| result.score).max().unwrap_or(0);
+    let mut leaders = scores.iter().filter(|result| result.score == max_score);
+
+    match (leaders.next(), leaders.next()) {
+        (Some(leader), None) if max_score > 0 => Some(leader.choice.to_owned()),
+        _ => None
+    }
+}
+
+/// Tally a method that only needs a single round of scoring
+fn single_round(scores: Vec<ChoiceResult>, description: &str) -> Tally {
+    let winner = leader(&scores);
+    let description = match &winner {
+        Some(winner) => format!("{}, {} has the highest score", description, winner),
+        None => format!("{}, there is no single highest score", description)
+    };
+
+    Tally {
+        winner,
+        rounds: vec![TallyRound { round: 1, description, scores: scores.clone() }],
+        scores
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::WeightedBallot;
+
+    pub fn choices(choices: &[&str]) -> Vec<String> {
+        choices.iter().map(|choice| choice.to_string()).collect()
+    }
+
+    pub fn ballot(weight: i32, choices: &[&str]) -> WeightedBallot {
+        WeightedBallot { choices: self::choices(choices), weight }
+    }
+}
@@ -0,0 +1,52 @@
+use super::{TallyMethod, Tally, WeightedBallot, score, single_round};
+
+/// Every voter picks a single choice, the choice with the most votes wins
+pub struct Plurality;
+
+impl TallyMethod for Plurality {
+    fn validate(&self, choices: &[String], ballot: &[String]) -> Result<(), String> {
+        if ballot.len() != 1 { return Err("Ballot must contain exactly one choice".to_string()) }
+        if !choices.contains(&ballot[0]) { return Err(format!("{} isn't available in this election", ballot[0])) }
+        Ok(())
+    }
+
+    fn tally(&self, choices: &[String], ballots: &[WeightedBallot]) -> Tally {
+        let scores = score(choices, |choice| {
+            ballots.iter()
+                .filter(|ballot| ballot.choices.first() == Some(choice))
+                .map(|ballot| ballot.weight)
+                .sum()
+        });
+
+        single_round(scores, "Counted the votes for each choice")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tally::{TallyMethod, test_utils::{ballot, choices}};
+    use super::Plurality;
+
+    #[test]
+    fn validates_ballots() {
+        let choices = choices(&["a", "b"]);
+
+        assert!(Plurality.validate(&choices, &["a".to_string()]).is_ok());
+        assert!(Plurality.validate(&choices, &[]).is_err());
+        assert!(Plurality.validate(&choices, &["c".to_string()]).is_err());
+        assert!(Plurality.validate(&choices, &["a".to_string(), "b".to_string()]).is_err());
+    }
+
+    #[test]
+    fn most_votes_win() {
+        let tally = Plurality.tally(&choices(&["a", "b", "c"]), &[ballot(2, &["a"]), ballot(1, &["b"]), ballot(1, &["b"])]);
+
+        assert_eq!(tally.scores.iter().map(|s| s.score).collect::<Vec<_>>(), vec![2, 2, 0]);
+        assert_eq!(tally.winner, None);
+
+        let tally = Plurality.tally(&choices(&["a", "b"]), &[ballot(3, &["a"]), ballot(2, &["b"])]);
+
+        assert_eq!(tally.winner, Some("a".to_string()));
+        assert_eq!(tally.rounds.len(), 1);
+    }
+}
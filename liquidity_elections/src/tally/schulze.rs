@@ -0,0 +1,159 @@
+use super::{TallyMethod, Tally, WeightedBallot};
+use crate::schema::{ChoiceResult, TallyRound};
+
+/// Every voter ranks the choices, unranked choices are ranked below all ranked ones.
+/// Choices are compared pairwise and the winner is the choice that beats every other choice
+/// through its strongest chain of pairwise victories (the Schulze method).
+pub struct Schulze;
+
+impl Schulze {
+    /// How many voters prefer each choice over each other choice
+    fn preferences(choices: &[String], ballots: &[WeightedBallot]) -> Vec<Vec<i32>> {
+        let n = choices.len();
+        let mut preferences = vec![vec![0; n]; n];
+
+        for ballot in ballots {
+            let ranks: Vec<Option<usize>> = choices.iter()
+                .map(|choice| ballot.choices.iter().position(|ranked| ranked == choice))
+                .collect();
+
+            for i in 0..n {
+                for j in 0..n {
+                    let prefers = match (ranks[i], ranks[j]) {
+                        (Some(a), Some(b)) => a < b,
+                        (Some(_), None) => true,
+                        _ => false
+                    };
+                    if prefers { preferences[i][j] += ballot.weight }
+                }
+            }
+        }
+
+        preferences
+    }
+
+    /// The strength of the strongest path between each pair of choices
+    fn strongest_paths(preferences: &[Vec<i32>]) -> Vec<Vec<i32>> {
+        let n = preferences.len();
+        let mut paths = vec![vec![0; n]; n];
+
+        for i in 0..n {
+            for j in 0..n {
+                if i != j && preferences[i][j] > preferences[j][i] {
+                    paths[i][j] = preferences[i][j];
+                }
+            }
+        }
+
+        for i in 0..n {
+            for j in 0..n {
+                if i == j { continue }
+                for k in 0..n {
+                    if i != k && j != k {
+                        paths[j][k] = paths[j][k].max(paths[j][i].min(paths[i][k]));
+                    }
+                }
+            }
+        }
+
+        paths
+    }
+
+    /// Score every choice by the number of other choices it beats
+    fn wins(choices: &[String], strengths: &[Vec<i32>]) -> Vec<ChoiceResult> {
+        let n = choices.len();
+        choices.iter().enumerate()
+            .map(|(i, choice)| {
+                let score = (0..n).filter(|&j| i != j && strengths[i][j] > strengths[j][i]).count() as i32;
+                ChoiceResult { choice: choice.to_owned(), score }
+            })
+            .collect()
+    }
+}
+
+impl TallyMethod for Schulze {
+    fn tally(&self, choices: &[String], ballots: &[WeightedBallot]) -> Tally {
+        let n = choices.len();
+        let preferences = Schulze::preferences(choices, ballots);
+        let paths = Schulze::strongest_paths(&preferences);
+
+        let mut pairwise = Vec::new();
+        for i in 0..n {
+            for j in (i + 1)..n {
+                pairwise.push(format!("{} {} - {} {}", choices[i], preferences[i][j], preferences[j][i], choices[j]));
+            }
+        }
+
+        let winners: Vec<&String> = (0..n)
+            .filter(|&i| (0..n).all(|j| i == j || paths[i][j] >= paths[j][i]))
+            .map(|i| &choices[i])
+            .collect();
+        let winner = match winners.as_slice() {
+            [winner] if !ballots.is_empty() => Some(winner.to_string()),
+            _ => None
+        };
+
+        let scores = Schulze::wins(choices, &paths);
+        let description = match &winner {
+            Some(winner) => format!("{} beats every other choice through its strongest paths", winner),
+            None => "No choice beats every other choice through its strongest paths".to_string()
+        };
+
+        Tally {
+            winner,
+            rounds: vec![
+                TallyRound {
+                    round: 1,
+                    description: format!("Compared every pair of choices: {}", pairwise.join(", ")),
+                    scores: Schulze::wins(choices, &preferences)
+                },
+                TallyRound { round: 2, description, scores: scores.clone() }
+            ],
+            scores
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tally::{TallyMethod, test_utils::{ballot, choices}};
+    use super::Schulze;
+
+    #[test]
+    fn condorcet_winner_wins() {
+        let ballots = [ballot(2, &["a", "b", "c"]), ballot(2, &["b", "a", "c"]), ballot(1, &["a", "c"])];
+        let tally = Schulze.tally(&choices(&["a", "b", "c"]), &ballots);
+
+        assert_eq!(tally.winner, Some("a".to_string()));
+        assert_eq!(tally.scores.iter().map(|s| s.score).collect::<Vec<_>>(), vec![2, 1, 0]);
+        assert_eq!(tally.rounds.len(), 2);
+    }
+
+    #[test]
+    fn resolves_cycles_with_strongest_paths() {
+        // The example from the Schulze method's Wikipedia article, with a winner of e
+        let ballots = [
+            ballot(5, &["a", "c", "b", "e", "d"]),
+            ballot(5, &["a", "d", "e", "c", "b"]),
+            ballot(8, &["b", "e", "d", "a", "c"]),
+            ballot(3, &["c", "a", "b", "e", "d"]),
+            ballot(7, &["c", "a", "e", "b", "d"]),
+            ballot(2, &["c", "b", "a", "d", "e"]),
+            ballot(7, &["d", "c", "e", "b", "a"]),
+            ballot(8, &["e", "b", "a", "d", "c"])
+        ];
+        let tally = Schulze.tally(&choices(&["a", "b", "c", "d", "e"]), &ballots);
+
+        assert_eq!(tally.winner, Some("e".to_string()));
+        assert_eq!(tally.scores.iter().map(|s| s.score).collect::<Vec<_>>(), vec![3, 1, 2, 0, 4]);
+    }
+
+    #[test]
+    fn ties_have_no_winner() {
+        let tally = Schulze.tally(&choices(&["a", "b"]), &[ballot(1, &["a"]), ballot(1, &["b"])]);
+        assert_eq!(tally.winner, None);
+
+        let tally = Schulze.tally(&choices(&["a", "b"]), &[]);
+        assert_eq!(tally.winner, None);
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::{Duration as ChronoDuration, Utc};
+use liquidity::Uuid;
+use liquidity_elections::{
+    ElectionResolvers, DelegationResolvers, delegation::schema::DelegationScopeInput, schema::{ElectionInput, Importance, PermissionSet}
+};
+use liquidity_test_utils::{connection::MockConnection, context::MockContext};
+use std::time::Duration;
+use tokio_test::block_on;
+
+fn context(conn: &MockConnection, id: &str) -> MockContext {
+    MockContext::with_user(conn.clone(), id, &["create:election", "view:election", "vote:election"])
+}
+
+fn importance_scope() -> DelegationScopeInput {
+    DelegationScopeInput {
+        election_id: None,
+        importance: Some(Importance::Regular)
+    }
+}
+
+#[test]
+fn delegate_works() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let alice = context(&conn, "alice");
+        let elections = ElectionResolvers::new(10, Duration::from_secs(600));
+        let delegations = DelegationResolvers::new(elections.repository());
+
+        let input = ElectionInput {
+            name: Some("test_name".to_string()),
+            start_date: Some(Utc::now()),
+            end_date: Some(Utc::now() + ChronoDuration::days(7)),
+            ..ElectionInput::default()
+        };
+        let election = elections.create_election(input, &alice)
+            .await
+            .expect("Creating the election shouldn't fail");
+
+        let election_scope = DelegationScopeInput { election_id: Some(election.id), importance: None };
+        let delegation = delegations.delegate("bob".to_string(), election_scope, &alice)
+            .await
+            .expect("Delegating shouldn't fail");
+
+        assert_eq!(delegation.user_id, "alice".to_string());
+        assert_eq!(delegation.delegate_id, "bob".to_string());
+        assert_eq!(delegation.election_id, Some(election.id));
+
+        assert!(!delegations.revoke_delegation(importance_scope(), &alice).await.unwrap());
+        delegations.delegate("bob".to_string(), importance_scope(), &alice).await.unwrap();
+        assert!(delegations.revoke_delegation(importance_scope(), &alice).await.unwrap());
+    })
+}
+
+#[test]
+fn delegate_rejects_invalid_delegations() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let alice = context(&conn, "alice");
+        let elections = ElectionResolvers::new(10, Duration::from_secs(600));
+        let delegations = DelegationResolvers::new(elections.repository());
+
+        let both = DelegationScopeInput { election_id: Some(Uuid::new_v4()), importance: Some(Importance::Minor) };
+        let missing_election = DelegationScopeInput { election_id: Some(Uuid::new_v4()), importance: None };
+
+        assert!(delegations.delegate("alice".to_string(), importance_scope(), &alice).await.is_err());
+        assert!(delegations.delegate("bob".to_string(), both, &alice).await.is_err());
+        assert!(delegations.delegate("bob".to_string(), missing_election, &alice).await.is_err());
+        assert!(delegations.delegate("bob".to_string(), importance_scope(), &MockContext::default()).await.is_err());
+    })
+}
+
+#[test]
+fn importance_delegations_only_count_where_the_delegator_may_vote() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let permissions = ["create:election", "view:election", "vote:election"];
+        let admin = MockContext::with_user(conn.clone(), "admin", &["create:election", "update:election", "view:election"]);
+        let voter = MockContext::with_roles(conn.clone(), "voter", &permissions, &["voters"]);
+        let member = MockContext::with_roles(conn.clone(), "member", &permissions, &["voters"]);
+        let outsider = MockContext::with_roles(conn.clone(), "outsider", &permissions, &[]);
+        let elections = ElectionResolvers::new(10, Duration::from_secs(600));
+        let delegations = DelegationResolvers::new(elections.repository());
+
+        let input = ElectionInput {
+            name: Some("test_name".to_string()),
+            choices: Some(vec!["test1".to_string(), "test2".to_string()]),
+            start_date: Some(Utc::now()),
+            end_date: Some(Utc::now() + ChronoDuration::hours(1)),
+            permissions: Some(PermissionSet { vote_roles: Some(vec!["voters".to_string()]), ..PermissionSet::default() }),
+            ..ElectionInput::default()
+        };
+        let election = elections.create_election(input, &admin).await.unwrap();
+        elections.publish_election(election.id, &admin).await.unwrap();
+        elections.open_election(election.id, &admin).await.unwrap();
+
+        delegations.delegate("voter".to_string(), importance_scope(), &member).await.unwrap();
+        delegations.delegate("voter".to_string(), importance_scope(), &outsider).await.unwrap();
+        elections.cast_vote(election.id, vec!["test1".to_string()], &voter).await.unwrap();
+
+        let results = elections.election_results(election.id, &admin).await.unwrap().unwrap();
+
+        assert_eq!(results.choices[0].score, 2);
+    })
+}
@@ -0,0 +1,513 @@
+use chrono::{Duration as ChronoDuration, Utc};
+use liquidity::db::{DbConnection, EventMetadata, EventType, ExpectedVersion};
+use liquidity_elections::{ElectionResolvers, schema::{Election, ElectionChangeType, ElectionInput, ElectionStatus, PermissionSet, VotingMethod}};
+use liquidity_test_utils::{connection::MockConnection, context::MockContext};
+use std::time::Duration;
+use tokio_test::block_on;
+
+fn resolvers() -> ElectionResolvers {
+    ElectionResolvers::new(10, Duration::from_secs(600))
+}
+
+fn context(conn: &MockConnection) -> MockContext {
+    MockContext::with_user(
+        conn.clone(),
+        "test_user_id",
+        &["create:election", "update:election", "view:election", "vote:election"]
+    )
+}
+
+fn election_input(start_offset: i64, end_offset: i64) -> ElectionInput {
+    let now = Utc::now();
+    ElectionInput {
+        name: Some("test_name".to_string()),
+        choices: Some(vec!["test1".to_string(), "test2".to_string()]),
+        start_date: Some(now + ChronoDuration::hours(start_offset)),
+        end_date: Some(now + ChronoDuration::hours(end_offset)),
+        ..ElectionInput::default()
+    }
+}
+
+fn ballot(choices: &[&str]) -> Vec<String> {
+    choices.iter().map(|choice| choice.to_string()).collect()
+}
+
+async fn create(resolvers: &ElectionResolvers, ctx: &MockContext, input: ElectionInput) -> Election {
+    resolvers.create_election(input, ctx)
+        .await
+        .expect("Creating the election shouldn't fail")
+}
+
+async fn create_open(resolvers: &ElectionResolvers, ctx: &MockContext, input: ElectionInput) -> Election {
+    let election = create(resolvers, ctx, input).await;
+    resolvers.publish_election(election.id, ctx).await.expect("Publishing the election shouldn't fail");
+    resolvers.open_election(election.id, ctx).await.expect("Opening the election shouldn't fail")
+}
+
+#[test]
+fn cast_vote_works() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+        let election = create_open(&resolvers, &ctx, election_input(-1, 1)).await;
+
+        let vote = resolvers.cast_vote(election.id, ballot(&["test1"]), &ctx)
+            .await
+            .expect("Casting a valid vote shouldn't fail");
+
+        assert_eq!(vote.election_id, election.id);
+        assert_eq!(vote.ballot, ballot(&["test1"]));
+    })
+}
+
+#[test]
+fn cast_vote_rejects_invalid_votes() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+
+        let draft = create(&resolvers, &ctx, election_input(-1, 1)).await;
+        let scheduled = create(&resolvers, &ctx, election_input(-1, 1)).await;
+        resolvers.publish_election(scheduled.id, &ctx).await.unwrap();
+        let closed = create_open(&resolvers, &ctx, election_input(-1, 1)).await;
+        resolvers.close_election(closed.id, false, &ctx).await.unwrap();
+        let open = create_open(&resolvers, &ctx, election_input(1, 2)).await;
+
+        assert!(resolvers.cast_vote(draft.id, ballot(&["test1"]), &ctx).await.is_err());
+        assert!(resolvers.cast_vote(scheduled.id, ballot(&["test1"]), &ctx).await.is_err());
+        assert!(resolvers.cast_vote(closed.id, ballot(&["test1"]), &ctx).await.is_err());
+        assert!(resolvers.cast_vote(open.id, ballot(&["not_a_choice"]), &ctx).await.is_err());
+
+        assert!(resolvers.cast_vote(open.id, ballot(&["test1", "test2"]), &ctx).await.is_err());
+        assert!(resolvers.cast_vote(open.id, ballot(&[]), &ctx).await.is_err());
+
+        let no_permission = MockContext::with_user(conn.clone(), "test_user_id", &["view:election"]);
+        assert!(resolvers.cast_vote(open.id, ballot(&["test1"]), &no_permission).await.is_err());
+
+        assert!(conn.events(format!("election-{}-votes", open.id)).is_empty());
+    })
+}
+
+#[test]
+fn cast_vote_rejects_votes_outside_the_dates() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+
+        let ended = create_open(&resolvers, &ctx, election_input(-1, 1)).await;
+        let past = ElectionInput {
+            start_date: Some(Utc::now() - ChronoDuration::hours(2)),
+            end_date: Some(Utc::now() - ChronoDuration::hours(1)),
+            ..ElectionInput::default()
+        };
+        assert_eq!(resolvers.edit_election(ended.id, past, &ctx).await.unwrap().status, ElectionStatus::Open);
+        assert!(resolvers.cast_vote(ended.id, ballot(&["test1"]), &ctx).await.is_err());
+
+        let postponed = create_open(&resolvers, &ctx, election_input(-1, 1)).await;
+        let future = ElectionInput {
+            start_date: Some(Utc::now() + ChronoDuration::hours(1)),
+            end_date: Some(Utc::now() + ChronoDuration::hours(2)),
+            ..ElectionInput::default()
+        };
+        resolvers.edit_election(postponed.id, future, &ctx).await.unwrap();
+        assert!(resolvers.cast_vote(postponed.id, ballot(&["test1"]), &ctx).await.is_err());
+
+        assert!(conn.events(format!("election-{}-votes", ended.id)).is_empty());
+        assert!(conn.events(format!("election-{}-votes", postponed.id)).is_empty());
+    })
+}
+
+#[test]
+fn election_results_works() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+        let election = create_open(&resolvers, &ctx, election_input(-1, 1)).await;
+
+        resolvers.cast_vote(election.id, ballot(&["test2"]), &ctx).await.unwrap();
+
+        let results = resolvers.election_results(election.id, &ctx)
+            .await
+            .expect("Fetching the results shouldn't fail")
+            .expect("The results should exist");
+
+        assert_eq!(results.choices[1].score, 1);
+        assert_eq!(results.turnout, 1);
+        assert_eq!(results.winner, Some("test2".to_string()));
+
+        assert!(!results.is_final);
+
+        let hidden = create_open(&resolvers, &ctx, ElectionInput { hide_results: Some(true), ..election_input(-1, 1) }).await;
+        assert!(resolvers.election_results(hidden.id, &ctx).await.is_err());
+
+        resolvers.close_election(hidden.id, false, &ctx).await.unwrap();
+        let results = resolvers.election_results(hidden.id, &ctx).await.unwrap().unwrap();
+        assert!(results.is_final);
+
+        let cancelled = create_open(&resolvers, &ctx, ElectionInput { hide_results: Some(true), ..election_input(-1, 1) }).await;
+        resolvers.cancel_election(cancelled.id, None, &ctx).await.unwrap();
+        assert!(resolvers.election_results(cancelled.id, &ctx).await.is_err());
+    })
+}
+
+#[test]
+fn cast_vote_validates_ballot_by_method() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+        let ranked = create_open(&resolvers, &ctx, ElectionInput { voting_method: Some(VotingMethod::InstantRunoff), ..election_input(-1, 1) }).await;
+
+        assert!(resolvers.cast_vote(ranked.id, ballot(&["test2", "test1"]), &ctx).await.is_ok());
+        assert!(resolvers.cast_vote(ranked.id, ballot(&["test2"]), &ctx).await.is_ok());
+        assert!(resolvers.cast_vote(ranked.id, ballot(&["test2", "test2"]), &ctx).await.is_err());
+
+        let results = resolvers.election_results(ranked.id, &ctx).await.unwrap().unwrap();
+        assert_eq!(results.voting_method, VotingMethod::InstantRunoff);
+        assert_eq!(results.winner, Some("test2".to_string()));
+    })
+}
+
+#[test]
+fn election_permissions_are_enforced() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+        let permissions = PermissionSet {
+            view_roles: Some(ballot(&["members"])),
+            vote_roles: Some(ballot(&["voters"])),
+            edit_roles: Some(ballot(&["editors"])),
+            admin_roles: Some(Vec::new())
+        };
+        let input = ElectionInput { permissions: Some(permissions), ..election_input(-1, 1) };
+        let election = create_open(&resolvers, &ctx, input).await;
+
+        let all_permissions = ["update:election", "view:election", "vote:election"];
+        let outsider = MockContext::with_roles(conn.clone(), "outsider", &all_permissions, &[]);
+        let member = MockContext::with_roles(conn.clone(), "member", &all_permissions, &["members"]);
+        let voter = MockContext::with_roles(conn.clone(), "voter", &all_permissions, &["members", "voters"]);
+        let editor = MockContext::with_roles(conn.clone(), "editor", &all_permissions, &["editors"]);
+
+        assert!(resolvers.election(election.id, None, &outsider).await.unwrap().is_none());
+        assert!(resolvers.election(election.id, None, &member).await.unwrap().is_some());
+        assert!(resolvers.election(election.id, None, &editor).await.unwrap().is_some());
+        assert!(resolvers.election_results(election.id, &outsider).await.unwrap().is_none());
+
+        assert!(resolvers.cast_vote(election.id, ballot(&["test1"]), &member).await.is_err());
+        assert!(resolvers.cast_vote(election.id, ballot(&["test1"]), &ctx).await.is_err());
+        assert!(resolvers.cast_vote(election.id, ballot(&["test1"]), &voter).await.is_ok());
+
+        let rename = || ElectionInput { name: Some("renamed".to_string()), ..ElectionInput::default() };
+        assert!(resolvers.edit_election(election.id, rename(), &member).await.is_err());
+        assert!(resolvers.edit_election(election.id, rename(), &editor).await.is_ok());
+
+        let open_up = || ElectionInput { permissions: Some(PermissionSet::default()), ..ElectionInput::default() };
+        assert!(resolvers.edit_election(election.id, open_up(), &editor).await.is_err());
+        assert!(resolvers.edit_election(election.id, open_up(), &ctx).await.is_ok());
+        assert!(resolvers.election(election.id, None, &outsider).await.unwrap().is_some());
+    })
+}
+
+#[test]
+fn edit_election_restricts_structural_edits() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+        let permissions = PermissionSet { edit_roles: Some(ballot(&["editors"])), ..PermissionSet::default() };
+        let upcoming = create(&resolvers, &ctx, ElectionInput { permissions: Some(permissions), ..election_input(1, 2) }).await;
+        let open = create_open(&resolvers, &ctx, election_input(-1, 1)).await;
+
+        let editor = MockContext::with_roles(
+            conn.clone(),
+            "editor",
+            &["update:election", "view:election"],
+            &["editors"]
+        );
+        let rename = ElectionInput { name: Some("renamed".to_string()), ..ElectionInput::default() };
+        let reschedule = ElectionInput { end_date: Some(Utc::now() + ChronoDuration::hours(3)), ..ElectionInput::default() };
+        assert_eq!(resolvers.edit_election(upcoming.id, rename, &editor).await.unwrap().name, "renamed");
+        assert!(resolvers.edit_election(upcoming.id, reschedule, &editor).await.is_err());
+
+        let new_choices = || ElectionInput { choices: Some(ballot(&["test1", "test3"])), ..ElectionInput::default() };
+        let edited = resolvers.edit_election(upcoming.id, new_choices(), &ctx).await
+            .expect("Changing the choices before voting starts shouldn't fail");
+        assert_eq!(edited.choices, ballot(&["test1", "test3"]));
+        assert!(resolvers.edit_election(open.id, new_choices(), &ctx).await.is_err());
+
+        let same_choices = || ElectionInput { choices: Some(open.choices.clone()), ..ElectionInput::default() };
+        assert!(resolvers.edit_election(open.id, same_choices(), &ctx).await.is_ok());
+
+        resolvers.close_election(open.id, false, &ctx).await.unwrap();
+        assert!(resolvers.edit_election(open.id, same_choices(), &ctx).await.is_err());
+    })
+}
+
+#[test]
+fn elections_need_dates_in_order() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+
+        let undated = ElectionInput { start_date: None, end_date: None, ..election_input(0, 1) };
+        assert!(resolvers.create_election(undated, &ctx).await.is_err());
+        assert!(resolvers.create_election(ElectionInput { end_date: None, ..election_input(0, 1) }, &ctx).await.is_err());
+        assert!(resolvers.create_election(election_input(1, 1), &ctx).await.is_err());
+        assert!(resolvers.create_election(election_input(2, 1), &ctx).await.is_err());
+
+        let election = create(&resolvers, &ctx, election_input(1, 2)).await;
+        let early_end = ElectionInput { end_date: Some(Utc::now()), ..ElectionInput::default() };
+        assert!(resolvers.edit_election(election.id, early_end, &ctx).await.is_err());
+        let late_start = ElectionInput { start_date: Some(Utc::now() + ChronoDuration::hours(3)), ..ElectionInput::default() };
+        assert!(resolvers.edit_election(election.id, late_start, &ctx).await.is_err());
+
+        let moved = ElectionInput {
+            start_date: Some(Utc::now() + ChronoDuration::hours(3)),
+            end_date: Some(Utc::now() + ChronoDuration::hours(4)),
+            ..ElectionInput::default()
+        };
+        let edited = resolvers.edit_election(election.id, moved, &ctx).await.unwrap();
+        assert!(edited.start_date < edited.end_date);
+    })
+}
+
+#[test]
+fn cancel_and_delete_work() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = MockContext::with_user(
+            conn.clone(),
+            "test_user_id",
+            &["create:election", "update:election", "delete:election", "view:election", "vote:election"]
+        );
+        let resolvers = resolvers();
+        let election = create_open(&resolvers, &ctx, election_input(-1, 1)).await;
+
+        let other = MockContext::with_user(conn.clone(), "other_user_id", &["update:election", "delete:election", "view:election"]);
+        assert!(resolvers.cancel_election(election.id, None, &other).await.is_err());
+        assert!(resolvers.delete_election(election.id, None, &other).await.is_err());
+
+        let cancelled = resolvers.cancel_election(election.id, Some("test_reason".to_string()), &ctx)
+            .await
+            .expect("Cancelling the election shouldn't fail");
+        assert_eq!(cancelled.status, ElectionStatus::Cancelled);
+        assert_eq!(resolvers.election(election.id, None, &ctx).await.unwrap().unwrap().cancellation_reason, Some("test_reason".to_string()));
+        assert!(resolvers.cancel_election(election.id, None, &ctx).await.is_err());
+        assert!(resolvers.cast_vote(election.id, ballot(&["test1"]), &ctx).await.is_err());
+        let rename = ElectionInput { name: Some("renamed".to_string()), ..ElectionInput::default() };
+        assert!(resolvers.edit_election(election.id, rename, &ctx).await.is_err());
+
+        resolvers.delete_election(election.id, Some("test_reason".to_string()), &ctx)
+            .await
+            .expect("Deleting the election shouldn't fail");
+        assert_eq!(resolvers.election(election.id, None, &ctx).await.unwrap(), None);
+    })
+}
+
+#[test]
+fn election_lifecycle_works() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+        let election = create(&resolvers, &ctx, election_input(1, 2)).await;
+        assert_eq!(election.status, ElectionStatus::Draft);
+
+        let other = MockContext::with_user(conn.clone(), "other_user_id", &["update:election", "view:election"]);
+        assert!(resolvers.publish_election(election.id, &other).await.is_err());
+        assert!(resolvers.open_election(election.id, &ctx).await.is_err());
+
+        let single_choice = create(&resolvers, &ctx, ElectionInput { choices: Some(ballot(&["test1"])), ..election_input(1, 2) }).await;
+        assert!(resolvers.publish_election(single_choice.id, &ctx).await.is_err());
+
+        let scheduled = resolvers.publish_election(election.id, &ctx).await.unwrap();
+        assert_eq!(scheduled.status, ElectionStatus::Scheduled);
+
+        let expired = create(&resolvers, &ctx, election_input(-2, -1)).await;
+        resolvers.publish_election(expired.id, &ctx).await.unwrap();
+        assert!(resolvers.open_election(expired.id, &ctx).await.is_err());
+        let moved = ElectionInput { end_date: Some(Utc::now() + ChronoDuration::hours(1)), ..ElectionInput::default() };
+        resolvers.edit_election(expired.id, moved, &ctx).await.unwrap();
+        assert_eq!(resolvers.open_election(expired.id, &ctx).await.unwrap().status, ElectionStatus::Open);
+
+        let opened = resolvers.open_election(election.id, &ctx).await.unwrap();
+        assert_eq!(opened.status, ElectionStatus::Open);
+        assert!(opened.start_date <= Utc::now());
+        resolvers.cast_vote(election.id, ballot(&["test1"]), &ctx).await.unwrap();
+
+        assert!(resolvers.certify_election(election.id, &ctx).await.is_err());
+        let closed = resolvers.close_election(election.id, false, &ctx).await.unwrap();
+        assert_eq!(closed.status, ElectionStatus::Closed);
+        assert!(closed.end_date <= Utc::now());
+
+        let certified = resolvers.certify_election(election.id, &ctx).await.unwrap();
+        assert_eq!(certified.status, ElectionStatus::Certified);
+        assert_eq!(certified.certified_version, Some(closed.version));
+        assert_eq!(opened.certified_version, None);
+
+        let closed_and_certified = create_open(&resolvers, &ctx, election_input(-1, 1)).await;
+        let certified = resolvers.close_election(closed_and_certified.id, true, &ctx).await.unwrap();
+        assert_eq!(certified.status, ElectionStatus::Certified);
+        assert_eq!(certified.version, closed_and_certified.version + 2);
+        assert_eq!(certified.certified_version, Some(closed_and_certified.version + 1));
+        assert!(resolvers.cancel_election(election.id, None, &ctx).await.is_err());
+        assert_eq!(resolvers.election(election.id, None, &ctx).await.unwrap().unwrap().status, ElectionStatus::Certified);
+    })
+}
+
+#[test]
+fn elections_works() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+
+        let mut created = Vec::new();
+        for offset in 0..5 {
+            created.push(create(&resolvers, &ctx, election_input(offset, offset + 1)).await);
+        }
+        let hidden_permissions = PermissionSet { view_roles: Some(Vec::new()), ..PermissionSet::default() };
+        let hidden = create(&resolvers, &ctx, ElectionInput { permissions: Some(hidden_permissions), ..election_input(0, 1) }).await;
+
+        let other = MockContext::with_user(conn.clone(), "other_user_id", &["view:election"]);
+        let page = resolvers.elections(None, None, Some(2), None, &other).await.unwrap();
+        assert_eq!(page.total_count, 5);
+        assert_eq!(page.edges.iter().map(|edge| edge.node.id).collect::<Vec<_>>(), vec![created[4].id, created[3].id]);
+        assert!(page.page_info.has_next_page);
+
+        // The cursor outlives the election it was taken from
+        let admin = MockContext::with_user(conn.clone(), "test_user_id", &["delete:election"]);
+        resolvers.delete_election(created[3].id, None, &admin).await.unwrap();
+
+        let last = resolvers.elections(None, None, Some(10), page.page_info.end_cursor, &other).await.unwrap();
+        assert_eq!(last.edges.iter().map(|edge| edge.node.id).collect::<Vec<_>>(), vec![created[2].id, created[1].id, created[0].id]);
+        assert!(!last.page_info.has_next_page);
+        assert!(last.page_info.has_previous_page);
+
+        let own = resolvers.elections(None, None, None, None, &ctx).await.unwrap();
+        assert_eq!(own.total_count, 5);
+        assert!(own.edges.iter().any(|edge| edge.node.id == hidden.id));
+
+        assert!(resolvers.elections(None, None, Some(0), None, &ctx).await.is_err());
+        assert!(resolvers.elections(None, None, None, Some("not_a_cursor".to_string()), &ctx).await.is_err());
+    })
+}
+
+#[test]
+fn edit_election_retries_concurrent_edits() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+        let election = create(&resolvers, &ctx, election_input(1, 2)).await;
+        resolvers.election(election.id, None, &ctx).await.unwrap();
+
+        // Another server instance edits the election, so the cached version is outdated
+        let stream_id = format!("election-{}", election.id);
+        let change = serde_json::json!({ "description": "changed elsewhere" });
+        conn.write_event(&stream_id, EventType::Update, change, &EventMetadata::system(), ExpectedVersion::Any).await.unwrap();
+
+        let rename = ElectionInput { name: Some("renamed".to_string()), ..ElectionInput::default() };
+        let edited = resolvers.edit_election(election.id, rename, &ctx)
+            .await
+            .expect("The edit should be retried against the latest version");
+
+        assert_eq!(edited.name, "renamed");
+        assert_eq!(edited.description, "changed elsewhere");
+        assert_eq!(edited.version, 2);
+    })
+}
+
+#[test]
+fn events_record_who_wrote_them() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+        let election = create(&resolvers, &ctx, election_input(1, 2)).await;
+        resolvers.publish_election(election.id, &ctx).await.unwrap();
+
+        let events = conn.read_all::<_, serde_json::Value>(format!("election-{}", election.id)).await.unwrap();
+        assert_eq!(events.len(), 2);
+        for event in events {
+            let metadata = event.metadata.expect("Every event should have metadata");
+            assert_eq!(metadata.actor_id, Some("test_user_id".to_string()));
+            assert_eq!(metadata.request_id, Some(ctx.request.request_id.clone()));
+            assert_eq!(metadata.correlation_id, Some(ctx.request.correlation_id.clone()));
+        }
+    })
+}
+
+#[test]
+fn election_history_works() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = MockContext::with_user(conn.clone(), "test_user_id", &["create:election", "update:election", "delete:election", "view:election"]);
+        let resolvers = resolvers();
+        let election = create(&resolvers, &ctx, election_input(1, 2)).await;
+
+        let admin_roles = PermissionSet { admin_roles: Some(vec!["auditor".to_string()]), ..PermissionSet::default() };
+        let input = ElectionInput { name: Some("renamed".to_string()), permissions: Some(admin_roles), ..ElectionInput::default() };
+        resolvers.edit_election(election.id, input, &ctx).await.unwrap();
+        resolvers.delete_election(election.id, Some("test_reason".to_string()), &ctx).await.unwrap();
+
+        let viewer = MockContext::with_user(conn.clone(), "viewer_id", &["view:election"]);
+        assert!(resolvers.election_history(election.id, &viewer).await.is_err());
+
+        let auditor = MockContext::with_roles(conn.clone(), "auditor_id", &["view:election"], &["auditor"]);
+        let history = resolvers.election_history(election.id, &auditor)
+            .await
+            .expect("Admins should be able to see the history")
+            .expect("Deleted elections should still have a history");
+
+        // The edit is stored as a rename and a generic update
+        assert_eq!(history.iter().map(|change| change.change_type).collect::<Vec<_>>(), vec![
+            ElectionChangeType::Created, ElectionChangeType::Updated, ElectionChangeType::Updated, ElectionChangeType::Deleted
+        ]);
+        assert!(history.iter().all(|change| change.actor_id == Some("test_user_id".to_string())));
+        assert!(history.iter().all(|change| change.timestamp.is_some()));
+        assert_eq!(history[1].request_id, history[2].request_id);
+        assert_eq!(history[1].changes.iter().map(|change| change.field.as_str()).collect::<Vec<_>>(), vec!["name"]);
+        assert_eq!(history[2].changes.iter().map(|change| change.field.as_str()).collect::<Vec<_>>(), vec!["permissions"]);
+        assert_eq!(history[3].reason, Some("test_reason".to_string()));
+
+        assert_eq!(resolvers.election_history(liquidity::Uuid::new_v4(), &auditor).await.unwrap(), None);
+    })
+}
+
+#[test]
+fn election_as_of_works() {
+    block_on(async {
+        let conn = MockConnection::default();
+        let ctx = context(&conn);
+        let resolvers = resolvers();
+        let before = Utc::now();
+        let election = create(&resolvers, &ctx, election_input(1, 2)).await;
+        let created = Utc::now();
+
+        let choices = ElectionInput { choices: Some(ballot(&["test1", "test2", "test3"])), ..ElectionInput::default() };
+        resolvers.edit_election(election.id, choices, &ctx).await.unwrap();
+
+        let original = resolvers.election(election.id, Some(created), &ctx).await.unwrap().unwrap();
+        assert_eq!(original.choices, ballot(&["test1", "test2"]));
+        assert_eq!(original.version, 0);
+
+        let latest = resolvers.election(election.id, None, &ctx).await.unwrap().unwrap();
+        assert_eq!(latest.choices, ballot(&["test1", "test2", "test3"]));
+        assert_eq!(resolvers.election(election.id, Some(before), &ctx).await.unwrap(), None);
+
+        let outsider = MockContext::with_user(conn.clone(), "outsider_id", &["view:election"]);
+        let hidden = ElectionInput {
+            permissions: Some(PermissionSet { view_roles: Some(Vec::new()), ..PermissionSet::default() }),
+            ..ElectionInput::default()
+        };
+        resolvers.edit_election(election.id, hidden, &ctx).await.unwrap();
+        assert_eq!(resolvers.election(election.id, Some(created), &outsider).await.unwrap(), None);
+    })
+}
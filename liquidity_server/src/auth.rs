@@ -69,20 +69,30 @@ fn issuer_valid(iss: String, payload: &Payload) -> Result<(), JWTError> {
     }
 }
 
+/// Read a claim holding a list of strings. A missing claim is an empty list.
+fn strings(payload: &Payload, claim: &str) -> Result<Vec<String>, JWTError> {
+    let values = match payload.get_array(claim) {
+        Some(values) => values,
+        None => return Ok(Vec::new())
+    };
+
+    values.iter()
+        .map(|value| value.as_str()
+            .map(|value| value.to_string())
+            .ok_or_else(|| InvalidJWTFormat(format!("Every entry of {} in the JWT must be a string", claim))))
+        .collect()
+}
+
 fn parse_user(payload: &Payload) -> Result<User, JWTError> {
     let id = payload.sub()
         .ok_or_else(|| InvalidJWTFormat("Missing subject from JWT".to_string()))?
         .to_string();
-    let empty = Vec::new();
-    let permissions = payload
-        .get_array("permissions")
-        .unwrap_or_else(|| &empty)
-        .iter()
-        .map(|x| x.as_str().expect("Can't convert permission to string").to_string())
-        .collect();
+    let permissions = strings(payload, "permissions")?;
+    let roles = strings(payload, "roles")?;
     Ok(User {
         id,
-        permissions
+        permissions,
+        roles
     })
 }
 
@@ -8,7 +8,7 @@ mod query;
 mod mutation;
 mod auth;
 
-use std::{sync::Arc, net::SocketAddr};
+use std::{sync::Arc, net::SocketAddr, path::PathBuf};
 use juniper::RootNode;
 use jwks_client::keyset::KeyStore;
 use crate::{auth::{JWTAuth, JWTError}, query::Query, mutation::Mutation};
@@ -18,25 +18,124 @@ use warp::{
     http::HeaderMap
 };
 use liquidity::{Connection, Credentials};
-use liquidity_api::{APIContext, ElectionResolvers};
+use liquidity::db::{Backend, DbConnection, FileConnection, FileOptions, MemoryConnection, PostgresClient, SqlConnection, SqliteClient, SyncPolicy};
+use liquidity::context::{RequestInfo, User};
+use liquidity_api::{APIContext, ElectionResolvers, DelegationResolvers};
+use liquidity::projection::{CheckpointStore, FileCheckpoints, MemoryCheckpoints};
+use liquidity_api::elections::{index::ElectionIndex, repository::ElectionRepository};
 use std::time::Duration;
+use futures::{future, pin_mut, FutureExt, TryFutureExt};
+use futures::channel::oneshot;
 
 const JWKS_URL: &str = "JWKS_URL";
 const JWT_ISSUER: &str = "JWT_ISSUER";
 const ENDPOINT_URL: &str = "ENDPOINT_URL";
 const GRAPHQL_PLAYGROUND: &str = "GRAPHQL_PLAYGROUND";
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+const CORRELATION_ID_HEADER: &str = "X-Correlation-Id";
+const CAUSATION_ID_HEADER: &str = "X-Causation-Id";
+
+/// The backend events are stored in
+enum DatabaseConfig {
+    EventStore { url: SocketAddr, login: String, password: String },
+    File { directory: PathBuf, options: FileOptions },
+    /// The first connection of a pool writes, the others read
+    Sqlite { path: PathBuf, pool_size: usize },
+    Postgres { url: String, pool_size: usize },
+    /// Events only live as long as the process, unless they're dumped to a file on shutdown
+    Memory { dump: Option<PathBuf> }
+}
+
+impl DatabaseConfig {
+    /// Read the backend from `DATABASE_BACKEND`, either `eventstore` (the default), `file`, `sqlite`, `postgres` or `memory`
+    pub fn from_env() -> Self {
+        let backend = std::env::var("DATABASE_BACKEND").unwrap_or_else(|_| "eventstore".to_string());
+        match backend.as_str() {
+            "eventstore" => {
+                let url = std::env::var("DATABASE_URL")
+                    .expect("DATABASE_URL must be set")
+                    .parse()
+                    .expect("DATABASE_URL must be a valid socket address");
+                let login = std::env::var("DATABASE_LOGIN").expect("DATABASE_LOGIN must be set");
+                let password = std::env::var("DATABASE_PASSWORD").expect("DATABASE_PASSWORD must be set");
+                DatabaseConfig::EventStore { url, login, password }
+            },
+            "file" => {
+                let directory = std::env::var("DATA_DIR").unwrap_or_else(|_| "data".to_string()).into();
+                let defaults = FileOptions::default();
+                let segment_size = std::env::var("SEGMENT_SIZE")
+                    .map(|x| x.parse().expect("Invalid segment size"))
+                    .unwrap_or(defaults.segment_size);
+                let sync = match std::env::var("FSYNC").unwrap_or_else(|_| "always".to_string()).as_str() {
+                    "always" => SyncPolicy::Always,
+                    "never" => SyncPolicy::Never,
+                    writes => SyncPolicy::Every(writes.parse().expect("FSYNC must be always, never or a number of writes"))
+                };
+                DatabaseConfig::File { directory, options: FileOptions::new(segment_size, sync) }
+            },
+            "sqlite" => DatabaseConfig::Sqlite {
+                path: std::env::var("DATABASE_PATH").unwrap_or_else(|_| "liquidity.db".to_string()).into(),
+                pool_size: pool_size()
+            },
+            "postgres" => DatabaseConfig::Postgres {
+                url: std::env::var("DATABASE_URL").expect("DATABASE_URL must be set"),
+                pool_size: pool_size()
+            },
+            "memory" => DatabaseConfig::Memory { dump: std::env::var("DUMP_FILE").ok().map(PathBuf::from) },
+            _ => panic!("DATABASE_BACKEND must be eventstore, file, sqlite, postgres or memory")
+        }
+    }
+
+    pub async fn connect(self) -> Backend {
+        match self {
+            DatabaseConfig::EventStore { url, login, password } => Arc::new(
+                Connection::builder()
+                    .with_default_user(Credentials::new(login, password))
+                    .single_node_connection(url)
+                    .await
+            ).into(),
+            DatabaseConfig::File { directory, options } => FileConnection::open(directory, options)
+                .expect("Failed to open the event log")
+                .into(),
+            DatabaseConfig::Sqlite { path, pool_size } => (0..pool_size)
+                .map(|_| SqliteClient::open(&path))
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(SqlConnection::pool)
+                .expect("Failed to open the SQLite database")
+                .into(),
+            DatabaseConfig::Postgres { url, pool_size } => (0..pool_size)
+                .map(|_| PostgresClient::connect(&url))
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(SqlConnection::pool)
+                .expect("Failed to connect to Postgres")
+                .into(),
+            DatabaseConfig::Memory { dump: Some(path) } => MemoryConnection::load(path)
+                .expect("Failed to load the dumped events")
+                .into(),
+            DatabaseConfig::Memory { dump: None } => MemoryConnection::new().into()
+        }
+    }
+}
+
+/// The number of connections to a SQL database from `DATABASE_POOL_SIZE`, 4 by default
+fn pool_size() -> usize {
+    std::env::var("DATABASE_POOL_SIZE")
+        .map(|x| x.parse().expect("Invalid database pool size"))
+        .unwrap_or(4)
+}
 
 struct Config {
     pub port: u16,
-    pub database_url: SocketAddr,
-    pub database_login: String,
-    pub database_password: String,
+    pub database: DatabaseConfig,
     pub playground_enabled: bool,
     pub jwks_url: String,
     pub issuer: String,
     pub audience: String,
     pub cache_size: usize,
-    pub cache_ttl: Duration
+    pub cache_ttl: Duration,
+    pub snapshot_interval: u64,
+    /// Where the checkpoints of the projections are kept. Without one, they're rebuilt whenever the server starts.
+    pub checkpoint_dir: Option<PathBuf>
 }
 
 impl Config {
@@ -45,12 +144,7 @@ impl Config {
             .map(|x| x.parse::<u16>())
             .unwrap_or(Ok(4000))
             .expect("Invalid port set in environment");
-        let database_url = std::env::var("DATABASE_URL")
-            .expect("DATABASE_URL must be set")
-            .parse()
-            .expect("DATABASE_URL must be a valid socket address");
-        let database_login = std::env::var("DATABASE_LOGIN").expect("DATABASE_LOGIN must be set");
-        let database_password = std::env::var("DATABASE_PASSWORD").expect("DATABASE_PASSWORD must be set");
+        let database = DatabaseConfig::from_env();
         let playground_enabled = std::env::var(GRAPHQL_PLAYGROUND).unwrap_or_else(|_| "false".to_string()).parse::<bool>().unwrap();
         let jwks_url = std::env::var(JWKS_URL).expect("JWKS_URL must be set");
         let issuer = std::env::var(JWT_ISSUER).expect("JWT_ISSUER must be set");
@@ -59,14 +153,18 @@ impl Config {
         let cache_ttl = std::env::var("CACHE_TIME_TO_LIVE")
             .map(|x| parse_duration::parse(x.as_str()).expect("Invalid cache TTL"))
             .unwrap_or_else(|_| Duration::from_secs(600));
+        let snapshot_interval = std::env::var("SNAPSHOT_INTERVAL").unwrap_or_else(|_| "50".to_string()).parse().expect("Invalid snapshot interval");
+        let checkpoint_dir = std::env::var("CHECKPOINT_DIR").ok().map(PathBuf::from);
 
         Config {
             port,
-            database_url, database_login, database_password,
+            database,
             playground_enabled,
             jwks_url,
             issuer, audience,
-            cache_size, cache_ttl
+            cache_size, cache_ttl,
+            snapshot_interval,
+            checkpoint_dir
         }
     }
 }
@@ -87,11 +185,12 @@ fn init_tracing() {
     tracing::subscriber::set_global_default(subscriber).expect("Failed to set tracing subscriber");
 }
 
-type Schema = RootNode<'static, Query, Mutation>;
+type Schema<DB> = RootNode<'static, Query<DB>, Mutation<DB>>;
 
-fn schema() -> Schema {
-    Schema::new(Query, Mutation)
+fn schema<DB: DbConnection>() -> Schema<DB> {
+    Schema::new(Query::default(), Mutation::default())
 }
+
 fn headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, "*".parse().unwrap());
@@ -112,45 +211,150 @@ async fn main() {
 
     info!("Listening on {}", addr.to_string());
 
-    let db_conn = Arc::new(
-        Connection::builder()
-            .with_default_user(Credentials::new(config.database_login, config.database_password))
-            .single_node_connection(config.database_url)
-            .await
-    );
+    let db_conn = config.database.connect().await;
 
     let key_store = KeyStore::new_from(config.jwks_url.as_str()).await.expect("Failed to create JWKS key store");
     let auth = Arc::new(JWTAuth::new(key_store, config.issuer, config.audience));
-    let elections = Arc::new(ElectionResolvers::new(config.cache_size, config.cache_ttl));
-    let base_ctx = APIContext::new(db_conn, None, elections);
-
-    let no_auth = {
-        let ctx = base_ctx.clone();
-        warp::any().map(move || -> Result<APIContext, JWTError> {
-            Ok(ctx.clone())
-        })
+    let checkpoints: Arc<dyn CheckpointStore> = match config.checkpoint_dir {
+        Some(directory) => Arc::new(FileCheckpoints::new(directory).expect("Failed to open the checkpoint directory")),
+        None => Arc::new(MemoryCheckpoints::new())
     };
-    let context = {
+    let index = Arc::new(ElectionIndex::with_checkpoints(checkpoints));
+    let election_repository = ElectionRepository::new(config.cache_size, config.cache_ttl)
+        .with_snapshot_interval(config.snapshot_interval)
+        .with_index(index.clone());
+    let elections = Arc::new(ElectionResolvers::from_repository(election_repository));
+    let delegations = Arc::new(DelegationResolvers::new(elections.repository()));
+    let base_ctx = APIContext::new(db_conn.clone(), None, elections, delegations);
+
+    let request = warp::addr::remote()
+        .and(warp::header::headers_cloned())
+        .map(|addr: Option<SocketAddr>, headers: HeaderMap| {
+            let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+            RequestInfo::from_client(
+                header(REQUEST_ID_HEADER),
+                header(CORRELATION_ID_HEADER),
+                header(CAUSATION_ID_HEADER),
+                addr.map(|addr| addr.ip().to_string())
+            )
+        });
+    let user = {
         warp::header::<String>("Authorization")
-            .map(move |jwt: String| -> Result<APIContext, JWTError> {
+            .map(move |jwt: String| -> Result<Option<User>, JWTError> {
                 let auth = auth.clone();
-                let user = auth.validate(jwt)?;
-
-                Ok(base_ctx.clone_with_user(user))
+                Ok(Some(auth.validate(jwt)?))
             })
-            .or(no_auth)
+            .or(warp::any().map(|| -> Result<Option<User>, JWTError> { Ok(None) }))
             .unify()
     };
+    let context = request.and(user)
+        .map(move |request: RequestInfo, user: Result<Option<User>, JWTError>| -> Result<APIContext, JWTError> {
+            Ok(base_ctx.clone_for_request(user?, request))
+        });
 
     let options = warp::options().map(warp::reply).with(warp::reply::with::headers(headers()));
     let graphql_filter = juniper_warp::make_graphql_filter(schema(), context.boxed());
 
-    warp::serve(
+    // The index follows the elections every instance writes for as long as the server runs. It saves a checkpoint
+    // after every event, so stopping it never loses any.
+    let (indexing, stop_indexing) = {
+        let db_conn = db_conn.clone();
+        future::abortable(async move {
+            if let Err(e) = index.run(&db_conn).await {
+                error!("The election index stopped: {:?}", e);
+            }
+        })
+    };
+    tokio::spawn(indexing);
+
+    let (stop, stopped) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down");
+        let _ = stop.send(());
+    });
+
+    let (_, server) = warp::serve(
         warp::get2()
             .and(warp::path::end())
             .and(juniper_warp::playground_filter("/graphql"))
             .or(warp::path("graphql").and(graphql_filter).with(warp::reply::with::headers(headers())))
             .or(options)
             .with(log)
-    ).run(addr)
-}
\ No newline at end of file
+    ).bind_with_graceful_shutdown(addr, stopped.map(|_| Ok::<(), ()>(())).boxed().compat());
+
+    // warp runs on a runtime of its own, which returns once the requests in flight are answered
+    tokio::task::spawn_blocking(move || tokio01::run(server)).await.expect("The server failed");
+    stop_indexing.abort();
+    close(db_conn).await;
+}
+
+/// Resolves when the process is asked to stop, with Ctrl+C or the SIGTERM container runtimes and init systems send
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        let interrupt = tokio::signal::ctrl_c();
+        pin_mut!(interrupt);
+        future::select(Box::pin(terminate.recv()), interrupt).await;
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
+}
+
+/// Make sure every write survives the process, once the server stopped writing
+async fn close(db_conn: Backend) {
+    match db_conn {
+        Backend::Memory(memory) => tokio::task::spawn_blocking(move || memory.dump())
+            .await
+            .expect("Dumping the events panicked")
+            .expect("Failed to dump the events"),
+        // Writes since the last flush would be lost if the machine went down after the process
+        Backend::File(file) => file.sync().await.expect("Failed to flush the event log"),
+        _ => ()
+    }
+}
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use futures::executor::block_on;
+    use juniper::{graphql_value, Variables};
+    use liquidity::context::User;
+    use liquidity_api::{APIContext, DelegationResolvers, ElectionResolvers};
+    use liquidity_test_utils::connection::MockConnection;
+    use crate::auth::JWTError;
+    use super::schema;
+
+    fn context(conn: &MockConnection) -> Result<APIContext<MockConnection>, JWTError> {
+        let elections = Arc::new(ElectionResolvers::new(10, Duration::from_secs(600)));
+        let delegations = Arc::new(DelegationResolvers::new(elections.repository()));
+        let user = User {
+            id: "test_user_id".to_string(),
+            permissions: vec!["create:election".to_string(), "view:election".to_string()],
+            roles: vec![]
+        };
+        Ok(APIContext::new(conn.clone(), Some(user), elections, delegations))
+    }
+
+    #[test]
+    fn schema_runs_against_any_connection() {
+        let conn = MockConnection::default();
+        let schema = schema::<MockConnection>();
+        let ctx = context(&conn);
+
+        block_on(async {
+            let create = r#"mutation { createElection(input: { name: "Budget", choices: ["Yes", "No"], startDate: "2020-01-01T00:00:00Z", endDate: "2020-01-02T00:00:00Z" }) { name } }"#;
+            let (created, errors) = juniper::execute_async(create, None, &schema, &Variables::new(), &ctx).await.unwrap();
+            assert!(errors.is_empty(), "{:?}", errors);
+            assert_eq!(created, graphql_value!({ "createElection": { "name": "Budget" } }));
+
+            let list = "{ elections { totalCount edges { node { name } } } }";
+            let (listed, errors) = juniper::execute_async(list, None, &schema, &Variables::new(), &ctx).await.unwrap();
+            assert!(errors.is_empty(), "{:?}", errors);
+            assert_eq!(listed, graphql_value!({ "elections": { "totalCount": 1, "edges": [{ "node": { "name": "Budget" } }] } }));
+        });
+    }
+}
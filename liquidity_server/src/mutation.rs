@@ -1,15 +1,25 @@
+use std::marker::PhantomData;
 use liquidity::Uuid;
-use liquidity_api::elections::schema::{Election, ElectionInput};
+use liquidity::db::DbConnection;
+use liquidity_api::elections::schema::{Election, ElectionInput, Vote};
+use liquidity_api::elections::delegation::schema::{Delegation, DelegationScopeInput};
 use crate::auth::JWTError;
 use juniper::FieldResult;
 use liquidity_api::APIContext;
 
-pub struct Mutation;
+/// The mutations of the API, generic over the database so the schema runs against any backend
+pub struct Mutation<DB>(PhantomData<DB>);
+
+impl<DB> Default for Mutation<DB> {
+    fn default() -> Self {
+        Mutation(PhantomData)
+    }
+}
 
 #[juniper::graphql_object(
-    Context = Result<APIContext, JWTError>
+    Context = Result<APIContext<DB>, JWTError>
 )]
-impl Mutation {
+impl<DB: DbConnection> Mutation<DB> {
     #[graphql(
         description="Create a new election",
         arguments(
@@ -18,7 +28,7 @@ impl Mutation {
             )
         )
     )]
-    pub async fn create_election(input: ElectionInput, context: &mut Result<APIContext, JWTError>) -> FieldResult<Election> {
+    pub async fn create_election(input: ElectionInput, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<Election> {
         let context = context.as_ref()?;
 
         Ok(context.elections().create_election(input, context).await?)
@@ -35,9 +45,141 @@ impl Mutation {
             )
         )
     )]
-    pub async fn edit_election(id: Uuid, input: ElectionInput, context: &mut Result<APIContext, JWTError>) -> FieldResult<Election> {
+    pub async fn edit_election(id: Uuid, input: ElectionInput, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<Election> {
         let context = context.as_ref()?;
         let result = context.elections().edit_election(id, input, context).await?;
         Ok(result)
     }
+
+    #[graphql(
+        description="Publish a draft election, scheduling it to be opened",
+        arguments(
+            id(
+                description = "The id of the election"
+            )
+        )
+    )]
+    pub async fn publish_election(id: Uuid, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<Election> {
+        let context = context.as_ref()?;
+        Ok(context.elections().publish_election(id, context).await?)
+    }
+
+    #[graphql(
+        description="Open a scheduled election for voting",
+        arguments(
+            id(
+                description = "The id of the election"
+            )
+        )
+    )]
+    pub async fn open_election(id: Uuid, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<Election> {
+        let context = context.as_ref()?;
+        Ok(context.elections().open_election(id, context).await?)
+    }
+
+    #[graphql(
+        description="Close an open election, ending the vote",
+        arguments(
+            id(
+                description = "The id of the election"
+            ),
+            certify(
+                description = "Whether to certify the results right away. Defaults to false"
+            )
+        )
+    )]
+    pub async fn close_election(id: Uuid, certify: Option<bool>, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<Election> {
+        let context = context.as_ref()?;
+        Ok(context.elections().close_election(id, certify.unwrap_or(false), context).await?)
+    }
+
+    #[graphql(
+        description="Certify the results of a closed election",
+        arguments(
+            id(
+                description = "The id of the election"
+            )
+        )
+    )]
+    pub async fn certify_election(id: Uuid, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<Election> {
+        let context = context.as_ref()?;
+        Ok(context.elections().certify_election(id, context).await?)
+    }
+
+    #[graphql(
+        description="Cancel an election. It stays readable, but can't be voted in or edited anymore",
+        arguments(
+            id(
+                description = "The id of the election"
+            ),
+            reason(
+                description = "The reason for cancelling the election"
+            )
+        )
+    )]
+    pub async fn cancel_election(id: Uuid, reason: Option<String>, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<Election> {
+        let context = context.as_ref()?;
+        Ok(context.elections().cancel_election(id, reason, context).await?)
+    }
+
+    #[graphql(
+        description="Delete an election",
+        arguments(
+            id(
+                description = "The id of the election"
+            ),
+            reason(
+                description = "The reason for deleting the election"
+            )
+        )
+    )]
+    pub async fn delete_election(id: Uuid, reason: Option<String>, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<Election> {
+        let context = context.as_ref()?;
+        Ok(context.elections().delete_election(id, reason, context).await?)
+    }
+
+    #[graphql(
+        description="Cast a vote in an election",
+        arguments(
+            election_id(
+                description = "The id of the election to vote in"
+            ),
+            ballot(
+                description = "The choices to vote for. A single choice for plurality voting, any number of approved choices for approval voting, or choices in order of preference for ranked voting methods"
+            )
+        )
+    )]
+    pub async fn cast_vote(election_id: Uuid, ballot: Vec<String>, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<Vote> {
+        let context = context.as_ref()?;
+        Ok(context.elections().cast_vote(election_id, ballot, context).await?)
+    }
+
+    #[graphql(
+        description="Delegate your vote to another user",
+        arguments(
+            to_user_id(
+                description = "The id of the user to delegate to"
+            ),
+            scope(
+                description = "The elections the delegation applies to"
+            )
+        )
+    )]
+    pub async fn delegate(to_user_id: String, scope: DelegationScopeInput, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<Delegation> {
+        let context = context.as_ref()?;
+        Ok(context.delegations().delegate(to_user_id, scope, context).await?)
+    }
+
+    #[graphql(
+        description="Revoke your delegation",
+        arguments(
+            scope(
+                description = "The scope of the delegation to revoke"
+            )
+        )
+    )]
+    pub async fn revoke_delegation(scope: DelegationScopeInput, context: &mut Result<APIContext<DB>, JWTError>) -> FieldResult<bool> {
+        let context = context.as_ref()?;
+        Ok(context.delegations().revoke_delegation(scope, context).await?)
+    }
 }
\ No newline at end of file
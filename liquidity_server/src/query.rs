@@ -1,25 +1,92 @@
+use std::marker::PhantomData;
 use liquidity::Uuid;
-use liquidity_api::elections::schema::Election;
+use liquidity::db::DbConnection;
+use chrono::{DateTime, Utc};
+use liquidity_api::elections::schema::{Election, ElectionResults, ElectionFilter, ElectionOrder, ElectionConnection, ElectionChange};
 use crate::auth::JWTError;
 use juniper::FieldResult;
 use liquidity_api::APIContext;
 
-pub struct Query;
+/// The queries of the API, generic over the database so the schema runs against any backend
+pub struct Query<DB>(PhantomData<DB>);
+
+impl<DB> Default for Query<DB> {
+    fn default() -> Self {
+        Query(PhantomData)
+    }
+}
 
 #[juniper::graphql_object(
-    Context = Result<APIContext, JWTError>
+    Context = Result<APIContext<DB>, JWTError>
 )]
-impl Query {
+impl<DB: DbConnection> Query<DB> {
     #[graphql(
         description="Fetch an election by id",
+            arguments(
+            id(
+                description = "The id of the election"
+            ),
+            as_of(
+                description = "The point in time to fetch the election at. Defaults to now"
+            )
+        )
+    )]
+    pub async fn election(id: Uuid, as_of: Option<DateTime<Utc>>, context: &Result<APIContext<DB>, JWTError>) -> FieldResult<Option<Election>> {
+        let context = context.as_ref()?;
+        Ok(context.elections().election(id, as_of, context).await?)
+    }
+
+    #[graphql(
+        description="List the elections you may view",
+            arguments(
+            filter(
+                description = "Filters the elections have to match"
+            ),
+            order_by(
+                description = "The order to list the elections in. Defaults to the latest start date first"
+            ),
+            first(
+                description = "The number of elections to return, between 1 and 100. Defaults to 20"
+            ),
+            after(
+                description = "The cursor of the election to continue after"
+            )
+        )
+    )]
+    pub async fn elections(
+        filter: Option<ElectionFilter>,
+        order_by: Option<ElectionOrder>,
+        first: Option<i32>,
+        after: Option<String>,
+        context: &Result<APIContext<DB>, JWTError>
+    ) -> FieldResult<ElectionConnection> {
+        let context = context.as_ref()?;
+        Ok(context.elections().elections(filter, order_by, first, after, context).await?)
+    }
+
+    #[graphql(
+        description="Fetch the live results of an election",
+            arguments(
+            id(
+                description = "The id of the election"
+            )
+        )
+    )]
+    pub async fn election_results(id: Uuid, context: &Result<APIContext<DB>, JWTError>) -> FieldResult<Option<ElectionResults>> {
+        let context = context.as_ref()?;
+        Ok(context.elections().election_results(id, context).await?)
+    }
+
+    #[graphql(
+        description="Fetch every change made to an election, including deleted elections",
             arguments(
             id(
                 description = "The id of the election"
             )
         )
     )]
-    pub async fn election(id: Uuid, context: &Result<APIContext, JWTError>) -> FieldResult<Option<Election>> {
+    pub async fn election_history(id: Uuid, context: &Result<APIContext<DB>, JWTError>) -> FieldResult<Option<Vec<ElectionChange>>> {
         let context = context.as_ref()?;
-        Ok(context.elections().election(id, context).await?)
+        Ok(context.elections().election_history(id, context).await?)
     }
 }
\ No newline at end of file
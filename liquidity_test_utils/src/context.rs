@@ -0,0 +1,40 @@
+use liquidity::{Context, context::{RequestInfo, User}};
+use crate::connection::MockConnection;
+use std::fmt;
+
+#[derive(Default, Clone)]
+pub struct MockContext {
+    pub db: MockConnection,
+    pub user: Option<User>,
+    pub request: RequestInfo
+}
+
+impl MockContext {
+    pub fn with_user(db: MockConnection, id: &str, permissions: &[&str]) -> Self {
+        MockContext::with_roles(db, id, permissions, &[])
+    }
+
+    pub fn with_roles(db: MockConnection, id: &str, permissions: &[&str], roles: &[&str]) -> Self {
+        MockContext {
+            db,
+            user: Some(User {
+                id: id.to_string(),
+                permissions: permissions.iter().map(|p| p.to_string()).collect(),
+                roles: roles.iter().map(|r| r.to_string()).collect()
+            }),
+            request: RequestInfo::default()
+        }
+    }
+}
+
+impl Context<MockConnection> for MockContext {
+    fn db(&self) -> MockConnection { self.db.clone() }
+    fn user(&self) -> &Option<User> { &self.user }
+    fn request(&self) -> &RequestInfo { &self.request }
+}
+
+impl fmt::Debug for MockContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "user: {:?}", self.user)
+    }
+}
@@ -1,3 +1,2 @@
-#[macro_use] extern crate async_trait;
-
-pub mod connection;
\ No newline at end of file
+pub mod connection;
+pub mod context;
\ No newline at end of file